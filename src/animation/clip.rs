@@ -0,0 +1,291 @@
+use crate::transform::Transform;
+use glam::{Quat, Vec3, Vec4};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    Step,
+    // every keyframe has an in tangent, a value and an out tangent, in that order
+    CubicSpline,
+}
+
+#[derive(Clone)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+}
+
+// target is the index of the animated node
+#[derive(Clone)]
+pub struct Channel {
+    pub target: usize,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+}
+
+#[derive(Clone)]
+pub struct AnimationClip {
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+    pub duration: f32,
+}
+
+// local transforms of the animated nodes
+pub type Pose = HashMap<usize, Transform>;
+
+trait Keyframe: Copy {
+    fn interpolate(self, other: Self, s: f32) -> Self;
+    fn hermite(v0: Self, b0: Self, v1: Self, a1: Self, s: f32, dt: f32) -> Self;
+}
+
+impl Keyframe for Vec3 {
+    fn interpolate(self, other: Self, s: f32) -> Self {
+        self.lerp(other, s)
+    }
+
+    fn hermite(v0: Self, b0: Self, v1: Self, a1: Self, s: f32, dt: f32) -> Self {
+        let (s2, s3) = (s * s, s * s * s);
+        v0 * (2.0 * s3 - 3.0 * s2 + 1.0)
+            + b0 * dt * (s3 - 2.0 * s2 + s)
+            + v1 * (-2.0 * s3 + 3.0 * s2)
+            + a1 * dt * (s3 - s2)
+    }
+}
+
+impl Keyframe for Quat {
+    fn interpolate(self, other: Self, s: f32) -> Self {
+        slerp(self, other, s)
+    }
+
+    fn hermite(v0: Self, b0: Self, v1: Self, a1: Self, s: f32, dt: f32) -> Self {
+        let v = Vec4::from(v0);
+        let (s2, s3) = (s * s, s * s * s);
+        let q = v * (2.0 * s3 - 3.0 * s2 + 1.0)
+            + Vec4::from(b0) * dt * (s3 - 2.0 * s2 + s)
+            + Vec4::from(v1) * (-2.0 * s3 + 3.0 * s2)
+            + Vec4::from(a1) * dt * (s3 - s2);
+        Quat::from(q).normalize()
+    }
+}
+
+// takes the shortest path, the slerp of glam does not and only approximates the angle
+fn slerp(from: Quat, to: Quat, s: f32) -> Quat {
+    let from = Vec4::from(from);
+    let mut to = Vec4::from(to);
+    let mut dot = from.dot(to);
+    if dot < 0.0 {
+        to = -to;
+        dot = -dot;
+    }
+    if dot > 0.9995 {
+        return Quat::from(from.lerp(to, s)).normalize();
+    }
+    let theta = dot.acos();
+    Quat::from((from * (theta * (1.0 - s)).sin() + to * (theta * s).sin()) / theta.sin()).normalize()
+}
+
+fn sample_keyframes<T: Keyframe>(times: &[f32], values: &[T], interpolation: Interpolation, time: f32) -> T {
+    let value = |key: usize| match interpolation {
+        Interpolation::CubicSpline => values[key * 3 + 1],
+        _ => values[key],
+    };
+    let last = times.len() - 1;
+    if time <= times[0] {
+        return value(0);
+    }
+    if time >= times[last] {
+        return value(last);
+    }
+    let key = times.iter().rposition(|t| *t <= time).unwrap();
+    let dt = times[key + 1] - times[key];
+    // times that are not increasing have no span to interpolate over
+    if dt <= 0.0 {
+        return value(key);
+    }
+    let s = (time - times[key]) / dt;
+    match interpolation {
+        Interpolation::Step => value(key),
+        Interpolation::Linear => value(key).interpolate(value(key + 1), s),
+        Interpolation::CubicSpline => T::hermite(
+            values[key * 3 + 1],
+            values[key * 3 + 2],
+            values[(key + 1) * 3 + 1],
+            values[(key + 1) * 3],
+            s,
+            dt,
+        ),
+    }
+}
+
+impl Keyframes {
+    pub fn len(&self) -> usize {
+        match self {
+            Keyframes::Translation(values) => values.len(),
+            Keyframes::Rotation(values) => values.len(),
+            Keyframes::Scale(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Channel {
+    // cubic splines have three values per keyframe
+    pub fn has_matching_keyframes(&self) -> bool {
+        let values_per_time = match self.interpolation {
+            Interpolation::CubicSpline => 3,
+            _ => 1,
+        };
+        self.keyframes.len() == self.times.len() * values_per_time
+    }
+
+    fn apply(&self, time: f32, transform: &mut Transform) {
+        if self.times.is_empty() || !self.has_matching_keyframes() {
+            return;
+        }
+        match &self.keyframes {
+            Keyframes::Translation(values) => {
+                transform.translation = sample_keyframes(&self.times, values, self.interpolation, time)
+            }
+            Keyframes::Rotation(values) => {
+                transform.rotation = sample_keyframes(&self.times, values, self.interpolation, time)
+            }
+            Keyframes::Scale(values) => {
+                transform.scale = sample_keyframes(&self.times, values, self.interpolation, time)
+            }
+        }
+    }
+}
+
+impl AnimationClip {
+    pub fn new(name: Option<String>, channels: Vec<Channel>) -> Self {
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |duration: f32, time| duration.max(*time));
+        Self {
+            name,
+            channels,
+            duration,
+        }
+    }
+
+    // nodes that are not animated by the clip are missing from the pose
+    pub fn sample(&self, time: f32, rest: &[Transform]) -> Pose {
+        let mut pose = Pose::new();
+        for channel in &self.channels {
+            let transform = pose
+                .entry(channel.target)
+                .or_insert_with(|| rest.get(channel.target).cloned().unwrap_or_default());
+            channel.apply(time, transform);
+        }
+        pose
+    }
+}
+
+pub(crate) fn blend_poses(from: &Pose, to: &Pose, weight: f32, rest: &[Transform]) -> Pose {
+    let rest_transform = |node: &usize| rest.get(*node).cloned().unwrap_or_default();
+    from.keys()
+        .chain(to.keys().filter(|node| !from.contains_key(node)))
+        .map(|node| {
+            let a = from.get(node).cloned().unwrap_or_else(|| rest_transform(node));
+            let b = to.get(node).cloned().unwrap_or_else(|| rest_transform(node));
+            let transform = Transform::from_translation_rotation_scale(
+                a.translation.lerp(b.translation, weight),
+                slerp(a.rotation, b.rotation, weight),
+                a.scale.lerp(b.scale, weight),
+            );
+            (*node, transform)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        animation::{clip::blend_poses, AnimationClip, Channel, Interpolation, Keyframes},
+        transform::Transform,
+    };
+    use glam::{Quat, Vec3};
+
+    fn translation_clip(interpolation: Interpolation, values: Vec<Vec3>) -> AnimationClip {
+        AnimationClip::new(
+            None,
+            vec![Channel {
+                target: 0,
+                interpolation,
+                times: vec![0.0, 1.0, 2.0],
+                keyframes: Keyframes::Translation(values),
+            }],
+        )
+    }
+
+    #[test]
+    fn interpolations() {
+        let values = vec![Vec3::zero(), Vec3::new(2.0, 0.0, 0.0), Vec3::new(2.0, 4.0, 0.0)];
+        let rest = [Transform::identity()];
+        let linear = translation_clip(Interpolation::Linear, values.clone());
+        assert_eq!(2.0, linear.duration);
+        assert_eq!(Vec3::new(1.0, 0.0, 0.0), linear.sample(0.5, &rest)[&0].translation);
+        assert_eq!(Vec3::new(2.0, 2.0, 0.0), linear.sample(1.5, &rest)[&0].translation);
+        assert_eq!(Vec3::new(2.0, 4.0, 0.0), linear.sample(3.0, &rest)[&0].translation);
+
+        let step = translation_clip(Interpolation::Step, values.clone());
+        assert_eq!(Vec3::zero(), step.sample(0.9, &rest)[&0].translation);
+        assert_eq!(Vec3::new(2.0, 0.0, 0.0), step.sample(1.1, &rest)[&0].translation);
+
+        // zero tangents, the curve eases in and out through the keyframe values
+        let cubic_values = values
+            .iter()
+            .flat_map(|value| vec![Vec3::zero(), *value, Vec3::zero()])
+            .collect();
+        let cubic = translation_clip(Interpolation::CubicSpline, cubic_values);
+        assert_eq!(Vec3::new(1.0, 0.0, 0.0), cubic.sample(0.5, &rest)[&0].translation);
+        assert!(cubic.sample(0.25, &rest)[&0].translation.x < 0.5);
+        assert_eq!(Vec3::new(2.0, 0.0, 0.0), cubic.sample(1.0, &rest)[&0].translation);
+    }
+
+    #[test]
+    fn invalid_channels_are_not_sampled() {
+        let rest = [Transform::from_translation(Vec3::new(0.0, 1.0, 0.0))];
+        let values = vec![Vec3::zero(), Vec3::new(2.0, 0.0, 0.0), Vec3::new(2.0, 4.0, 0.0)];
+        let cubic = translation_clip(Interpolation::CubicSpline, values.clone());
+        assert!(!cubic.channels[0].has_matching_keyframes());
+        assert_eq!(rest[0].translation, cubic.sample(0.5, &rest)[&0].translation);
+
+        // keyframes at the same time jump to the later value
+        let mut repeated = translation_clip(Interpolation::Linear, values);
+        repeated.channels[0].times = vec![0.0, 1.0, 1.0];
+        let translation = repeated.sample(0.999, &rest)[&0].translation;
+        assert!(translation.is_finite());
+        assert_eq!(Vec3::new(2.0, 4.0, 0.0), repeated.sample(1.0, &rest)[&0].translation);
+    }
+
+    #[test]
+    fn blend_rotations() {
+        let rest = [
+            Transform::identity(),
+            Transform::from_translation(Vec3::new(0.0, 1.0, 0.0)),
+        ];
+        let clip = AnimationClip::new(
+            None,
+            vec![Channel {
+                target: 0,
+                interpolation: Interpolation::Linear,
+                times: vec![0.0],
+                keyframes: Keyframes::Rotation(vec![Quat::from_rotation_y(std::f32::consts::FRAC_PI_2)]),
+            }],
+        );
+        let other = translation_clip(Interpolation::Linear, vec![Vec3::new(4.0, 0.0, 0.0); 3]);
+        let pose = blend_poses(&clip.sample(0.0, &rest), &other.sample(0.0, &rest), 0.5, &rest);
+        let rotation = pose[&0].rotation;
+        let expected = Quat::from_rotation_y(std::f32::consts::FRAC_PI_4);
+        assert!(rotation.dot(expected).abs() > 0.9999);
+        assert_eq!(Vec3::new(2.0, 0.0, 0.0), pose[&0].translation);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::entity::Entities;
+
+// shows one frame after another by replacing the frame component of the entity, e.g. the meshes of
+// the models in a .vox file
+pub struct Flipbook<M> {
+    pub frames: Vec<M>,
+    pub frame_rate: f32,
+    pub looping: bool,
+    pub playing: bool,
+    time: f32,
+    shown: Option<usize>,
+}
+
+impl<M> Flipbook<M> {
+    pub fn new(frames: Vec<M>, frame_rate: f32) -> Self {
+        Self {
+            frames,
+            frame_rate,
+            looping: true,
+            playing: true,
+            time: 0.0,
+            shown: None,
+        }
+    }
+
+    pub fn current_frame(&self) -> usize {
+        let frame = (self.time * self.frame_rate) as usize;
+        if self.looping {
+            frame % self.frames.len().max(1)
+        } else {
+            frame.min(self.frames.len().saturating_sub(1))
+        }
+    }
+
+    pub fn restart(&mut self) {
+        self.time = 0.0;
+        self.playing = true;
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.playing {
+            self.time += dt;
+            if self.frame_rate > 0.0 {
+                let duration = self.frames.len() as f32 / self.frame_rate;
+                if self.looping {
+                    self.time %= duration;
+                } else if self.time >= duration {
+                    // stays on the last frame until it is restarted
+                    self.time = duration;
+                    self.playing = false;
+                }
+            }
+        }
+    }
+}
+
+pub fn animate_flipbooks<M: Clone + 'static>(entities: &mut Entities, dt: f32) {
+    let mut frames = Vec::new();
+    for (entity, flipbook) in entities.query_mut::<Flipbook<M>>() {
+        flipbook.update(dt);
+        let frame = flipbook.current_frame();
+        if flipbook.shown != Some(frame) {
+            if let Some(component) = flipbook.frames.get(frame) {
+                frames.push((entity, component.clone()));
+                flipbook.shown = Some(frame);
+            }
+        }
+    }
+    for (entity, frame) in frames {
+        entities.insert(&entity, frame);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        animation::{animate_flipbooks, Flipbook},
+        entity::Entities,
+        world::mesh_data_from_vox_frames,
+    };
+    use dot_vox::{DotVoxData, Model, Size, Voxel};
+
+    #[test]
+    fn frames_follow_frame_rate() {
+        let mut entities = Entities::new();
+        let walking = entities.spawn().with(Flipbook::new(vec![0u32, 1, 2], 4.0)).id();
+        animate_flipbooks::<u32>(&mut entities, 0.0);
+        assert_eq!(Some(&0), entities.get::<u32>(&walking));
+        animate_flipbooks::<u32>(&mut entities, 0.3);
+        assert_eq!(Some(&1), entities.get::<u32>(&walking));
+        animate_flipbooks::<u32>(&mut entities, 0.5);
+        assert_eq!(Some(&0), entities.get::<u32>(&walking));
+
+        let flipbook = entities.get_mut::<Flipbook<u32>>(&walking).unwrap();
+        flipbook.looping = false;
+        flipbook.restart();
+        animate_flipbooks::<u32>(&mut entities, 10.0);
+        assert_eq!(Some(&2), entities.get::<u32>(&walking));
+        let flipbook = entities.get_mut::<Flipbook<u32>>(&walking).unwrap();
+        assert!(!flipbook.playing);
+        flipbook.restart();
+        animate_flipbooks::<u32>(&mut entities, 0.3);
+        assert_eq!(Some(&1), entities.get::<u32>(&walking));
+    }
+
+    #[test]
+    fn every_vox_model_is_a_frame() {
+        let voxel = |x| Voxel { x, y: 0, z: 0, i: 1 };
+        let data = DotVoxData {
+            version: 150,
+            models: vec![
+                Model {
+                    size: Size { x: 2, y: 1, z: 1 },
+                    voxels: vec![voxel(0)],
+                },
+                Model {
+                    size: Size { x: 2, y: 1, z: 1 },
+                    voxels: vec![voxel(0), voxel(1)],
+                },
+            ],
+            palette: vec![0xffffffff; 256],
+            materials: vec![],
+        };
+        let frames = mesh_data_from_vox_frames(&data);
+        assert_eq!(2, frames.len());
+        let max_x = |frame: usize| {
+            frames[frame]
+                .vertices
+                .iter()
+                .fold(0, |max_x, vertex| max_x.max(vertex.position[0]))
+        };
+        assert!(max_x(1) > max_x(0));
+    }
+}
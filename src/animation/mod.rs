@@ -0,0 +1,9 @@
+mod clip;
+mod flipbook;
+mod player;
+mod skin;
+
+pub use clip::{AnimationClip, Channel, Interpolation, Keyframes, Pose};
+pub use flipbook::{animate_flipbooks, Flipbook};
+pub use player::{animate, AnimationPlayer};
+pub use skin::{joint_matrices, skin_vertex, Skin};
@@ -0,0 +1,213 @@
+use crate::{
+    animation::{
+        clip::{blend_poses, AnimationClip},
+        Pose,
+    },
+    entity::{Entities, Entity},
+    registry::Handle,
+    transform::Transform,
+};
+
+struct BlendLayer {
+    clip: usize,
+    time: f32,
+    weight: f32,
+    // a cross fade raises the weight to 1 over this duration and then switches to the clip
+    fade_duration: Option<f32>,
+}
+
+// targets maps the nodes animated by the clips to entities, rest holds the local transforms of the nodes
+pub struct AnimationPlayer {
+    pub clips: Vec<AnimationClip>,
+    pub targets: Vec<Handle<Entity>>,
+    pub rest: Vec<Transform>,
+    pub speed: f32,
+    pub looping: bool,
+    current: usize,
+    time: f32,
+    blend: Option<BlendLayer>,
+}
+
+impl AnimationPlayer {
+    pub fn new(clips: Vec<AnimationClip>, targets: Vec<Handle<Entity>>, rest: Vec<Transform>) -> Self {
+        Self {
+            clips,
+            targets,
+            rest,
+            speed: 1.0,
+            looping: true,
+            current: 0,
+            time: 0.0,
+            blend: None,
+        }
+    }
+
+    pub fn find_clip(&self, name: &str) -> Option<usize> {
+        self.clips.iter().position(|clip| clip.name.as_deref() == Some(name))
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn play(&mut self, clip: usize) {
+        self.current = clip;
+        self.time = 0.0;
+        self.blend = None;
+    }
+
+    pub fn cross_fade(&mut self, clip: usize, duration: f32) {
+        self.blend = Some(BlendLayer {
+            clip,
+            time: 0.0,
+            weight: 0.0,
+            fade_duration: Some(duration),
+        });
+    }
+
+    // keeps playing both clips, weight 0 is only the current clip and weight 1 only the blended clip
+    pub fn blend_with(&mut self, clip: usize, weight: f32) {
+        match &mut self.blend {
+            Some(blend) if blend.clip == clip => {
+                blend.weight = weight;
+                blend.fade_duration = None;
+            }
+            _ => {
+                self.blend = Some(BlendLayer {
+                    clip,
+                    time: self.time,
+                    weight,
+                    fade_duration: None,
+                })
+            }
+        }
+    }
+
+    fn advance(&self, clip: usize, time: f32, dt: f32) -> f32 {
+        let duration = self.clips[clip].duration;
+        let time = time + dt * self.speed;
+        if duration <= 0.0 {
+            0.0
+        } else if self.looping {
+            time.rem_euclid(duration)
+        } else {
+            time.max(0.0).min(duration)
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if self.clips.is_empty() {
+            return;
+        }
+        self.time = self.advance(self.current, self.time, dt);
+        if let Some(mut blend) = self.blend.take() {
+            blend.time = self.advance(blend.clip, blend.time, dt);
+            if let Some(fade_duration) = blend.fade_duration {
+                blend.weight = if fade_duration > 0.0 {
+                    (blend.weight + dt / fade_duration).min(1.0)
+                } else {
+                    1.0
+                };
+            }
+            if blend.fade_duration.is_some() && blend.weight >= 1.0 {
+                self.current = blend.clip;
+                self.time = blend.time;
+            } else {
+                self.blend = Some(blend);
+            }
+        }
+    }
+
+    pub fn pose(&self) -> Pose {
+        if self.clips.is_empty() {
+            return Pose::new();
+        }
+        let pose = self.clips[self.current].sample(self.time, &self.rest);
+        match &self.blend {
+            Some(blend) => blend_poses(
+                &pose,
+                &self.clips[blend.clip].sample(blend.time, &self.rest),
+                blend.weight,
+                &self.rest,
+            ),
+            None => pose,
+        }
+    }
+}
+
+// advances every animation player and writes the pose into the transforms of the targets
+pub fn animate(entities: &mut Entities, dt: f32) {
+    let mut poses = Vec::new();
+    for (_, player) in entities.query_mut::<AnimationPlayer>() {
+        player.update(dt);
+        for (node, transform) in player.pose() {
+            if let Some(target) = player.targets.get(node) {
+                poses.push((*target, transform));
+            }
+        }
+    }
+    for (target, transform) in poses {
+        entities.insert(&target, transform);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        animation::{animate, AnimationClip, AnimationPlayer, Channel, Interpolation, Keyframes},
+        entity::Entities,
+        transform::Transform,
+    };
+    use glam::Vec3;
+
+    fn move_clip(name: &str, to: Vec3) -> AnimationClip {
+        AnimationClip::new(
+            Some(name.to_string()),
+            vec![Channel {
+                target: 0,
+                interpolation: Interpolation::Linear,
+                times: vec![0.0, 1.0],
+                keyframes: Keyframes::Translation(vec![Vec3::zero(), to]),
+            }],
+        )
+    }
+
+    #[test]
+    fn play_and_cross_fade() {
+        let mut entities = Entities::new();
+        let bone = entities.spawn().with(Transform::identity()).id();
+        let player = AnimationPlayer::new(
+            vec![
+                move_clip("walk", Vec3::new(1.0, 0.0, 0.0)),
+                move_clip("jump", Vec3::new(0.0, 1.0, 0.0)),
+            ],
+            vec![bone],
+            vec![Transform::identity()],
+        );
+        let animated = entities.spawn().with(player).id();
+
+        animate(&mut entities, 0.5);
+        assert_eq!(
+            Vec3::new(0.5, 0.0, 0.0),
+            entities.get::<Transform>(&bone).unwrap().translation
+        );
+        animate(&mut entities, 0.75);
+        let translation = entities.get::<Transform>(&bone).unwrap().translation;
+        assert!((translation - Vec3::new(0.25, 0.0, 0.0)).length() < 0.0001);
+
+        let player = entities.get_mut::<AnimationPlayer>(&animated).unwrap();
+        let jump = player.find_clip("jump").unwrap();
+        player.cross_fade(jump, 1.0);
+        animate(&mut entities, 0.5);
+        let translation = entities.get::<Transform>(&bone).unwrap().translation;
+        assert!((translation - Vec3::new(0.375, 0.25, 0.0)).length() < 0.0001);
+        animate(&mut entities, 0.5);
+        let player = entities.get::<AnimationPlayer>(&animated).unwrap();
+        assert_eq!(jump, player.current());
+        assert_eq!(0.0, player.time());
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{
+    entity::{Entities, Entity},
+    mesh::SkinnedVertex,
+    registry::Handle,
+    transform::GlobalTransform,
+};
+use glam::{Mat4, Vec3};
+
+// inverse_bind_matrices has a matrix for every joint
+pub struct Skin {
+    pub joints: Vec<Handle<Entity>>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+// joint matrices in the model space of the skinned entity, uses the transforms of the last propagate_transforms
+pub fn joint_matrices(entities: &Entities, skinned: &Handle<Entity>) -> Option<Vec<Mat4>> {
+    let skin = entities.get::<Skin>(skinned)?;
+    let inverse_model = entities
+        .get::<GlobalTransform>(skinned)
+        .map(|transform| transform.0.to_matrix().inverse())
+        .unwrap_or(Mat4::identity());
+    skin.joints
+        .iter()
+        .zip(skin.inverse_bind_matrices.iter())
+        .map(|(joint, inverse_bind_matrix)| {
+            let joint_transform = entities.get::<GlobalTransform>(joint)?.0.to_matrix();
+            Some(inverse_model * joint_transform * *inverse_bind_matrix)
+        })
+        .collect()
+}
+
+// position and normal in model space like vs_skinned computes them, joint_matrices are the ones of the skin
+pub fn skin_vertex(joint_matrices: &[Mat4], vertex: &SkinnedVertex) -> (Vec3, Vec3) {
+    let position = Vec3::from(vertex.position);
+    let normal = Vec3::from(vertex.normal);
+    vertex
+        .joints
+        .iter()
+        .zip(vertex.weights.iter())
+        .filter(|(_, weight)| **weight > 0.0)
+        .fold(
+            (Vec3::zero(), Vec3::zero()),
+            |(skinned_position, skinned_normal), (joint, weight)| {
+                let joint_matrix = joint_matrices[*joint as usize];
+                (
+                    skinned_position + joint_matrix.transform_point3(position) * *weight,
+                    skinned_normal + joint_matrix.transform_vector3(normal) * *weight,
+                )
+            },
+        )
+}
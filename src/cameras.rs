@@ -56,6 +56,19 @@ impl FollowCamera {
         self.to_follow = to_follow;
     }
 
+    pub fn follow_angle(&self) -> f32 {
+        self.follow_angle
+    }
+
+    pub fn follow_distance(&self) -> f32 {
+        self.follow_distance
+    }
+
+    pub fn set_follow_angle_distance(&mut self, follow_angle: f32, follow_distance: f32) {
+        self.follow_angle = follow_angle;
+        self.follow_distance = follow_distance;
+    }
+
     fn get_camera_transform(&self) -> Mat4 {
         let mut rotate_around = self.to_follow.clone();
         rotate_around.rotation *= Quat::from_rotation_x(-self.follow_angle * std::f32::consts::PI * 2.0 / 360.0);
@@ -0,0 +1,217 @@
+use crate::{
+    entity::Entity,
+    registry::{Handle, Registry},
+};
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+type Storage<T> = HashMap<Handle<Entity>, T>;
+
+trait AnyStorage {
+    fn remove_entity(&mut self, entity: &Handle<Entity>);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+    fn remove_entity(&mut self, entity: &Handle<Entity>) {
+        self.remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// component store, every component type has its own storage keyed by entity
+#[derive(Default)]
+pub struct Entities {
+    entities: Registry<Entity>,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+}
+
+pub struct EntityBuilder<'a> {
+    entities: &'a mut Entities,
+    entity: Handle<Entity>,
+}
+
+impl<'a> EntityBuilder<'a> {
+    pub fn with<T: 'static>(self, component: T) -> Self {
+        self.entities.insert(&self.entity, component);
+        self
+    }
+
+    pub fn id(&self) -> Handle<Entity> {
+        self.entity
+    }
+}
+
+impl Entities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> EntityBuilder<'_> {
+        let entity = self.entities.add(Entity);
+        EntityBuilder { entities: self, entity }
+    }
+
+    // children of the despawned entity move to its parent, use despawn_recursive to remove them as well
+    pub fn despawn(&mut self, entity: &Handle<Entity>) {
+        if self.contains(entity) {
+            self.detach(entity);
+            self.entities.remove(*entity);
+            for storage in self.storages.values_mut() {
+                storage.remove_entity(entity);
+            }
+        }
+    }
+
+    pub fn contains(&self, entity: &Handle<Entity>) -> bool {
+        self.entities.contains(entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Handle<Entity>> + '_ {
+        self.entities.handles()
+    }
+
+    fn storage<T: 'static>(&self) -> Option<&Storage<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> Option<&mut Storage<T>> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut())
+    }
+
+    fn storage_or_insert<T: 'static>(&mut self) -> &mut Storage<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Storage::<T>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+
+    // returns the component that was replaced, components are not added to despawned entities
+    pub fn insert<T: 'static>(&mut self, entity: &Handle<Entity>, component: T) -> Option<T> {
+        if !self.contains(entity) {
+            return None;
+        }
+        self.storage_or_insert().insert(*entity, component)
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: &Handle<Entity>) -> Option<T> {
+        self.storage_mut()?.remove(entity)
+    }
+
+    pub fn get<T: 'static>(&self, entity: &Handle<Entity>) -> Option<&T> {
+        self.storage()?.get(entity)
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: &Handle<Entity>) -> Option<&mut T> {
+        self.storage_mut()?.get_mut(entity)
+    }
+
+    pub fn has<T: 'static>(&self, entity: &Handle<Entity>) -> bool {
+        self.get::<T>(entity).is_some()
+    }
+
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (Handle<Entity>, &T)> {
+        self.storage::<T>()
+            .into_iter()
+            .flat_map(|storage| storage.iter().map(|(entity, component)| (*entity, component)))
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (Handle<Entity>, &mut T)> {
+        self.storage_mut::<T>()
+            .into_iter()
+            .flat_map(|storage| storage.iter_mut().map(|(entity, component)| (*entity, component)))
+    }
+
+    pub fn query2<A: 'static, B: 'static>(&self) -> impl Iterator<Item = (Handle<Entity>, &A, &B)> {
+        let b = self.storage::<B>();
+        self.query::<A>()
+            .filter_map(move |(entity, a)| b?.get(&entity).map(|b| (entity, a, b)))
+    }
+
+    // the storage of A is taken out while iterating, so A and B have to be different components
+    pub fn for_each2_mut<A: 'static, B: 'static>(&mut self, mut f: impl FnMut(Handle<Entity>, &mut A, &B)) {
+        assert_ne!(TypeId::of::<A>(), TypeId::of::<B>());
+        if let Some(mut storage_a) = self.storages.remove(&TypeId::of::<A>()) {
+            if let Some(storage_b) = self.storage::<B>() {
+                let a = storage_a.as_any_mut().downcast_mut::<Storage<A>>().unwrap();
+                for (entity, a) in a.iter_mut() {
+                    if let Some(b) = storage_b.get(entity) {
+                        f(*entity, a, b);
+                    }
+                }
+            }
+            self.storages.insert(TypeId::of::<A>(), storage_a);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::Entities;
+
+    struct Position(i32);
+    struct Velocity(i32);
+
+    #[test]
+    fn query_component_combinations() {
+        let mut entities = Entities::new();
+        let moving = entities.spawn().with(Position(0)).with(Velocity(2)).id();
+        let still = entities.spawn().with(Position(5)).id();
+        entities.spawn().with(Velocity(1));
+
+        assert_eq!(2, entities.query::<Position>().count());
+        let both = entities.query2::<Position, Velocity>().collect::<Vec<_>>();
+        assert_eq!(1, both.len());
+        assert_eq!(moving, both[0].0);
+
+        entities.for_each2_mut::<Position, Velocity>(|_, position, velocity| position.0 += velocity.0);
+        assert_eq!(2, entities.get::<Position>(&moving).unwrap().0);
+        assert_eq!(5, entities.get::<Position>(&still).unwrap().0);
+    }
+
+    #[test]
+    fn despawn_removes_components() {
+        let mut entities = Entities::new();
+        let entity = entities.spawn().with(Position(1)).with(Velocity(1)).id();
+        entities.despawn(&entity);
+        assert!(!entities.contains(&entity));
+        assert!(entities.get::<Position>(&entity).is_none());
+        assert_eq!(0, entities.query::<Velocity>().count());
+        assert!(entities.insert(&entity, Position(3)).is_none());
+        assert_eq!(0, entities.query::<Position>().count());
+    }
+
+    #[test]
+    fn missing_components_do_not_create_storages() {
+        let mut entities = Entities::new();
+        let entity = entities.spawn().with(Position(1)).id();
+        assert!(entities.remove::<Velocity>(&entity).is_none());
+        assert!(entities.get_mut::<Velocity>(&entity).is_none());
+        assert_eq!(0, entities.query_mut::<Velocity>().count());
+        assert_eq!(1, entities.storages.len());
+    }
+}
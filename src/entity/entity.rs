@@ -1,7 +1,2 @@
-use crate::{physics::CollisionShape, registry::Handle, renderer::Mesh, transform::Transform};
-
-pub struct Entity {
-    pub mesh_handle: Handle<Mesh>,
-    pub collision_shape: Option<CollisionShape>,
-    pub transform: Transform,
-}
+// entities are only ids, everything else is attached to them as components
+pub struct Entity;
@@ -0,0 +1,177 @@
+use crate::{
+    entity::{Entities, Entity},
+    registry::Handle,
+    transform::{GlobalTransform, Transform},
+};
+
+pub struct Parent(pub Handle<Entity>);
+
+#[derive(Default)]
+pub struct Children(pub Vec<Handle<Entity>>);
+
+impl Entities {
+    // returns false when the parent does not exist or is a descendant of the child
+    pub fn set_parent(&mut self, child: &Handle<Entity>, parent: &Handle<Entity>) -> bool {
+        if !self.contains(child) || !self.contains(parent) || self.is_ancestor(child, parent) {
+            return false;
+        }
+        self.remove_parent(child);
+        self.insert(child, Parent(*parent));
+        if self.get::<Children>(parent).is_none() {
+            self.insert(parent, Children::default());
+        }
+        self.get_mut::<Children>(parent).unwrap().0.push(*child);
+        true
+    }
+
+    pub fn remove_parent(&mut self, child: &Handle<Entity>) {
+        if let Some(Parent(parent)) = self.remove::<Parent>(child) {
+            if let Some(children) = self.get_mut::<Children>(&parent) {
+                children.0.retain(|c| c != child);
+            }
+        }
+    }
+
+    pub fn despawn_recursive(&mut self, entity: &Handle<Entity>) {
+        if let Some(Children(children)) = self.remove::<Children>(entity) {
+            for child in children {
+                self.despawn_recursive(&child);
+            }
+        }
+        self.despawn(entity);
+    }
+
+    // the children move up to the parent of the entity and keep their place in the world
+    pub(crate) fn detach(&mut self, entity: &Handle<Entity>) {
+        let parent = self.get::<Parent>(entity).map(|parent| parent.0);
+        self.remove_parent(entity);
+        let local = self.get::<Transform>(entity).cloned().unwrap_or_default();
+        if let Some(Children(children)) = self.remove::<Children>(entity) {
+            for child in children {
+                self.remove::<Parent>(&child);
+                if let Some(transform) = self.get_mut::<Transform>(&child) {
+                    *transform = local.mul_transform(transform.clone());
+                }
+                if let Some(parent) = &parent {
+                    self.set_parent(&child, parent);
+                }
+            }
+        }
+    }
+
+    fn is_ancestor(&self, ancestor: &Handle<Entity>, entity: &Handle<Entity>) -> bool {
+        let mut current = Some(*entity);
+        while let Some(entity) = current {
+            if entity == *ancestor {
+                return true;
+            }
+            current = self.get::<Parent>(&entity).map(|parent| parent.0);
+        }
+        false
+    }
+}
+
+// computes the GlobalTransform of every entity with a Transform by walking down from the roots
+pub fn propagate_transforms(entities: &mut Entities) {
+    let mut stack = entities
+        .query::<Transform>()
+        .filter(|(entity, _)| !entities.has::<Parent>(entity))
+        .map(|(entity, _)| (entity, Transform::identity()))
+        .collect::<Vec<_>>();
+    while let Some((entity, parent_transform)) = stack.pop() {
+        let local = entities.get::<Transform>(&entity).cloned().unwrap_or_default();
+        let global = parent_transform.mul_transform(local);
+        if let Some(children) = entities.get::<Children>(&entity) {
+            stack.extend(children.0.iter().map(|child| (*child, global.clone())));
+        }
+        entities.insert(&entity, GlobalTransform(global));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        entity::{propagate_transforms, Children, Entities, Parent},
+        transform::{GlobalTransform, Transform},
+    };
+    use glam::{Quat, Vec3};
+
+    #[test]
+    fn global_transforms_follow_parent() {
+        let mut entities = Entities::new();
+        let character = entities
+            .spawn()
+            .with(Transform::from_translation_rotation(
+                Vec3::new(1.0, 0.0, 0.0),
+                Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            ))
+            .id();
+        let lamp = entities
+            .spawn()
+            .with(Transform::from_translation(Vec3::new(0.0, 0.0, 2.0)))
+            .id();
+        assert!(entities.set_parent(&lamp, &character));
+        propagate_transforms(&mut entities);
+        let lamp_translation = entities.get::<GlobalTransform>(&lamp).unwrap().0.translation;
+        assert!((lamp_translation - Vec3::new(3.0, 0.0, 0.0)).length() < 0.001);
+
+        entities.get_mut::<Transform>(&character).unwrap().translation = Vec3::new(0.0, 5.0, 0.0);
+        propagate_transforms(&mut entities);
+        let lamp_translation = entities.get::<GlobalTransform>(&lamp).unwrap().0.translation;
+        assert!((lamp_translation - Vec3::new(2.0, 5.0, 0.0)).length() < 0.001);
+    }
+
+    #[test]
+    fn reparent_and_despawn_subtrees() {
+        let mut entities = Entities::new();
+        let root = entities.spawn().with(Transform::identity()).id();
+        let other_root = entities.spawn().with(Transform::identity()).id();
+        let child = entities.spawn().with(Transform::identity()).id();
+        let grandchild = entities.spawn().with(Transform::identity()).id();
+        assert!(entities.set_parent(&child, &root));
+        assert!(entities.set_parent(&grandchild, &child));
+        assert!(!entities.set_parent(&root, &grandchild));
+
+        assert!(entities.set_parent(&child, &other_root));
+        assert!(entities.get::<Children>(&root).unwrap().0.is_empty());
+        assert_eq!(vec![child], entities.get::<Children>(&other_root).unwrap().0);
+
+        entities.despawn(&child);
+        assert_eq!(
+            Some(other_root),
+            entities.get::<Parent>(&grandchild).map(|parent| parent.0)
+        );
+        assert_eq!(vec![grandchild], entities.get::<Children>(&other_root).unwrap().0);
+
+        assert!(entities.set_parent(&grandchild, &root));
+        entities.despawn_recursive(&root);
+        assert!(!entities.contains(&root));
+        assert!(!entities.contains(&grandchild));
+        assert!(entities.contains(&other_root));
+    }
+
+    #[test]
+    fn children_keep_their_place_when_the_parent_is_despawned() {
+        let mut entities = Entities::new();
+        let character = entities
+            .spawn()
+            .with(Transform::from_translation_rotation(
+                Vec3::new(1.0, 0.0, 0.0),
+                Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+            ))
+            .id();
+        let lamp = entities
+            .spawn()
+            .with(Transform::from_translation(Vec3::new(0.0, 0.0, 2.0)))
+            .id();
+        assert!(entities.set_parent(&lamp, &character));
+        propagate_transforms(&mut entities);
+        let before = entities.get::<GlobalTransform>(&lamp).unwrap().0.translation;
+
+        entities.despawn(&character);
+        propagate_transforms(&mut entities);
+        assert!(entities.get::<Parent>(&lamp).is_none());
+        let after = entities.get::<GlobalTransform>(&lamp).unwrap().0.translation;
+        assert!((after - before).length() < 0.001);
+    }
+}
@@ -1,3 +1,7 @@
+mod entities;
 mod entity;
+mod hierarchy;
 
+pub use entities::{Entities, EntityBuilder};
 pub use entity::Entity;
+pub use hierarchy::{propagate_transforms, Children, Parent};
@@ -1,5 +1,15 @@
-use crate::mesh::{triangle_normal, MeshData, Vertex};
-use gltf::mesh::Mode;
+use crate::{
+    animation::{AnimationClip, Channel, Interpolation, Keyframes},
+    gltf::{GltfNode, GltfScene, GltfSkin},
+    mesh::{triangle_normal, MeshData, SkinnedMeshData, SkinnedVertex, Vertex},
+    transform::Transform,
+};
+use glam::{Mat4, Quat, Vec3};
+use gltf::{
+    animation::{util::ReadOutputs, Property},
+    mesh::Mode,
+};
+use std::{path::Path, sync::Arc};
 
 #[derive(Debug)]
 pub enum MeshLoadError {
@@ -8,6 +18,16 @@ pub enum MeshLoadError {
     UnsupportedBufferFormat,
     UnsupportedPrimitiveMode,
     MissingBlob,
+    MissingPositions,
+    // a vertex attribute has a different number of values than the positions
+    AttributeCountMismatch,
+    // an animation channel has a different number of values than keyframe times
+    KeyframeCountMismatch,
+    InvalidIndices,
+    // GltfScene::spawn got fewer mesh handles than there are meshes
+    MissingMeshHandle,
+    IOError(std::io::Error),
+    Image(image::ImageError),
 }
 
 impl From<gltf::Error> for MeshLoadError {
@@ -22,72 +42,308 @@ impl From<base64::DecodeError> for MeshLoadError {
     }
 }
 
+impl From<std::io::Error> for MeshLoadError {
+    fn from(e: std::io::Error) -> MeshLoadError {
+        MeshLoadError::IOError(e)
+    }
+}
+
+impl From<image::ImageError> for MeshLoadError {
+    fn from(e: image::ImageError) -> MeshLoadError {
+        MeshLoadError::Image(e)
+    }
+}
+
+// calls named_mesh for every named node with a mesh, the node transforms are not applied
 pub fn load_gltf(bytes: &[u8], mut named_mesh: impl FnMut(String, MeshData)) -> Result<(), MeshLoadError> {
+    let scene = load_gltf_scene(bytes)?;
+    for node in &scene.nodes {
+        if let (Some(name), Some(mesh)) = (&node.name, node.mesh) {
+            named_mesh(name.clone(), scene.meshes[mesh].clone());
+        }
+    }
+    Ok(())
+}
+
+// only embedded buffers and images can be resolved, use load_gltf_file for files referring to other files
+pub fn load_gltf_scene(bytes: &[u8]) -> Result<GltfScene, MeshLoadError> {
+    load_gltf_scene_from(bytes, None)
+}
+
+// loads .gltf and .glb files, relative uris are resolved against the directory of the file
+pub fn load_gltf_file(path: impl AsRef<Path>) -> Result<GltfScene, MeshLoadError> {
+    let path = path.as_ref();
+    load_gltf_scene_from(std::fs::read(path)?.as_slice(), path.parent())
+}
+
+fn load_gltf_scene_from(bytes: &[u8], base: Option<&Path>) -> Result<GltfScene, MeshLoadError> {
     let gltf = gltf::Gltf::from_slice(bytes)?;
-    let buffer_data = load_buffers(&gltf)?;
-    for node in gltf.nodes() {
-        if let Some(node_name) = node.name() {
-            let mesh = node.mesh().unwrap();
-            let mut vertices = Vec::new();
-            let mut indices = Vec::new();
-            for primitive in mesh.primitives() {
-                if primitive.mode() != Mode::Triangles {
-                    return Err(MeshLoadError::UnsupportedPrimitiveMode);
+    let buffer_data = load_buffers(&gltf, base)?;
+    let images = load_images(&gltf, &buffer_data, base)?;
+    let (meshes, skinned_meshes) = gltf
+        .meshes()
+        .map(|mesh| load_mesh(&mesh, &buffer_data, &images))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .unzip();
+    let skins = gltf.skins().map(|skin| load_skin(&skin, &buffer_data)).collect();
+    let animations = gltf
+        .animations()
+        .map(|animation| load_animation(&animation, &buffer_data))
+        .collect::<Result<_, _>>()?;
+    let nodes = gltf
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            GltfNode {
+                name: node.name().map(|name| name.to_string()),
+                transform: Transform::from_translation_rotation_scale(
+                    Vec3::from(translation),
+                    Quat::from_xyzw(rotation[0], rotation[1], rotation[2], rotation[3]),
+                    Vec3::from(scale),
+                ),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                skin: node.skin().map(|skin| skin.index()),
+                children: node.children().map(|child| child.index()).collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let roots = match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+        Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+        None => (0..nodes.len())
+            .filter(|index| !nodes.iter().any(|node| node.children.contains(index)))
+            .collect(),
+    };
+    Ok(GltfScene {
+        nodes,
+        meshes,
+        skinned_meshes,
+        skins,
+        animations,
+        images,
+        roots,
+    })
+}
+
+// all primitives of a mesh are merged, vertex colors are multiplied by the base color of the material
+// a skinned mesh is only returned when every primitive has joints and weights, the same holds for uvs and tangents
+// the first base color texture of the primitives is used for the whole mesh
+fn load_mesh(
+    mesh: &gltf::Mesh,
+    buffer_data: &[Vec<u8>],
+    images: &[Arc<image::RgbaImage>],
+) -> Result<(MeshData, Option<SkinnedMeshData>), MeshLoadError> {
+    let mut base_color_texture = None;
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut influences = Some(Vec::new());
+    let mut uvs = Some(Vec::new());
+    let mut tangents = Some(Vec::new());
+    for primitive in mesh.primitives() {
+        if primitive.mode() != Mode::Triangles {
+            return Err(MeshLoadError::UnsupportedPrimitiveMode);
+        }
+        let reader = primitive.reader(|buffer| buffer_data.get(buffer.index()).map(|data| data.as_slice()));
+        let positions = reader
+            .read_positions()
+            .ok_or(MeshLoadError::MissingPositions)?
+            .collect::<Vec<[f32; 3]>>();
+        let primitive_indices = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect::<Vec<u32>>(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        if primitive_indices.len() % 3 != 0 || primitive_indices.iter().any(|i| *i as usize >= positions.len()) {
+            return Err(MeshLoadError::InvalidIndices);
+        }
+        let pbr = primitive.material().pbr_metallic_roughness();
+        let base_color = pbr.base_color_factor();
+        if base_color_texture.is_none() {
+            base_color_texture = pbr
+                .base_color_texture()
+                .and_then(|info| images.get(info.texture().source().index()))
+                .cloned();
+        }
+        let colors = match reader.read_colors(0) {
+            Some(colors) => colors.into_rgb_f32().collect::<Vec<[f32; 3]>>(),
+            None => vec![[1.0, 1.0, 1.0]; positions.len()],
+        };
+        let colors = colors
+            .iter()
+            .map(|c| [c[0] * base_color[0], c[1] * base_color[1], c[2] * base_color[2]])
+            .collect::<Vec<_>>();
+        let primitive_influences = match (reader.read_joints(0), reader.read_weights(0)) {
+            (Some(joints), Some(weights)) => Some(
+                joints
+                    .into_u16()
+                    .map(|j| [j[0] as u32, j[1] as u32, j[2] as u32, j[3] as u32])
+                    .zip(weights.into_f32())
+                    .collect::<Vec<_>>(),
+            ),
+            _ => None,
+        };
+        let primitive_uvs = reader
+            .read_tex_coords(0)
+            .map(|uvs| uvs.into_f32().collect::<Vec<[f32; 2]>>());
+        let primitive_tangents = reader
+            .read_tangents()
+            .map(|tangents| tangents.collect::<Vec<[f32; 4]>>());
+        let normals = reader.read_normals().map(|normals| normals.collect::<Vec<[f32; 3]>>());
+        let attribute_counts = [
+            normals.as_ref().map(|normals| normals.len()),
+            Some(colors.len()),
+            primitive_influences.as_ref().map(|influences| influences.len()),
+            primitive_uvs.as_ref().map(|uvs| uvs.len()),
+            primitive_tangents.as_ref().map(|tangents| tangents.len()),
+        ];
+        if attribute_counts.iter().flatten().any(|count| *count != positions.len()) {
+            return Err(MeshLoadError::AttributeCountMismatch);
+        }
+        let offset = vertices.len() as u32;
+        match normals {
+            Some(normals) => {
+                vertices.extend(
+                    positions
+                        .iter()
+                        .zip(normals)
+                        .zip(colors.iter())
+                        .map(|((position, normal), color)| Vertex {
+                            position: *position,
+                            normal,
+                            color: *color,
+                        }),
+                );
+                indices.extend(primitive_indices.iter().map(|i| offset + i));
+                if let (Some(influences), Some(primitive_influences)) = (&mut influences, &primitive_influences) {
+                    influences.extend_from_slice(primitive_influences);
+                }
+                if let (Some(uvs), Some(primitive_uvs)) = (&mut uvs, &primitive_uvs) {
+                    uvs.extend_from_slice(primitive_uvs);
+                }
+                if let (Some(tangents), Some(primitive_tangents)) = (&mut tangents, &primitive_tangents) {
+                    tangents.extend_from_slice(primitive_tangents);
                 }
-                let reader = primitive.reader(|buffer| Some(&buffer_data[buffer.index()]));
-
-                if let Some(positions) = reader.read_positions().map(|v| v.collect::<Vec<[f32; 3]>>()) {
-                    if let Some(gltf_indices) = reader
-                        .read_indices()
-                        .map(|indices| indices.into_u32().collect::<Vec<u32>>())
-                    {
-                        assert!(gltf_indices.len() % 3 == 0);
-                        let mut count = 0;
-                        for i in gltf_indices.chunks(3) {
-                            let v0 = positions[i[0] as usize];
-                            let v1 = positions[i[1] as usize];
-                            let v2 = positions[i[2] as usize];
-                            let n = triangle_normal(v0, v1, v2);
-                            vertices.extend_from_slice(&[
-                                Vertex {
-                                    position: v0,
-                                    normal: n,
-                                    color: [1.0, 0.0, 0.0],
-                                },
-                                Vertex {
-                                    position: v1,
-                                    normal: n,
-                                    color: [1.0, 0.0, 0.0],
-                                },
-                                Vertex {
-                                    position: v2,
-                                    normal: n,
-                                    color: [1.0, 0.0, 0.0],
-                                },
-                            ]);
-                            indices.extend_from_slice(&[count, count + 1, count + 2]);
-                            count += 3;
-                        }
+            }
+            // without normals every triangle gets its own vertices with a flat normal
+            None => {
+                for (count, i) in primitive_indices.chunks(3).enumerate() {
+                    let n = triangle_normal(
+                        positions[i[0] as usize],
+                        positions[i[1] as usize],
+                        positions[i[2] as usize],
+                    );
+                    vertices.extend(i.iter().map(|i| Vertex {
+                        position: positions[*i as usize],
+                        normal: n,
+                        color: colors[*i as usize],
+                    }));
+                    let first = offset + count as u32 * 3;
+                    indices.extend_from_slice(&[first, first + 1, first + 2]);
+                    if let (Some(influences), Some(primitive_influences)) = (&mut influences, &primitive_influences) {
+                        influences.extend(i.iter().map(|i| primitive_influences[*i as usize]));
+                    }
+                    if let (Some(uvs), Some(primitive_uvs)) = (&mut uvs, &primitive_uvs) {
+                        uvs.extend(i.iter().map(|i| primitive_uvs[*i as usize]));
+                    }
+                    if let (Some(tangents), Some(primitive_tangents)) = (&mut tangents, &primitive_tangents) {
+                        tangents.extend(i.iter().map(|i| primitive_tangents[*i as usize]));
                     }
                 }
             }
-            named_mesh(node_name.to_string(), MeshData { vertices, indices });
+        }
+        if primitive_influences.is_none() {
+            influences = None;
+        }
+        if primitive_uvs.is_none() {
+            uvs = None;
+        }
+        if primitive_tangents.is_none() {
+            tangents = None;
         }
     }
-    Ok(())
+    let skinned = influences.map(|influences| SkinnedMeshData {
+        vertices: vertices
+            .iter()
+            .zip(influences)
+            .map(|(vertex, (joints, weights))| SkinnedVertex::new(*vertex, joints, weights))
+            .collect(),
+        indices: indices.clone(),
+    });
+    Ok((
+        MeshData {
+            vertices,
+            indices,
+            base_color_texture: base_color_texture.filter(|_| uvs.is_some()),
+            uvs,
+            tangents,
+        },
+        skinned,
+    ))
+}
+
+fn load_skin(skin: &gltf::Skin, buffer_data: &[Vec<u8>]) -> GltfSkin {
+    let joints = skin.joints().map(|joint| joint.index()).collect::<Vec<_>>();
+    let reader = skin.reader(|buffer| buffer_data.get(buffer.index()).map(|data| data.as_slice()));
+    let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+        Some(matrices) => matrices.map(|m| Mat4::from_cols_array_2d(&m)).collect(),
+        None => vec![Mat4::identity(); joints.len()],
+    };
+    GltfSkin {
+        joints,
+        inverse_bind_matrices,
+    }
 }
 
-fn load_buffers(gltf: &gltf::Gltf) -> Result<Vec<Vec<u8>>, MeshLoadError> {
-    const OCTET_STREAM_URI: &str = "data:application/octet-stream;base64,";
+// morph target weights are not supported and skipped
+fn load_animation(animation: &gltf::Animation, buffer_data: &[Vec<u8>]) -> Result<AnimationClip, MeshLoadError> {
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        if let Property::MorphTargetWeights = channel.target().property() {
+            continue;
+        }
+        let reader = channel.reader(|buffer| buffer_data.get(buffer.index()).map(|data| data.as_slice()));
+        let (times, outputs) = match (reader.read_inputs(), reader.read_outputs()) {
+            (Some(times), Some(outputs)) => (times.collect::<Vec<f32>>(), outputs),
+            _ => continue,
+        };
+        let keyframes = match outputs {
+            ReadOutputs::Translations(translations) => Keyframes::Translation(translations.map(Vec3::from).collect()),
+            ReadOutputs::Rotations(rotations) => Keyframes::Rotation(
+                rotations
+                    .into_f32()
+                    .map(|r| Quat::from_xyzw(r[0], r[1], r[2], r[3]))
+                    .collect(),
+            ),
+            ReadOutputs::Scales(scales) => Keyframes::Scale(scales.map(Vec3::from).collect()),
+            ReadOutputs::MorphTargetWeights(_) => continue,
+        };
+        let interpolation = match channel.sampler().interpolation() {
+            gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            gltf::animation::Interpolation::Step => Interpolation::Step,
+            gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        };
+        let channel = Channel {
+            target: channel.target().node().index(),
+            interpolation,
+            times,
+            keyframes,
+        };
+        if !channel.has_matching_keyframes() {
+            return Err(MeshLoadError::KeyframeCountMismatch);
+        }
+        channels.push(channel);
+    }
+    Ok(AnimationClip::new(
+        animation.name().map(|name| name.to_string()),
+        channels,
+    ))
+}
+
+fn load_buffers(gltf: &gltf::Gltf, base: Option<&Path>) -> Result<Vec<Vec<u8>>, MeshLoadError> {
     let mut buffer_data = Vec::new();
     for buffer in gltf.buffers() {
         match buffer.source() {
             gltf::buffer::Source::Uri(uri) => {
-                if uri.starts_with(OCTET_STREAM_URI) {
-                    buffer_data.push(base64::decode(&uri[OCTET_STREAM_URI.len()..])?);
-                } else {
-                    return Err(MeshLoadError::UnsupportedBufferFormat);
-                }
+                buffer_data.push(load_uri(uri, base)?);
             }
             gltf::buffer::Source::Bin => {
                 if let Some(blob) = gltf.blob.as_deref() {
@@ -102,16 +358,270 @@ fn load_buffers(gltf: &gltf::Gltf) -> Result<Vec<Vec<u8>>, MeshLoadError> {
     Ok(buffer_data)
 }
 
+fn load_images(
+    gltf: &gltf::Gltf,
+    buffer_data: &[Vec<u8>],
+    base: Option<&Path>,
+) -> Result<Vec<Arc<image::RgbaImage>>, MeshLoadError> {
+    let mut images = Vec::new();
+    for image in gltf.images() {
+        let bytes = match image.source() {
+            gltf::image::Source::Uri { uri, .. } => load_uri(uri, base)?,
+            gltf::image::Source::View { view, .. } => {
+                let buffer = buffer_data
+                    .get(view.buffer().index())
+                    .ok_or(MeshLoadError::MissingBlob)?;
+                buffer
+                    .get(view.offset()..view.offset() + view.length())
+                    .ok_or(MeshLoadError::MissingBlob)?
+                    .to_vec()
+            }
+        };
+        images.push(Arc::new(image::load_from_memory(&bytes)?.to_rgba8()));
+    }
+    Ok(images)
+}
+
+// data uris are decoded, other uris are paths relative to base
+fn load_uri(uri: &str, base: Option<&Path>) -> Result<Vec<u8>, MeshLoadError> {
+    if uri.starts_with("data:") {
+        match uri.find(";base64,") {
+            Some(start) => Ok(base64::decode(&uri[start + ";base64,".len()..])?),
+            None => Err(MeshLoadError::UnsupportedBufferFormat),
+        }
+    } else {
+        match base {
+            Some(base) => Ok(std::fs::read(base.join(uri))?),
+            None => Err(MeshLoadError::UnsupportedBufferFormat),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::gltf::loader::load_gltf;
+    use crate::{
+        animation::{animate, joint_matrices, skin_vertex},
+        entity::{propagate_transforms, Entities, Parent},
+        gltf::loader::{load_gltf, load_gltf_file, load_gltf_scene, MeshLoadError},
+        transform::GlobalTransform,
+    };
+    use glam::{Mat4, Vec3};
 
     #[test]
     fn load_gltf_test() {
-        load_gltf(
-            std::fs::read("res/gltf/test.gltf").unwrap().as_slice(),
-            |_mesh, _name| {},
+        for path in &["res/gltf/test.gltf", "res/gltf/test.glb"] {
+            let scene = load_gltf_file(path).unwrap();
+            assert_eq!(vec![0], scene.roots);
+            let quad = scene.named_mesh("quad").unwrap();
+            assert_eq!(4, quad.vertices.len());
+            assert_eq!(6, quad.indices.len());
+            assert_eq!([1.0, 0.0, 0.0], quad.vertices[1].color);
+            assert_eq!(Some(4), quad.uvs.as_ref().map(|uvs| uvs.len()));
+            assert!(quad.tangents.is_none());
+            assert_eq!(1, scene.images.len());
+            assert_eq!((2, 2), scene.images[0].dimensions());
+            assert_eq!([0, 255, 0, 255], scene.images[0].get_pixel(1, 0).0);
+            assert_eq!(
+                Some((2, 2)),
+                quad.base_color_texture.as_ref().map(|image| image.dimensions())
+            );
+        }
+        assert!(matches!(
+            load_gltf(
+                std::fs::read("res/gltf/test.gltf").unwrap().as_slice(),
+                |_name, _mesh| {}
+            ),
+            Err(MeshLoadError::UnsupportedBufferFormat)
+        ));
+    }
+
+    // a red tinted triangle with per vertex colors under a parent node, next to a camera node
+    fn triangle_gltf() -> String {
+        let mut buffer = Vec::new();
+        let positions = [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let normals = [[0.0f32, 0.0, 1.0]; 3];
+        let colors = [[1.0f32, 1.0, 1.0], [0.5, 0.5, 0.5], [0.0, 1.0, 0.0]];
+        for values in positions.iter().chain(normals.iter()).chain(colors.iter()) {
+            for value in values {
+                buffer.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        for index in &[0u16, 1, 2] {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+        format!(
+            r#"{{
+    "asset": {{"version": "2.0"}},
+    "scene": 0,
+    "scenes": [{{"nodes": [0, 2]}}],
+    "nodes": [
+        {{"name": "parent", "translation": [0.0, 2.0, 0.0], "children": [1]}},
+        {{"name": "triangle", "mesh": 0, "translation": [1.0, 0.0, 0.0]}},
+        {{"name": "camera", "camera": 0}}
+    ],
+    "cameras": [{{"type": "perspective", "perspective": {{"yfov": 0.8, "znear": 0.1}}}}],
+    "materials": [{{"pbrMetallicRoughness": {{"baseColorFactor": [1.0, 0.5, 0.5, 1.0]}}}}],
+    "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "NORMAL": 1, "COLOR_0": 2}}, "indices": 3, "material": 0}}]}}],
+    "buffers": [{{"byteLength": {}, "uri": "data:application/octet-stream;base64,{}"}}],
+    "bufferViews": [{{"buffer": 0, "byteLength": 108}}, {{"buffer": 0, "byteOffset": 108, "byteLength": 6}}],
+    "accessors": [
+        {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]}},
+        {{"bufferView": 0, "byteOffset": 36, "componentType": 5126, "count": 3, "type": "VEC3"}},
+        {{"bufferView": 0, "byteOffset": 72, "componentType": 5126, "count": 3, "type": "VEC3"}},
+        {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+    ]
+}}"#,
+            buffer.len(),
+            base64::encode(&buffer)
         )
-        .unwrap();
+    }
+
+    #[test]
+    fn node_tree_and_vertex_attributes() {
+        let scene = load_gltf_scene(triangle_gltf().as_bytes()).unwrap();
+        assert_eq!(3, scene.nodes.len());
+        assert_eq!(vec![0, 2], scene.roots);
+        assert!(scene.nodes[2].mesh.is_none());
+
+        let triangle = scene.named_mesh("triangle").unwrap();
+        assert_eq!(vec![0, 1, 2], triangle.indices);
+        assert_eq!([0.0, 0.0, 1.0], triangle.vertices[0].normal);
+        assert_eq!([1.0, 0.5, 0.5], triangle.vertices[0].color);
+        assert_eq!([0.5, 0.25, 0.25], triangle.vertices[1].color);
+        assert!(triangle.uvs.is_none());
+
+        let mut entities = Entities::new();
+        assert!(matches!(
+            scene.spawn::<u32>(&mut entities, &[]),
+            Err(MeshLoadError::MissingMeshHandle)
+        ));
+        assert!(entities.is_empty());
+        let handles = scene.spawn(&mut entities, &[0u32]).unwrap();
+        propagate_transforms(&mut entities);
+        assert_eq!(Some(&0u32), entities.get::<u32>(&handles[1]));
+        assert_eq!(handles[0], entities.get::<Parent>(&handles[1]).unwrap().0);
+        assert_eq!(
+            Vec3::new(1.0, 2.0, 0.0),
+            entities.get::<GlobalTransform>(&handles[1]).unwrap().0.translation
+        );
+    }
+
+    #[test]
+    fn mismatched_attribute_counts_are_errors() {
+        let gltf = triangle_gltf().replace(
+            r#""byteOffset": 36, "componentType": 5126, "count": 3"#,
+            r#""byteOffset": 36, "componentType": 5126, "count": 2"#,
+        );
+        assert!(matches!(
+            load_gltf_scene(gltf.as_bytes()),
+            Err(MeshLoadError::AttributeCountMismatch)
+        ));
+    }
+
+    // a triangle skinned to two joints, the root joint moves along x in an animation of one second
+    fn skinned_gltf() -> String {
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut views = Vec::new();
+        let mut push = |bytes: Vec<u8>| {
+            views.push(format!(
+                r#"{{"buffer": 0, "byteOffset": {}, "byteLength": {}}}"#,
+                buffer.len(),
+                bytes.len()
+            ));
+            buffer.extend_from_slice(&bytes);
+            while buffer.len() % 4 != 0 {
+                buffer.push(0);
+            }
+        };
+        let floats = |values: &[f32]| {
+            values
+                .iter()
+                .flat_map(|v| v.to_le_bytes().to_vec())
+                .collect::<Vec<u8>>()
+        };
+        let shorts = |values: &[u16]| {
+            values
+                .iter()
+                .flat_map(|v| v.to_le_bytes().to_vec())
+                .collect::<Vec<u8>>()
+        };
+        push(floats(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 2.0, 0.0]));
+        push(shorts(&[0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0]));
+        push(floats(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0]));
+        push(shorts(&[0, 1, 2]));
+        let mut inverse_bind_matrices = Mat4::identity().to_cols_array().to_vec();
+        inverse_bind_matrices.extend_from_slice(&Mat4::from_translation(Vec3::new(0.0, -1.0, 0.0)).to_cols_array());
+        push(floats(&inverse_bind_matrices));
+        push(floats(&[0.0, 1.0]));
+        push(floats(&[0.0, 0.0, 0.0, 2.0, 0.0, 0.0]));
+        format!(
+            r#"{{
+    "asset": {{"version": "2.0"}},
+    "scenes": [{{"nodes": [0, 1]}}],
+    "nodes": [
+        {{"name": "skinned", "mesh": 0, "skin": 0}},
+        {{"name": "root_joint", "children": [2]}},
+        {{"name": "child_joint", "translation": [0.0, 1.0, 0.0]}}
+    ],
+    "skins": [{{"joints": [1, 2], "inverseBindMatrices": 4}}],
+    "animations": [{{
+        "name": "move",
+        "samplers": [{{"input": 5, "output": 6, "interpolation": "LINEAR"}}],
+        "channels": [{{"sampler": 0, "target": {{"node": 1, "path": "translation"}}}}]
+    }}],
+    "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "JOINTS_0": 1, "WEIGHTS_0": 2}}, "indices": 3}}]}}],
+    "buffers": [{{"byteLength": {}, "uri": "data:application/octet-stream;base64,{}"}}],
+    "bufferViews": [{}],
+    "accessors": [
+        {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0.0, 0.0, 0.0], "max": [1.0, 2.0, 0.0]}},
+        {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "VEC4"}},
+        {{"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC4"}},
+        {{"bufferView": 3, "componentType": 5123, "count": 3, "type": "SCALAR"}},
+        {{"bufferView": 4, "componentType": 5126, "count": 2, "type": "MAT4"}},
+        {{"bufferView": 5, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0.0], "max": [1.0]}},
+        {{"bufferView": 6, "componentType": 5126, "count": 2, "type": "VEC3"}}
+    ]
+}}"#,
+            buffer.len(),
+            base64::encode(&buffer),
+            views.join(", ")
+        )
+    }
+
+    #[test]
+    fn skins_and_animations() {
+        let scene = load_gltf_scene(skinned_gltf().as_bytes()).unwrap();
+        let skinned_mesh = scene.skinned_meshes[0].as_ref().unwrap();
+        assert_eq!([1, 0, 0, 0], skinned_mesh.vertices[2].joints);
+        assert_eq!([1.0, 0.0, 0.0, 0.0], skinned_mesh.vertices[2].weights);
+        assert_eq!(vec![1, 2], scene.skins[0].joints);
+        assert_eq!(1.0, scene.animations[0].duration);
+
+        let mut entities = Entities::new();
+        let handles = scene.spawn(&mut entities, &[0u32]).unwrap();
+        let player = scene.animation_player(&handles).unwrap();
+        assert_eq!(Some(0), player.find_clip("move"));
+        entities.insert(&handles[1], player);
+        animate(&mut entities, 0.5);
+        propagate_transforms(&mut entities);
+        let matrices = joint_matrices(&entities, &handles[0]).unwrap();
+        let moved = Mat4::from_translation(Vec3::new(1.0, 0.0, 0.0));
+        assert!(matrices[0].abs_diff_eq(moved, 0.0001));
+        assert!(matrices[1].abs_diff_eq(moved, 0.0001));
+        // the vertex only follows the second joint, so it moves along with it
+        let vertex = &skinned_mesh.vertices[2];
+        let (position, normal) = skin_vertex(&matrices, vertex);
+        assert!(position.abs_diff_eq(Vec3::from(vertex.position) + Vec3::new(1.0, 0.0, 0.0), 0.0001));
+        assert!(normal.abs_diff_eq(Vec3::from(vertex.normal), 0.0001));
+    }
+
+    #[test]
+    fn mismatched_keyframe_counts_are_errors() {
+        // cubic splines need three values per keyframe, the channel only has one
+        let gltf = skinned_gltf().replace(r#""interpolation": "LINEAR""#, r#""interpolation": "CUBICSPLINE""#);
+        assert!(matches!(
+            load_gltf_scene(gltf.as_bytes()),
+            Err(MeshLoadError::KeyframeCountMismatch)
+        ));
     }
 }
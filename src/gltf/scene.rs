@@ -0,0 +1,100 @@
+use crate::{
+    animation::{AnimationClip, AnimationPlayer, Skin},
+    entity::{Entities, Entity},
+    gltf::MeshLoadError,
+    mesh::{MeshData, SkinnedMeshData},
+    registry::Handle,
+    transform::Transform,
+};
+use glam::Mat4;
+use std::sync::Arc;
+
+// nodes without a mesh, e.g. cameras, lights or empty groups, are kept for their transform
+pub struct GltfNode {
+    pub name: Option<String>,
+    pub transform: Transform,
+    pub mesh: Option<usize>,
+    pub skin: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+// joints are node indices
+pub struct GltfSkin {
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+// skinned_meshes has an entry for every mesh, animation channels target node indices
+pub struct GltfScene {
+    pub nodes: Vec<GltfNode>,
+    pub meshes: Vec<MeshData>,
+    pub skinned_meshes: Vec<Option<SkinnedMeshData>>,
+    pub skins: Vec<GltfSkin>,
+    pub animations: Vec<AnimationClip>,
+    pub images: Vec<Arc<image::RgbaImage>>,
+    pub roots: Vec<usize>,
+}
+
+impl GltfScene {
+    // mesh_handles has a handle for every mesh in meshes, returns the entities in the order of nodes
+    pub fn spawn<M: Clone + 'static>(
+        &self,
+        entities: &mut Entities,
+        mesh_handles: &[M],
+    ) -> Result<Vec<Handle<Entity>>, MeshLoadError> {
+        if self
+            .nodes
+            .iter()
+            .filter_map(|node| node.mesh)
+            .any(|mesh| mesh >= mesh_handles.len())
+        {
+            return Err(MeshLoadError::MissingMeshHandle);
+        }
+        let handles = self
+            .nodes
+            .iter()
+            .map(|node| {
+                let entity = entities.spawn().with(node.transform.clone()).id();
+                if let Some(mesh) = node.mesh {
+                    entities.insert(&entity, mesh_handles[mesh].clone());
+                }
+                entity
+            })
+            .collect::<Vec<_>>();
+        for (index, node) in self.nodes.iter().enumerate() {
+            if let Some(skin) = node.skin.and_then(|skin| self.skins.get(skin)) {
+                let skin = Skin {
+                    joints: skin.joints.iter().map(|joint| handles[*joint]).collect(),
+                    inverse_bind_matrices: skin.inverse_bind_matrices.clone(),
+                };
+                entities.insert(&handles[index], skin);
+            }
+        }
+        for (index, node) in self.nodes.iter().enumerate() {
+            for child in &node.children {
+                entities.set_parent(&handles[*child], &handles[index]);
+            }
+        }
+        Ok(handles)
+    }
+
+    // handles are the entities returned by spawn
+    pub fn animation_player(&self, handles: &[Handle<Entity>]) -> Option<AnimationPlayer> {
+        if self.animations.is_empty() {
+            return None;
+        }
+        Some(AnimationPlayer::new(
+            self.animations.clone(),
+            handles.to_vec(),
+            self.nodes.iter().map(|node| node.transform.clone()).collect(),
+        ))
+    }
+
+    pub fn named_mesh(&self, name: &str) -> Option<&MeshData> {
+        self.nodes
+            .iter()
+            .find(|node| node.name.as_deref() == Some(name))
+            .and_then(|node| node.mesh)
+            .map(|mesh| &self.meshes[mesh])
+    }
+}
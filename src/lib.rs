@@ -1,3 +1,4 @@
+pub mod animation;
 pub mod cameras;
 pub mod controllers;
 pub mod entity;
@@ -8,6 +9,7 @@ pub mod mesh;
 pub mod physics;
 pub mod registry;
 pub mod renderer;
+pub mod scene;
 pub mod transform;
 pub mod winit_impl;
 pub mod world;
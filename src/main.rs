@@ -5,23 +5,72 @@ use winit::{
     window::WindowBuilder,
 };
 use xp_vox_engine::{
-    cameras::FollowCamera,
+    animation::{animate, animate_flipbooks},
+    cameras::{FollowCamera, StaticCamera},
     controllers::{CameraController, CharacterController},
-    entity::Entity,
-    input::{keyboard_state_from_events, InputAll},
-    mesh::{Cube, IcoSphere, MeshData},
-    physics::{Body, BodyStatus, CollisionShape, Cuboid, Physics, Sphere},
-    registry::Registry,
+    entity::{propagate_transforms, Entities},
+    input::{keyboard_state_from_events, InputAll, KeyCode},
+    mesh::{Cube, MeshData},
+    physics::Physics,
+    registry::{AssetHandle, Assets},
     renderer,
-    renderer::{BindGroup, DirectionalProperties, Light, LightBindGroup, Mesh, PointProperties, SpotProperties},
+    renderer::{apply_sky, BindGroup, Camera, DebugDraw, Frame, Mesh, RenderGraph, SceneView, TimeOfDay},
+    scene::{load_meshes, load_scene, save_scene, Scene, SceneCamera},
     transform::Transform,
     winit_impl,
     world::World,
 };
 
+const SCENE_PATH: &str = "res/scenes/main.ron";
+const SAVED_SCENE_PATH: &str = "res/scenes/saved.ron";
+const SCREENSHOT_DIRECTORY: &str = "screenshots";
+// status messages are shown above the character for a few seconds
+const STATUS_DURATION: std::time::Duration = std::time::Duration::from_secs(3);
+const STATUS_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const ERROR_COLOR: [f32; 4] = [1.0, 0.3, 0.3, 1.0];
+
 #[derive(Debug)]
 pub enum GameError {}
 
+struct StatusMessage {
+    text: String,
+    color: [f32; 4],
+    shown_at: std::time::Instant,
+}
+
+impl StatusMessage {
+    fn info(text: String) -> Self {
+        Self {
+            text,
+            color: STATUS_COLOR,
+            shown_at: std::time::Instant::now(),
+        }
+    }
+
+    // errors are written to stderr as well, the message on screen only lasts a few seconds
+    fn error(text: String) -> Self {
+        eprintln!("{}", text);
+        Self {
+            text,
+            color: ERROR_COLOR,
+            shown_at: std::time::Instant::now(),
+        }
+    }
+}
+
+// returns the path of the saved screenshot
+fn save_screenshot(renderer: &renderer::Renderer, target: &renderer::OffscreenTarget) -> Result<String, String> {
+    let image = target.read(renderer).map_err(|e| format!("{:?}", e))?;
+    std::fs::create_dir_all(SCREENSHOT_DIRECTORY).map_err(|e| e.to_string())?;
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_secs();
+    let path = format!("{}/screenshot-{}.png", SCREENSHOT_DIRECTORY, seconds);
+    image.save(&path).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
 fn main() -> Result<(), GameError> {
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
@@ -29,69 +78,55 @@ fn main() -> Result<(), GameError> {
         .expect("Could not create window");
     let mut renderer =
         futures::executor::block_on(renderer::Renderer::new(&window)).expect("Could not create renderer");
-    let pipeline_bindgroup = BindGroup::new(&renderer);
-    let pipeline = futures::executor::block_on(renderer::Pipeline::new(&renderer, &pipeline_bindgroup))
-        .expect("Could not create pipeline");
-    let light_pipeline_bindgroup = LightBindGroup::new(&renderer);
-    let pipeline_light =
-        futures::executor::block_on(renderer::LightPipeline::new(&renderer, &light_pipeline_bindgroup))
-            .expect("Could not create pipeline light");
-
     let mut physics = Physics::default();
-    let mut meshes = Registry::new();
-    let mut lights = Registry::new();
-    let mut entities = Registry::new();
+    let mut meshes = Assets::new();
+    let mut entities = Entities::new();
     let mut world = World::new();
+    // the fog hides where the chunks end
+    let fog = &mut renderer.settings.post_process.fog;
+    fog.end = world.view_distance();
+    fog.start = 0.6 * fog.end;
     let light_mesh_handle = meshes.add(Mesh::from_mesh_data(&renderer, MeshData::from(Cube::new(0.25))));
-    lights.add(Light::Directional(DirectionalProperties::new([-1.0, -0.5, -1.0, 1.0])));
-
-    lights.add(Light::Spot(SpotProperties::new(
-        [0.0, 4.0, 0.0, 1.0],
-        [0.0, -1.0, 0.0, 1.0],
-    )));
-    lights.add(Light::Spot(SpotProperties::new(
-        [8.0, 4.0, 0.0, 1.0],
-        [0.0, -1.0, 0.0, 1.0],
-    )));
-    lights.add(Light::Point(PointProperties::new([8.0, 4.0, 8.0, 1.0])));
-    lights.add(Light::Point(PointProperties::new([-8.0, 4.0, 8.0, 1.0])));
-
-    let cube = entities.add(Entity {
-        mesh_handle: meshes.add(Mesh::from_mesh_data(&renderer, MeshData::from(Cube::new(1.0)))),
-        collision_shape: Some(CollisionShape {
-            body_status: BodyStatus::Static,
-            body: Body::Cuboid(Cuboid {
-                half_extent_x: 0.5,
-                half_extent_y: 0.5,
-                half_extent_z: 0.5,
-            }),
-        }),
-        transform: Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)),
-    });
+    let mut bind_group = BindGroup::new(&renderer);
+    let mut render_graph =
+        futures::executor::block_on(RenderGraph::standard(&renderer, &bind_group, light_mesh_handle))
+            .expect("Could not create render graph");
+    let scene = load_scene(SCENE_PATH).expect("Could not load scene");
+    let scene_entities = scene.instantiate(&mut entities).expect("Could not instantiate scene");
+    load_meshes(&mut entities, &renderer, &mut meshes).expect("Could not load scene meshes");
+    for entity in &scene_entities {
+        physics
+            .register(*entity, &entities, &meshes)
+            .expect("Could not register scene collider");
+    }
+    let character = scene_entities[scene.character.expect("Scene has no character")];
+    physics.register_character(character);
+    propagate_transforms(&mut entities);
 
-    physics.register(cube, &entities);
-
-    let character = entities.add(Entity {
-        mesh_handle: meshes.add(Mesh::from_mesh_data(&renderer, MeshData::from(IcoSphere::new(0.5)))),
-        collision_shape: Some(CollisionShape {
-            body_status: BodyStatus::Dynamic,
-            body: Body::Sphere(Sphere { radius: 0.5 }),
-        }),
-        transform: Transform::from_translation(Vec3::new(0.0, 10.0, 4.0)),
-    });
-    physics.register(character.clone(), &entities);
-    physics.register_character(character.clone());
-
-    let mut follow_camera = FollowCamera::new(
-        entities.get(&character).unwrap().transform.clone(),
-        renderer.swap_chain_descriptor.width as f32 / renderer.swap_chain_descriptor.height as f32,
-    );
+    let aspect = renderer.swap_chain_descriptor.width as f32 / renderer.swap_chain_descriptor.height as f32;
+    let mut follow_camera = FollowCamera::new(entities.get::<Transform>(&character).unwrap().clone(), aspect);
+    // the follow camera is used when the scene has no static one
+    let mut static_camera = None;
+    match scene.camera.clone() {
+        Some(SceneCamera::Follow {
+            follow_angle,
+            follow_distance,
+        }) => follow_camera.set_follow_angle_distance(follow_angle, follow_distance),
+        Some(SceneCamera::Static { position, target }) => {
+            static_camera = Some(StaticCamera::new(position.into(), target.into(), aspect))
+        }
+        None => {}
+    }
 
     let mut input_all = InputAll::default();
     let mut character_controller = CharacterController::default();
     let mut camera_controller = CameraController::default();
+    let mut render_stats = renderer::RenderStats::default();
+    let mut time_of_day = TimeOfDay::default();
+    let mut debug_draw = DebugDraw::new();
     let start_time = std::time::Instant::now();
     let mut steps_taken = 0;
+    let mut status: Option<StatusMessage> = None;
     event_loop.run(move |event, _, control_flow| {
         *control_flow = winit::event_loop::ControlFlow::Poll;
         match event {
@@ -100,52 +135,129 @@ fn main() -> Result<(), GameError> {
                 let steps = steps_since_start - steps_taken;
                 keyboard_state_from_events(&input_all.keyboard_events, &mut input_all.keyboard_input);
                 character_controller.keyboard(&input_all.keyboard_input);
+                if input_all.keyboard_input.just_pressed(KeyCode::F5) {
+                    let camera = match &static_camera {
+                        Some(_) => scene.camera.clone(),
+                        None => Some(SceneCamera::Follow {
+                            follow_angle: follow_camera.follow_angle(),
+                            follow_distance: follow_camera.follow_distance(),
+                        }),
+                    };
+                    let scene = Scene::from_entities(&entities, Some(&character), camera);
+                    status = Some(match save_scene(SAVED_SCENE_PATH, &scene) {
+                        Ok(()) => StatusMessage::info(format!("Saved scene {}", SAVED_SCENE_PATH)),
+                        Err(e) => StatusMessage::error(format!("Could not save scene: {:?}", e)),
+                    });
+                }
+                if input_all.keyboard_input.just_pressed(KeyCode::F7) {
+                    status = Some(StatusMessage::info(format!(
+                        "drawn: {}, culled: {}",
+                        render_stats.drawn, render_stats.culled
+                    )));
+                }
+                let take_screenshot = input_all.keyboard_input.just_pressed(KeyCode::F8);
+                let debug = &mut renderer.settings.debug;
+                if input_all.keyboard_input.just_pressed(KeyCode::F1) {
+                    debug.colliders = !debug.colliders;
+                }
+                if input_all.keyboard_input.just_pressed(KeyCode::F2) {
+                    debug.chunk_grid = !debug.chunk_grid;
+                }
+                if input_all.keyboard_input.just_pressed(KeyCode::F3) {
+                    debug.frusta = !debug.frusta;
+                }
+                if input_all.keyboard_input.just_pressed(KeyCode::F4) {
+                    debug.light_volumes = !debug.light_volumes;
+                }
+                if input_all.keyboard_input.just_pressed(KeyCode::F6) {
+                    renderer.settings.shadows.enabled = !renderer.settings.shadows.enabled;
+                }
+                let post_process = &mut renderer.settings.post_process;
+                if input_all.keyboard_input.just_pressed(KeyCode::F9) {
+                    post_process.bloom.enabled = !post_process.bloom.enabled;
+                }
+                if input_all.keyboard_input.just_pressed(KeyCode::F10) {
+                    post_process.fog.enabled = !post_process.fog.enabled;
+                }
+                if input_all.keyboard_input.just_pressed(KeyCode::F11) {
+                    post_process.fxaa = !post_process.fxaa;
+                }
+                if input_all.keyboard_input.just_pressed(KeyCode::F12) {
+                    post_process.tonemapping = !post_process.tonemapping;
+                }
                 camera_controller.mouse_handling(&input_all.mouse_wheel_events, &input_all.mouse_motion_events);
                 follow_camera.handle_camera_controller(&camera_controller);
                 for _ in 0..steps {
                     physics.step(&mut entities, &character_controller);
                 }
                 steps_taken = steps_since_start;
-                follow_camera.follow(entities.get(&character).unwrap().transform.clone());
+                animate(&mut entities, steps as f32 / 60.0);
+                time_of_day.advance(steps as f32 / 60.0);
+                let sky = time_of_day.sky();
+                apply_sky(&sky, &mut entities);
+                renderer.settings.post_process.fog.color = sky.horizon_color;
+                animate_flipbooks::<AssetHandle<Mesh>>(&mut entities, steps as f32 / 60.0);
+                propagate_transforms(&mut entities);
+                follow_camera.follow(entities.get::<Transform>(&character).unwrap().clone());
                 input_all.clear_events();
-                let player_position = entities.get(&character).unwrap().transform.clone().translation;
+                let player_position = entities.get::<Transform>(&character).unwrap().translation;
                 let before_generate = std::time::Instant::now();
-                world.update(
+                if let Err(e) = world.update(
                     [player_position[0], player_position[1], player_position[2]],
                     &mut renderer,
                     &mut physics,
-                    &mut meshes,
-                );
+                    &mut bind_group,
+                ) {
+                    status = Some(StatusMessage::error(format!("Could not draw every chunk yet: {:?}", e)));
+                }
+                meshes.update();
+                debug_draw.clear();
+                if renderer.settings.debug.colliders {
+                    physics.draw_colliders(&mut debug_draw);
+                }
+                if let Some(status) = &status {
+                    if status.shown_at.elapsed() < STATUS_DURATION {
+                        debug_draw.text(
+                            player_position + Vec3::new(0.0, 1.5, 0.0),
+                            &status.text,
+                            0.1,
+                            status.color,
+                        );
+                    }
+                }
 
                 let after_generate = std::time::Instant::now();
                 let before_render = std::time::Instant::now();
-                let target = &renderer
-                    .swap_chain
+                let frame = renderer
                     .get_current_frame()
-                    .expect("Could not get next frame texture_view")
-                    .output
-                    .view;
-
-                pipeline.render(
-                    &world,
-                    &entities,
-                    &mut meshes,
-                    &lights,
-                    &pipeline_bindgroup,
-                    &follow_camera,
-                    &mut renderer,
-                    target,
-                    player_position.into(),
-                );
-                pipeline_light.render(
-                    &light_mesh_handle,
-                    &lights,
-                    &light_pipeline_bindgroup,
-                    &follow_camera,
-                    &mut meshes,
-                    &mut renderer,
-                    target,
-                );
+                    .expect("Could not get next frame texture_view");
+                let mut render_to = |target: &wgpu::TextureView| {
+                    let scene = SceneView {
+                        world: &world,
+                        entities: &entities,
+                        meshes: &meshes,
+                        camera: match &static_camera {
+                            Some(static_camera) => static_camera as &dyn Camera,
+                            None => &follow_camera,
+                        },
+                        position: player_position.into(),
+                        sky,
+                        debug: &debug_draw,
+                    };
+                    let mut frame = Frame::new(&renderer, target, scene, &mut bind_group);
+                    render_graph.execute(&mut frame);
+                    frame.stats
+                };
+                render_stats = render_to(&frame.output.view);
+                if take_screenshot {
+                    // the frame is rendered again into a texture that can be read back
+                    let screenshot = renderer::OffscreenTarget::new(&renderer);
+                    render_to(&screenshot.view);
+                    status = Some(match save_screenshot(&renderer, &screenshot) {
+                        Ok(path) => StatusMessage::info(format!("Saved screenshot {}", path)),
+                        Err(e) => StatusMessage::error(format!("Could not save screenshot: {}", e)),
+                    });
+                }
                 let after_render = std::time::Instant::now();
                 /*println!(
                     "render-time: {}, generate_time: {}",
@@ -162,10 +274,16 @@ fn main() -> Result<(), GameError> {
             } if window_id == window.id() => match window_event {
                 WindowEvent::Resized(size) => {
                     follow_camera.set_aspect_ratio(size.width as f32 / size.height as f32);
+                    if let Some(static_camera) = &mut static_camera {
+                        static_camera.set_aspect_ratio(size.width as f32 / size.height as f32);
+                    }
                     futures::executor::block_on(renderer.resize(size.width, size.height));
                 }
                 WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
                     follow_camera.set_aspect_ratio(new_inner_size.width as f32 / new_inner_size.height as f32);
+                    if let Some(static_camera) = &mut static_camera {
+                        static_camera.set_aspect_ratio(new_inner_size.width as f32 / new_inner_size.height as f32);
+                    }
                     futures::executor::block_on(renderer.resize(new_inner_size.width, new_inner_size.height));
                 }
                 WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
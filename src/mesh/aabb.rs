@@ -0,0 +1,83 @@
+use glam::{Mat4, Vec3};
+
+// axis aligned bounding box
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    // an empty iterator gives a box of zero size at the origin
+    pub fn from_points(mut points: impl Iterator<Item = Vec3>) -> Self {
+        match points.next() {
+            Some(first) => points.fold(Self::new(first, first), |aabb, point| {
+                Self::new(aabb.min.min(point), aabb.max.max(point))
+            }),
+            None => Self::new(Vec3::zero(), Vec3::zero()),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    // box around the transformed corners, a rotated box grows
+    pub fn transformed(&self, matrix: &Mat4) -> Self {
+        Self::from_points(self.corners().iter().map(|corner| matrix.transform_point3(*corner)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mesh::Aabb;
+    use glam::{Mat4, Quat, Vec3};
+
+    #[test]
+    fn from_points_and_transformed() {
+        let aabb = Aabb::from_points(
+            [
+                Vec3::new(1.0, -2.0, 0.5),
+                Vec3::new(-1.0, 2.0, 0.0),
+                Vec3::new(0.0, 0.0, -0.5),
+            ]
+            .iter()
+            .copied(),
+        );
+        assert_eq!(Aabb::new(Vec3::new(-1.0, -2.0, -0.5), Vec3::new(1.0, 2.0, 0.5)), aabb);
+        assert_eq!(Vec3::zero(), aabb.center());
+        assert_eq!(Vec3::new(1.0, 2.0, 0.5), aabb.half_extents());
+
+        let moved = aabb.transformed(&Mat4::from_scale_rotation_translation(
+            Vec3::new(2.0, 2.0, 2.0),
+            Quat::identity(),
+            Vec3::new(10.0, 0.0, 0.0),
+        ));
+        assert_eq!(Aabb::new(Vec3::new(8.0, -4.0, -1.0), Vec3::new(12.0, 4.0, 1.0)), moved);
+
+        let rotated = aabb.transformed(&Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        assert!((rotated.half_extents() - Vec3::new(2.0, 1.0, 0.5)).abs().max_element() < 1e-5);
+    }
+}
@@ -1,14 +1,103 @@
 use crate::{
     generators::{Height, Zero},
-    mesh::Vertex,
+    mesh::{Aabb, SkinnedVertex, Vertex, VoxelVertex},
 };
 use glam::Vec3;
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
+// uvs and tangents are optional channels with one entry per vertex
 #[derive(Clone)]
 pub struct MeshData {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
+    pub uvs: Option<Vec<[f32; 2]>>,
+    pub tangents: Option<Vec<[f32; 4]>>,
+    // multiplied with the vertex colors, only meshes with uvs have one
+    pub base_color_texture: Option<Arc<image::RgbaImage>>,
+}
+
+impl MeshData {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        Self {
+            vertices,
+            indices,
+            uvs: None,
+            tangents: None,
+            base_color_texture: None,
+        }
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(self.vertices.iter().map(|v| Vec3::from(v.position)))
+    }
+
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.vertices.as_slice())
+            + std::mem::size_of_val(self.indices.as_slice())
+            + self.uvs.as_ref().map_or(0, |uvs| std::mem::size_of_val(uvs.as_slice()))
+            + self
+                .tangents
+                .as_ref()
+                .map_or(0, |tangents| std::mem::size_of_val(tangents.as_slice()))
+    }
+}
+
+#[derive(Clone)]
+pub struct VoxelMeshData {
+    pub vertices: Vec<VoxelVertex>,
+    pub indices: Vec<u32>,
+    // the indices after these are of transparent faces
+    pub nr_of_opaque_indices: usize,
+}
+
+impl VoxelMeshData {
+    // indices fit in 16 bits for meshes with at most 65536 vertices
+    pub fn has_short_indices(&self) -> bool {
+        self.vertices.len() <= u16::MAX as usize + 1
+    }
+
+    // in voxels like the positions
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(
+            self.vertices
+                .iter()
+                .map(|v| Vec3::new(v.position[0] as f32, v.position[1] as f32, v.position[2] as f32)),
+        )
+    }
+
+    // as uploaded by voxel meshes and the chunk buffers, with 16 bit indices when they fit
+    pub fn size_in_bytes(&self) -> usize {
+        let index_size = if self.has_short_indices() {
+            std::mem::size_of::<u16>()
+        } else {
+            std::mem::size_of::<u32>()
+        };
+        std::mem::size_of_val(self.vertices.as_slice()) + self.indices.len() * index_size
+    }
+
+    pub fn positions(&self, voxel_size: f32) -> Vec<[f32; 3]> {
+        self.vertices.iter().map(|v| v.to_vertex(voxel_size).position).collect()
+    }
+
+    pub fn to_mesh_data(&self, voxel_size: f32) -> MeshData {
+        MeshData::new(
+            self.vertices.iter().map(|v| v.to_vertex(voxel_size)).collect(),
+            self.indices.clone(),
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct SkinnedMeshData {
+    pub vertices: Vec<SkinnedVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl SkinnedMeshData {
+    // bounds of the bind pose
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(self.vertices.iter().map(|v| Vec3::from(v.position)))
+    }
 }
 
 pub struct Plane {
@@ -120,7 +209,7 @@ impl From<Plane> for MeshData {
                 index_count += 6;
             }
         }
-        Self { vertices, indices }
+        Self::new(vertices, indices)
     }
 }
 
@@ -195,7 +284,7 @@ impl From<Cube> for MeshData {
             0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28,
             29, 30, 31, 32, 33, 34, 35,
         ]);
-        Self { vertices, indices }
+        Self::new(vertices, indices)
     }
 }
 
@@ -318,7 +407,7 @@ impl From<IcoSphere> for MeshData {
             ));
             indices.extend((0..3).into_iter().map(|i| count + i));
         }
-        Self { vertices, indices }
+        Self::new(vertices, indices)
     }
 }
 
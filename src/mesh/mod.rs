@@ -1,5 +1,7 @@
+mod aabb;
 mod mesh_data;
 mod vertex;
 
-pub use mesh_data::{triangle_normal, Cube, IcoSphere, MeshData, Plane};
-pub use vertex::Vertex;
+pub use aabb::Aabb;
+pub use mesh_data::{triangle_normal, Cube, IcoSphere, MeshData, Plane, SkinnedMeshData, VoxelMeshData};
+pub use vertex::{SkinnedVertex, Vertex, VoxelVertex, VOXEL_AO_MAX, VOXEL_EMISSION_MAX, VOXEL_NORMALS, VOXEL_TRANSLUCENCY_MAX};
@@ -15,3 +15,125 @@ impl Vertex {
         }
     }
 }
+
+// vertex of a skinned mesh, every vertex is influenced by up to four joints of the skin
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct SkinnedVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub color: [f32; 3],
+    pub joints: [u32; 4],
+    pub weights: [f32; 4],
+}
+
+impl SkinnedVertex {
+    pub fn new(vertex: Vertex, joints: [u32; 4], weights: [f32; 4]) -> Self {
+        Self {
+            position: vertex.position,
+            normal: vertex.normal,
+            color: vertex.color,
+            joints,
+            weights,
+        }
+    }
+}
+
+// outward normals of voxel faces, a voxel vertex stores an index into this table
+pub const VOXEL_NORMALS: [[f32; 3]; 6] = [
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+];
+
+// fully lit vertices have an ambient occlusion of 3, vertices in a corner between two voxels 0
+pub const VOXEL_AO_MAX: u8 = 3;
+
+// alpha is stored in 3 bits as a translucency, 0 is opaque and 7 the most transparent
+pub const VOXEL_TRANSLUCENCY_MAX: u8 = 7;
+
+// emission is a multiple of the voxel color, stored in 8 bits up to this value
+pub const VOXEL_EMISSION_MAX: f32 = 8.0;
+
+// compact vertex of a voxel mesh, positions are in voxels
+// position[3] packs the normal index in bits 0..3, the ambient occlusion in bits 3..5, the translucency in bits 5..8
+// and the emission in bits 8..16
+// color holds the rgb of the voxel and its palette index
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VoxelVertex {
+    pub position: [u16; 4],
+    pub color: [u8; 4],
+}
+
+impl VoxelVertex {
+    pub fn new(position: [u16; 3], normal: u8, ao: u8, color: [f32; 3], palette_index: u8) -> Self {
+        assert!((normal as usize) < VOXEL_NORMALS.len());
+        assert!(ao <= VOXEL_AO_MAX);
+        let to_u8 = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Self {
+            position: [position[0], position[1], position[2], normal as u16 | (ao as u16) << 3],
+            color: [to_u8(color[0]), to_u8(color[1]), to_u8(color[2]), palette_index],
+        }
+    }
+
+    pub fn with_emission(mut self, emission: f32) -> Self {
+        let level = (emission.clamp(0.0, VOXEL_EMISSION_MAX) / VOXEL_EMISSION_MAX * 255.0).round() as u16;
+        self.position[3] = self.position[3] & 0xFF | level << 8;
+        self
+    }
+
+    pub fn with_alpha(mut self, alpha: f32) -> Self {
+        let levels = VOXEL_TRANSLUCENCY_MAX as f32 + 1.0;
+        let translucency = ((1.0 - alpha) * levels)
+            .round()
+            .clamp(0.0, VOXEL_TRANSLUCENCY_MAX as f32) as u16;
+        self.position[3] = self.position[3] & !0xE0 | translucency << 5;
+        self
+    }
+
+    pub fn normal_index(&self) -> u8 {
+        (self.position[3] & 0x7) as u8
+    }
+
+    pub fn normal(&self) -> [f32; 3] {
+        VOXEL_NORMALS[self.normal_index() as usize]
+    }
+
+    pub fn ao(&self) -> u8 {
+        (self.position[3] >> 3 & 0x3) as u8
+    }
+
+    pub fn alpha(&self) -> f32 {
+        1.0 - (self.position[3] >> 5 & 0x7) as f32 / (VOXEL_TRANSLUCENCY_MAX as f32 + 1.0)
+    }
+
+    pub fn emission(&self) -> f32 {
+        (self.position[3] >> 8) as f32 / 255.0 * VOXEL_EMISSION_MAX
+    }
+
+    pub fn palette_index(&self) -> u8 {
+        self.color[3]
+    }
+
+    // ambient occlusion is baked into the color, the voxel shader uses the same factor
+    pub fn to_vertex(&self, voxel_size: f32) -> Vertex {
+        let ao = 0.4 + 0.6 * self.ao() as f32 / VOXEL_AO_MAX as f32;
+        Vertex {
+            position: [
+                self.position[0] as f32 * voxel_size,
+                self.position[1] as f32 * voxel_size,
+                self.position[2] as f32 * voxel_size,
+            ],
+            normal: self.normal(),
+            color: [
+                self.color[0] as f32 / 255.0 * ao,
+                self.color[1] as f32 / 255.0 * ao,
+                self.color[2] as f32 / 255.0 * ao,
+            ],
+        }
+    }
+}
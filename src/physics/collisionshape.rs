@@ -1,29 +1,170 @@
-#[derive(Clone)]
+use crate::{mesh::MeshData, renderer::Mesh, transform::Transform};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
 pub enum BodyStatus {
     Static,
     Dynamic,
+    // moved by its entity transform instead of by the simulation
+    Kinematic,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Cuboid {
     pub half_extent_x: f32,
     pub half_extent_y: f32,
     pub half_extent_z: f32,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Sphere {
     pub radius: f32,
 }
 
-#[derive(Clone)]
+// capsule and cylinder are aligned with the y axis
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Capsule {
+    pub half_height: f32,
+    pub radius: f32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Cylinder {
+    pub half_height: f32,
+    pub radius: f32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ConvexHull {
+    pub points: Vec<[f32; 3]>,
+}
+
+impl From<&MeshData> for ConvexHull {
+    fn from(mesh_data: &MeshData) -> Self {
+        Self {
+            points: mesh_data.vertices.iter().map(|v| v.position).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TriMesh {
+    pub vertices: Vec<[f32; 3]>,
+    pub indices: Vec<[u32; 3]>,
+}
+
+impl From<&MeshData> for TriMesh {
+    fn from(mesh_data: &MeshData) -> Self {
+        Self {
+            vertices: mesh_data.vertices.iter().map(|v| v.position).collect(),
+            indices: mesh_data.indices.chunks(3).map(|v| [v[0], v[1], v[2]]).collect(),
+        }
+    }
+}
+
+impl From<&Mesh> for TriMesh {
+    fn from(mesh: &Mesh) -> Self {
+        Self {
+            vertices: mesh.positions.clone(),
+            indices: mesh.indices.chunks(3).map(|v| [v[0], v[1], v[2]]).collect(),
+        }
+    }
+}
+
+// scale of the local transforms is ignored, only translation and rotation are used
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Compound {
+    pub shapes: Vec<(Transform, Body)>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub enum Body {
     Cuboid(Cuboid),
     Sphere(Sphere),
+    Capsule(Capsule),
+    Cylinder(Cylinder),
+    ConvexHull(ConvexHull),
+    TriMesh(TriMesh),
+    // trimesh of the mesh the entity is drawn with, taken from the AssetHandle<Mesh> of the entity
+    Mesh,
+    Compound(Compound),
+}
+
+// a collider is part of the groups in memberships and only interacts with colliders in the groups of filter
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct CollisionGroups {
+    pub memberships: u16,
+    pub filter: u16,
+}
+
+impl CollisionGroups {
+    pub fn new(memberships: u16, filter: u16) -> Self {
+        Self { memberships, filter }
+    }
+
+    pub fn all() -> Self {
+        Self::new(u16::MAX, u16::MAX)
+    }
+}
+
+impl Default for CollisionGroups {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+// mass overrides density, the density is then derived from the volume of the body
+// bodies without volume, like trimeshes, get the mass directly
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct PhysicsMaterial {
+    pub friction: f32,
+    pub restitution: f32,
+    pub density: f32,
+    pub mass: Option<f32>,
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        Self {
+            friction: 0.0,
+            restitution: 0.0,
+            density: 1.0,
+            mass: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct BodyParameters {
+    pub linear_damping: f32,
+    pub angular_damping: f32,
+    pub gravity_scale: f32,
+    pub locked_rotations: [bool; 3],
+    pub lock_translations: bool,
+    pub ccd_enabled: bool,
+}
+
+impl Default for BodyParameters {
+    fn default() -> Self {
+        Self {
+            linear_damping: 0.0,
+            angular_damping: 0.0,
+            gravity_scale: 1.0,
+            locked_rotations: [false; 3],
+            lock_translations: false,
+            ccd_enabled: false,
+        }
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CollisionShape {
     pub body_status: BodyStatus,
     pub body: Body,
+    #[serde(default)]
+    pub collision_groups: CollisionGroups,
+    #[serde(default)]
+    pub material: PhysicsMaterial,
+    #[serde(default)]
+    pub body_parameters: BodyParameters,
 }
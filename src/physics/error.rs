@@ -0,0 +1,7 @@
+#[derive(Debug)]
+pub enum PhysicsError {
+    // the body has no volume or area to collide with, e.g. a convex hull of points on one plane
+    InvalidShape,
+    // a Body::Mesh needs an entity with a loaded mesh
+    MissingMesh,
+}
@@ -0,0 +1,53 @@
+use crate::transform::Transform;
+use glam::Vec3;
+
+#[derive(Clone, Copy)]
+pub struct JointMotor {
+    pub target_position: f32,
+    pub target_velocity: f32,
+    pub stiffness: f32,
+    pub damping: f32,
+}
+
+// anchors and axes are in the local space of the connected entities
+#[derive(Clone)]
+pub struct BallJoint {
+    pub anchor1: Vec3,
+    pub anchor2: Vec3,
+}
+
+#[derive(Clone)]
+pub struct FixedJoint {
+    pub anchor1: Transform,
+    pub anchor2: Transform,
+}
+
+#[derive(Clone)]
+pub struct PrismaticJoint {
+    pub anchor1: Vec3,
+    pub axis1: Vec3,
+    pub anchor2: Vec3,
+    pub axis2: Vec3,
+    pub limits: Option<[f32; 2]>,
+    pub motor: Option<JointMotor>,
+}
+
+// rapier has no angular limits, the limits are kept by a stiff motor that only engages outside of them.
+// the angles are in radians between -pi and pi
+#[derive(Clone)]
+pub struct RevoluteJoint {
+    pub anchor1: Vec3,
+    pub axis1: Vec3,
+    pub anchor2: Vec3,
+    pub axis2: Vec3,
+    pub limits: Option<[f32; 2]>,
+    pub motor: Option<JointMotor>,
+}
+
+#[derive(Clone)]
+pub enum Joint {
+    Ball(BallJoint),
+    Fixed(FixedJoint),
+    Prismatic(PrismaticJoint),
+    Revolute(RevoluteJoint),
+}
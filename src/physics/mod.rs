@@ -1,5 +1,14 @@
 mod collisionshape;
+mod error;
+mod joint;
 mod physics;
+mod query;
 
-pub use collisionshape::{Body, BodyStatus, CollisionShape, Cuboid, Sphere};
-pub use physics::{Physics, PhysicsHandle};
+pub use collisionshape::{
+    Body, BodyParameters, BodyStatus, Capsule, CollisionGroups, CollisionShape, Compound, ConvexHull, Cuboid, Cylinder,
+    PhysicsMaterial, Sphere, TriMesh,
+};
+pub use error::PhysicsError;
+pub use joint::{BallJoint, FixedJoint, Joint, JointMotor, PrismaticJoint, RevoluteJoint};
+pub use physics::{Physics, PhysicsHandle, PhysicsJointHandle};
+pub use query::{RayHit, ShapeHit};
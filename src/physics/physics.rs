@@ -1,26 +1,57 @@
 use crate::{
     controllers::CharacterController,
-    entity::Entity,
-    mesh::MeshData,
-    physics::collisionshape::{Body, BodyStatus},
-    registry::{Handle, Registry},
+    entity::{Entities, Entity, Parent},
+    mesh::Aabb,
+    physics::{
+        collisionshape::{Body, BodyStatus, CollisionGroups, CollisionShape, TriMesh},
+        error::PhysicsError,
+        joint::{Joint, JointMotor},
+        query::{RayHit, ShapeHit},
+    },
+    registry::{AssetHandle, Assets, Handle},
+    renderer::{DebugDraw, Mesh},
+    transform::{GlobalTransform, Transform},
 };
 use futures::StreamExt;
-use glam::Quat;
+use glam::{Mat4, Quat, Vec3};
 use rapier3d::{
-    dynamics::{CCDSolver, IntegrationParameters, JointSet, RigidBodyBuilder, RigidBodyHandle, RigidBodySet},
-    geometry::{BroadPhase, ColliderBuilder, ColliderHandle, ColliderSet, NarrowPhase},
-    na::Vector3,
-    pipeline::PhysicsPipeline,
+    dynamics::{
+        BallJoint, CCDSolver, FixedJoint, IntegrationParameters, JointHandle, JointParams, JointSet, PrismaticJoint,
+        RevoluteJoint, RigidBodyBuilder, RigidBodyHandle, RigidBodySet,
+    },
+    geometry::{
+        BroadPhase, ColliderBuilder, ColliderHandle, ColliderSet, InteractionGroups, NarrowPhase, Ray, Shape,
+        SharedShape,
+    },
+    math::{Isometry, Point},
+    na::{Quaternion, Translation3, Unit, UnitQuaternion, Vector3},
+    pipeline::{PhysicsPipeline, QueryPipeline},
 };
 use std::collections::HashMap;
 
+const STATIC_COLLIDER_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+const DYNAMIC_COLLIDER_COLOR: [f32; 4] = [0.2, 1.0, 0.4, 1.0];
+// the motor of a violated limit solves half of the angle error and stops the rotation in one step
+const REVOLUTE_LIMIT_STIFFNESS: f32 = 0.5;
+const REVOLUTE_LIMIT_DAMPING: f32 = 1.0;
+
 #[derive(Clone)]
 pub struct PhysicsHandle {
     r: RigidBodyHandle,
     c: ColliderHandle,
 }
 
+#[derive(Clone)]
+pub struct PhysicsJointHandle {
+    j: JointHandle,
+}
+
+// the motor of the joint is restored while the angle is within the limits
+struct RevoluteLimits {
+    limits: [f32; 2],
+    motor: Option<JointMotor>,
+}
+
 pub struct Physics {
     int_params: IntegrationParameters,
     pipeline: PhysicsPipeline,
@@ -30,8 +61,11 @@ pub struct Physics {
     colliders: ColliderSet,
     joints: JointSet,
     ccd_solver: CCDSolver,
-    physics_objects_dynamic: HashMap<u64, PhysicsHandle>,
+    query_pipeline: QueryPipeline,
+    physics_objects: HashMap<Handle<Entity>, PhysicsHandle>,
+    collider_entities: HashMap<ColliderHandle, Handle<Entity>>,
     character: Option<Handle<Entity>>,
+    revolute_limits: HashMap<JointHandle, RevoluteLimits>,
 }
 
 impl Default for Physics {
@@ -45,25 +79,31 @@ impl Default for Physics {
             colliders: ColliderSet::new(),
             joints: JointSet::new(),
             ccd_solver: CCDSolver::new(),
-            physics_objects_dynamic: HashMap::new(),
+            query_pipeline: QueryPipeline::new(),
+            physics_objects: HashMap::new(),
+            collider_entities: HashMap::new(),
             character: None,
+            revolute_limits: HashMap::new(),
         }
     }
 }
 
 impl Physics {
-    pub fn step(&mut self, entities: &mut Registry<Entity>, character_controller: &CharacterController) {
+    pub fn step(&mut self, entities: &mut Entities, character_controller: &CharacterController) {
         let step_time = 1.0 / 60.0;
-        if let Some(entity_handle) = self.character.clone() {
-            let entity = entities.get_mut(&entity_handle).unwrap();
-            entity.transform.rotation *= Quat::from_rotation_y(-character_controller.rotate * 0.02);
-            let new_velocity = entity.transform.forward() * character_controller.forward * 5.0;
-            if let Some(physics_object) = &mut self.physics_objects_dynamic.get(&entity_handle.id) {
+        self.remove_despawned(entities);
+        self.move_kinematic(entities);
+        if let Some(entity_handle) = self.character {
+            let transform = entities.get_mut::<Transform>(&entity_handle).unwrap();
+            transform.rotation *= Quat::from_rotation_y(-character_controller.rotate * 0.02);
+            let new_velocity = transform.forward() * character_controller.forward * 5.0;
+            if let Some(physics_object) = &mut self.physics_objects.get(&entity_handle) {
                 let rigid_body = self.bodies.get_mut(physics_object.r).unwrap();
                 let y = rigid_body.linvel().y;
                 rigid_body.set_linvel(Vector3::new(new_velocity.x, y, new_velocity.z), true);
             }
         }
+        self.apply_revolute_limits();
         self.pipeline.step(
             &Vector3::new(0.0, -9.81, 0.0),
             &self.int_params,
@@ -76,80 +116,609 @@ impl Physics {
             &(),
             &(),
         );
-        if let Some(entity_handle) = self.character.clone() {
-            let entity = entities.get_mut(&entity_handle).unwrap();
-            entity.transform.rotation *= Quat::from_rotation_y(-character_controller.rotate * 0.02);
-            entity.transform.translation += entity.transform.forward() * character_controller.forward * step_time * 5.0;
-            if let Some(physics_object) = &mut self.physics_objects_dynamic.get(&entity_handle.id) {
+        self.query_pipeline.update(&self.bodies, &self.colliders);
+        self.sync_dynamic(entities);
+        if let Some(entity_handle) = self.character {
+            let transform = entities.get_mut::<Transform>(&entity_handle).unwrap();
+            transform.rotation *= Quat::from_rotation_y(-character_controller.rotate * 0.02);
+            transform.translation += transform.forward() * character_controller.forward * step_time * 5.0;
+            if let Some(physics_object) = &mut self.physics_objects.get(&entity_handle) {
                 let rb = self.bodies.get(physics_object.r).unwrap();
-                let translation = rb.position().translation.clone();
-                entity.transform.translation.x = translation.x;
-                entity.transform.translation.y = translation.y;
-                entity.transform.translation.z = translation.z;
+                let translation = rb.position().translation;
+                transform.translation.x = translation.x;
+                transform.translation.y = translation.y;
+                transform.translation.z = translation.z;
             }
         }
     }
 
+    // bodies of entities that were despawned without Physics::despawn are cleaned up on the next step
+    fn remove_despawned(&mut self, entities: &Entities) {
+        let despawned = self
+            .physics_objects
+            .keys()
+            .copied()
+            .filter(|entity_handle| !entities.contains(entity_handle))
+            .collect::<Vec<_>>();
+        for entity_handle in despawned {
+            self.unregister(&entity_handle);
+        }
+    }
+
+    fn apply_revolute_limits(&mut self) {
+        let joints = &mut self.joints;
+        let bodies = &self.bodies;
+        self.revolute_limits.retain(|joint_handle, revolute_limits| {
+            let joint = match joints.get_mut(*joint_handle) {
+                Some(joint) => joint,
+                None => return false,
+            };
+            let (body1, body2) = match (bodies.get(joint.body1), bodies.get(joint.body2)) {
+                (Some(body1), Some(body2)) => (body1, body2),
+                _ => return false,
+            };
+            if let JointParams::RevoluteJoint(params) = &mut joint.params {
+                let angle = params.estimate_motor_angle(body1.position(), body2.position());
+                let [min, max] = revolute_limits.limits;
+                if angle < min || angle > max {
+                    params.configure_motor_position(
+                        angle.clamp(min, max),
+                        REVOLUTE_LIMIT_STIFFNESS,
+                        REVOLUTE_LIMIT_DAMPING,
+                    );
+                } else {
+                    configure_revolute_motor(params, revolute_limits.motor);
+                }
+            }
+            true
+        });
+    }
+
+    // the bodies are in world space, children get their transform relative to the parent back
+    fn sync_dynamic(&self, entities: &mut Entities) {
+        for (entity_handle, physics_handle) in &self.physics_objects {
+            if self.character == Some(*entity_handle) {
+                continue;
+            }
+            let position = match self.bodies.get(physics_handle.r) {
+                Some(rigid_body) if rigid_body.is_dynamic() => rigid_body.position(),
+                _ => continue,
+            };
+            let mut world = match world_transform(entities, entity_handle) {
+                Some(transform) => transform.clone(),
+                None => continue,
+            };
+            let (t, r) = (position.translation, position.rotation);
+            world.translation = Vec3::new(t.x, t.y, t.z);
+            world.rotation = Quat::from_xyzw(r.i, r.j, r.k, r.w);
+            let parent_transform = entities
+                .get::<Parent>(entity_handle)
+                .and_then(|parent| entities.get::<GlobalTransform>(&parent.0));
+            let local = match parent_transform {
+                Some(parent_transform) => parent_transform.0.inverse().mul_transform(world.clone()),
+                None => world.clone(),
+            };
+            if let Some(transform) = entities.get_mut::<Transform>(entity_handle) {
+                *transform = local;
+            }
+            if let Some(global_transform) = entities.get_mut::<GlobalTransform>(entity_handle) {
+                global_transform.0 = world;
+            }
+        }
+    }
+
+    fn move_kinematic(&mut self, entities: &Entities) {
+        for (entity_handle, physics_handle) in &self.physics_objects {
+            if let Some(rigid_body) = self.bodies.get_mut(physics_handle.r) {
+                if rigid_body.is_kinematic() {
+                    if let Some(transform) = world_transform(entities, entity_handle) {
+                        rigid_body.set_next_kinematic_position(isometry_from_transform(transform));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn unregister(&mut self, entity_handle: &Handle<Entity>) {
+        if let Some(physics_handle) = self.physics_objects.remove(entity_handle) {
+            self.remove_physics_handle(&physics_handle);
+        }
+        if let Some(character) = &self.character {
+            if character == entity_handle {
+                self.character = None;
+            }
+        }
+    }
+
+    // removes the body right away, so queries no longer hit the entity
+    pub fn despawn(&mut self, entity_handle: &Handle<Entity>, entities: &mut Entities) {
+        self.unregister(entity_handle);
+        entities.despawn(entity_handle);
+    }
+
+    pub fn is_registered(&self, entity_handle: &Handle<Entity>) -> bool {
+        self.physics_objects.contains_key(entity_handle)
+    }
+
     pub fn register_character(&mut self, entity_handle: Handle<Entity>) {
         self.character = Some(entity_handle);
     }
 
-    pub fn register_trimesh(&mut self, mesh_data: &MeshData, translation: [f32; 3]) -> PhysicsHandle {
-        let vertices = mesh_data.vertices.iter().map(|v| v.position.into()).collect();
-        let indices = mesh_data.indices.chunks(3).map(|v| [v[0], v[1], v[2]]).collect();
+    pub fn register_trimesh(&mut self, trimesh: TriMesh, translation: [f32; 3]) -> PhysicsHandle {
+        let vertices = trimesh.vertices.iter().map(|v| Point::from(*v)).collect();
         let rigid_body = RigidBodyBuilder::new_static()
             .translation(translation[0], translation[1], translation[2])
             .build();
         let r = self.bodies.insert(rigid_body);
-        let collider = ColliderBuilder::trimesh(vertices, indices).build();
+        let collider = ColliderBuilder::trimesh(vertices, trimesh.indices).build();
         let c = self.colliders.insert(collider, r, &mut self.bodies);
         PhysicsHandle { r, c }
     }
 
     pub fn remove_physics_handle(&mut self, physics_handle: &PhysicsHandle) {
+        if let Some(collider_entity) = self.collider_entities.remove(&physics_handle.c) {
+            self.physics_objects.remove(&collider_entity);
+            if self.character == Some(collider_entity) {
+                self.character = None;
+            }
+        }
         self.colliders.remove(physics_handle.c, &mut self.bodies, false);
         self.bodies
             .remove(physics_handle.r, &mut self.colliders, &mut self.joints);
     }
 
-    pub fn register(&mut self, entity_handle: Handle<Entity>, entities: &Registry<Entity>) {
-        if let Some(entity) = entities.get(&entity_handle) {
-            if let Some(collision_shape) = &entity.collision_shape {
-                let collider = match &collision_shape.body {
-                    Body::Cuboid(cuboid) => {
-                        ColliderBuilder::cuboid(cuboid.half_extent_x, cuboid.half_extent_y, cuboid.half_extent_z)
-                            .friction(0.0)
-                            .build()
-                    }
-                    Body::Sphere(sphere) => ColliderBuilder::ball(sphere.radius).friction(0.0).build(),
-                };
-                let translation = entity.transform.translation;
-                let rigid_body = match &collision_shape.body_status {
-                    BodyStatus::Static => RigidBodyBuilder::new_static()
-                        .translation(translation.x, translation.y, translation.z)
-                        .build(),
-                    BodyStatus::Dynamic => RigidBodyBuilder::new_dynamic()
-                        .translation(translation.x, translation.y, translation.z)
-                        .build(),
-                };
-                let r = self.bodies.insert(rigid_body);
-                let c = self.colliders.insert(collider, r, &mut self.bodies);
-                match &collision_shape.body_status {
-                    BodyStatus::Static => (),
-                    BodyStatus::Dynamic => {
-                        self.physics_objects_dynamic
-                            .insert(entity_handle.id, PhysicsHandle { r, c });
+    // entities without a transform or a collision shape are not registered
+    pub fn register(
+        &mut self,
+        entity_handle: Handle<Entity>,
+        entities: &Entities,
+        meshes: &Assets<Mesh>,
+    ) -> Result<(), PhysicsError> {
+        if let Some(physics_handle) = self.physics_objects.remove(&entity_handle) {
+            self.remove_physics_handle(&physics_handle);
+        }
+        let (transform, collision_shape) = match (
+            world_transform(entities, &entity_handle),
+            entities.get::<CollisionShape>(&entity_handle),
+        ) {
+            (Some(transform), Some(collision_shape)) => (transform, collision_shape),
+            _ => return Ok(()),
+        };
+        let mesh = entities
+            .get::<AssetHandle<Mesh>>(&entity_handle)
+            .and_then(|mesh_handle| meshes.get(mesh_handle));
+        let shape = shape_from_body(&collision_shape.body, mesh)?;
+        let material = &collision_shape.material;
+        // shapes without volume, like trimeshes, can't derive a density from the mass, their body gets the mass
+        let (density, body_mass) = match material.mass {
+            Some(mass) => {
+                let inv_volume = shape.mass_properties(1.0).inv_mass;
+                if inv_volume > 0.0 {
+                    (mass * inv_volume, None)
+                } else {
+                    (0.0, Some(mass))
+                }
+            }
+            None => (material.density, None),
+        };
+        let collider = ColliderBuilder::new(shape)
+            .friction(material.friction)
+            .restitution(material.restitution)
+            .density(density)
+            .collision_groups(interaction_groups(collision_shape.collision_groups))
+            .build();
+        let parameters = &collision_shape.body_parameters;
+        let mut rigid_body_builder = match &collision_shape.body_status {
+            BodyStatus::Static => RigidBodyBuilder::new_static(),
+            BodyStatus::Dynamic => RigidBodyBuilder::new_dynamic(),
+            BodyStatus::Kinematic => RigidBodyBuilder::new_kinematic(),
+        }
+        .position(isometry_from_transform(transform))
+        .linear_damping(parameters.linear_damping)
+        .angular_damping(parameters.angular_damping)
+        .gravity_scale(parameters.gravity_scale)
+        .restrict_rotations(
+            !parameters.locked_rotations[0],
+            !parameters.locked_rotations[1],
+            !parameters.locked_rotations[2],
+        )
+        .ccd_enabled(parameters.ccd_enabled);
+        if let Some(mass) = body_mass {
+            rigid_body_builder = rigid_body_builder.additional_mass(mass);
+        }
+        let rigid_body = if parameters.lock_translations {
+            rigid_body_builder.lock_translations().build()
+        } else {
+            rigid_body_builder.build()
+        };
+        let r = self.bodies.insert(rigid_body);
+        let c = self.colliders.insert(collider, r, &mut self.bodies);
+        self.collider_entities.insert(c, entity_handle);
+        self.physics_objects.insert(entity_handle, PhysicsHandle { r, c });
+        Ok(())
+    }
+
+    // joints are removed together with the rigid body of either entity
+    pub fn add_joint(
+        &mut self,
+        entity1: &Handle<Entity>,
+        entity2: &Handle<Entity>,
+        joint: &Joint,
+    ) -> Option<PhysicsJointHandle> {
+        let r1 = self.physics_objects.get(entity1)?.r;
+        let r2 = self.physics_objects.get(entity2)?.r;
+        let j = self
+            .joints
+            .insert(&mut self.bodies, r1, r2, joint_params_from_joint(joint)?);
+        if let Joint::Revolute(revolute) = joint {
+            if let Some(limits) = revolute.limits {
+                self.revolute_limits.insert(
+                    j,
+                    RevoluteLimits {
+                        limits,
+                        motor: revolute.motor,
+                    },
+                );
+            }
+        }
+        Some(PhysicsJointHandle { j })
+    }
+
+    pub fn remove_joint(&mut self, joint_handle: &PhysicsJointHandle) {
+        self.revolute_limits.remove(&joint_handle.j);
+        self.joints.remove(joint_handle.j, &mut self.bodies, true);
+    }
+
+    // only prismatic and revolute joints have a motor
+    pub fn set_joint_motor(&mut self, joint_handle: &PhysicsJointHandle, motor: JointMotor) {
+        if let Some(joint) = self.joints.get_mut(joint_handle.j) {
+            match &mut joint.params {
+                JointParams::PrismaticJoint(params) => params.configure_motor(
+                    motor.target_position,
+                    motor.target_velocity,
+                    motor.stiffness,
+                    motor.damping,
+                ),
+                JointParams::RevoluteJoint(params) => {
+                    if let Some(revolute_limits) = self.revolute_limits.get_mut(&joint_handle.j) {
+                        revolute_limits.motor = Some(motor);
                     }
+                    configure_revolute_motor(params, Some(motor));
+                }
+                _ => return,
+            }
+            let (body1, body2) = (joint.body1, joint.body2);
+            for body in [body1, body2].iter() {
+                if let Some(rigid_body) = self.bodies.get_mut(*body) {
+                    rigid_body.wake_up(true);
                 }
             }
         }
     }
+
+    // queries see the colliders as they were after the last call to step
+    pub fn cast_ray(&self, origin: Vec3, direction: Vec3, max_toi: f32, groups: CollisionGroups) -> Option<RayHit> {
+        let ray = Ray::new(
+            Point::new(origin.x, origin.y, origin.z),
+            Vector3::new(direction.x, direction.y, direction.z),
+        );
+        self.query_pipeline
+            .cast_ray_and_get_normal(&self.colliders, &ray, max_toi, true, interaction_groups(groups), None)
+            .map(|(c, intersection)| RayHit {
+                entity: self.collider_entities.get(&c).cloned(),
+                toi: intersection.toi,
+                point: origin + direction * intersection.toi,
+                normal: Vec3::new(intersection.normal.x, intersection.normal.y, intersection.normal.z),
+            })
+    }
+
+    pub fn cast_shape(
+        &self,
+        body: &Body,
+        transform: &Transform,
+        velocity: Vec3,
+        max_toi: f32,
+        groups: CollisionGroups,
+    ) -> Option<ShapeHit> {
+        let shape = shape_from_body(body, None).ok()?;
+        let shape_position = isometry_from_transform(transform);
+        self.query_pipeline
+            .cast_shape(
+                &self.colliders,
+                &shape_position,
+                &Vector3::new(velocity.x, velocity.y, velocity.z),
+                &*shape,
+                max_toi,
+                interaction_groups(groups),
+                None,
+            )
+            .map(|(c, toi)| {
+                // the colliders are the first shape of the query, so witness1 and normal1 are in world space
+                let point = toi.witness1;
+                let normal = toi.normal1;
+                ShapeHit {
+                    entity: self.collider_entities.get(&c).cloned(),
+                    toi: toi.toi,
+                    point: Vec3::new(point.x, point.y, point.z),
+                    normal: Vec3::new(normal.x, normal.y, normal.z),
+                }
+            })
+    }
+
+    // colliders of dynamic and kinematic bodies stand out from the static ones
+    pub fn draw_colliders(&self, debug: &mut DebugDraw) {
+        for (_, collider) in self.colliders.iter() {
+            let color = match self.bodies.get(collider.parent()) {
+                Some(body) if !body.is_static() => DYNAMIC_COLLIDER_COLOR,
+                _ => STATIC_COLLIDER_COLOR,
+            };
+            draw_shape(debug, collider.position(), collider.shape(), color);
+        }
+    }
+
+    // returns None for every intersecting collider which is not owned by an entity
+    pub fn intersections_with_shape(
+        &self,
+        body: &Body,
+        transform: &Transform,
+        groups: CollisionGroups,
+    ) -> Vec<Option<Handle<Entity>>> {
+        let mut intersections = Vec::new();
+        if let Ok(shape) = shape_from_body(body, None) {
+            self.query_pipeline.intersections_with_shape(
+                &self.colliders,
+                &isometry_from_transform(transform),
+                &*shape,
+                interaction_groups(groups),
+                None,
+                |c, _| {
+                    intersections.push(self.collider_entities.get(&c).cloned());
+                    true
+                },
+            );
+        }
+        intersections
+    }
+}
+
+// bodies follow the GlobalTransform when the entity is part of a hierarchy
+fn world_transform<'a>(entities: &'a Entities, entity_handle: &Handle<Entity>) -> Option<&'a Transform> {
+    entities
+        .get::<GlobalTransform>(entity_handle)
+        .map(|transform| &transform.0)
+        .or_else(|| entities.get::<Transform>(entity_handle))
+}
+
+// trimeshes like the ones of the chunks and convex hulls are drawn as their bounds
+fn draw_shape(debug: &mut DebugDraw, position: &Isometry<f32>, shape: &dyn Shape, color: [f32; 4]) {
+    let translation = &position.translation.vector;
+    let rotation = &position.rotation;
+    let m = Mat4::from_rotation_translation(
+        Quat::from_xyzw(rotation.i, rotation.j, rotation.k, rotation.w),
+        Vec3::new(translation.x, translation.y, translation.z),
+    );
+    if let Some(cuboid) = shape.as_cuboid() {
+        let half_extents = cuboid.half_extents;
+        debug.cuboid(m, Vec3::new(half_extents.x, half_extents.y, half_extents.z), color);
+    } else if let Some(ball) = shape.as_ball() {
+        debug.sphere(m.transform_point3(Vec3::zero()), ball.radius, color);
+    } else if let Some(capsule) = shape.as_capsule() {
+        let a = m.transform_point3(Vec3::new(capsule.segment.a.x, capsule.segment.a.y, capsule.segment.a.z));
+        let b = m.transform_point3(Vec3::new(capsule.segment.b.x, capsule.segment.b.y, capsule.segment.b.z));
+        debug.sphere(a, capsule.radius, color);
+        debug.sphere(b, capsule.radius, color);
+        debug.line(a, b, color);
+    } else if let Some(cylinder) = shape.as_cylinder() {
+        let up = m.transform_vector3(Vec3::unit_y()) * cylinder.half_height;
+        let center = m.transform_point3(Vec3::zero());
+        debug.circle(center + up, up, cylinder.radius, color);
+        debug.circle(center - up, up, cylinder.radius, color);
+        debug.line(center - up, center + up, color);
+    } else if let Some(compound) = shape.as_compound() {
+        for (shape_position, shape) in compound.shapes() {
+            draw_shape(debug, &(position * shape_position), &**shape, color);
+        }
+    } else {
+        let aabb = shape.compute_aabb(position);
+        debug.aabb(
+            &Aabb::new(
+                Vec3::new(aabb.mins.x, aabb.mins.y, aabb.mins.z),
+                Vec3::new(aabb.maxs.x, aabb.maxs.y, aabb.maxs.z),
+            ),
+            color,
+        );
+    }
+}
+
+fn point_from_vec3(v: Vec3) -> Point<f32> {
+    Point::new(v.x, v.y, v.z)
+}
+
+fn axis_from_vec3(v: Vec3) -> Option<Unit<Vector3<f32>>> {
+    Unit::try_new(Vector3::new(v.x, v.y, v.z), 1.0e-6)
+}
+
+fn joint_params_from_joint(joint: &Joint) -> Option<JointParams> {
+    match joint {
+        Joint::Ball(ball) => Some(BallJoint::new(point_from_vec3(ball.anchor1), point_from_vec3(ball.anchor2)).into()),
+        Joint::Fixed(fixed) => Some(
+            FixedJoint::new(
+                isometry_from_transform(&fixed.anchor1),
+                isometry_from_transform(&fixed.anchor2),
+            )
+            .into(),
+        ),
+        Joint::Prismatic(prismatic) => {
+            let mut params = PrismaticJoint::new(
+                point_from_vec3(prismatic.anchor1),
+                axis_from_vec3(prismatic.axis1)?,
+                Vector3::zeros(),
+                point_from_vec3(prismatic.anchor2),
+                axis_from_vec3(prismatic.axis2)?,
+                Vector3::zeros(),
+            );
+            if let Some(limits) = prismatic.limits {
+                params.limits_enabled = true;
+                params.limits = limits;
+            }
+            if let Some(motor) = &prismatic.motor {
+                params.configure_motor(
+                    motor.target_position,
+                    motor.target_velocity,
+                    motor.stiffness,
+                    motor.damping,
+                );
+            }
+            Some(params.into())
+        }
+        Joint::Revolute(revolute) => {
+            let mut params = RevoluteJoint::new(
+                point_from_vec3(revolute.anchor1),
+                axis_from_vec3(revolute.axis1)?,
+                point_from_vec3(revolute.anchor2),
+                axis_from_vec3(revolute.axis2)?,
+            );
+            configure_revolute_motor(&mut params, revolute.motor);
+            Some(params.into())
+        }
+    }
+}
+
+fn configure_revolute_motor(params: &mut RevoluteJoint, motor: Option<JointMotor>) {
+    match motor {
+        Some(motor) => params.configure_motor(
+            motor.target_position,
+            motor.target_velocity,
+            motor.stiffness,
+            motor.damping,
+        ),
+        None => params.configure_motor(0.0, 0.0, 0.0, 0.0),
+    }
+}
+
+fn interaction_groups(groups: CollisionGroups) -> InteractionGroups {
+    InteractionGroups::new(groups.memberships, groups.filter)
+}
+
+fn isometry_from_transform(transform: &Transform) -> Isometry<f32> {
+    let t = transform.translation;
+    let r = transform.rotation;
+    Isometry::from_parts(
+        Translation3::new(t.x, t.y, t.z),
+        UnitQuaternion::from_quaternion(Quaternion::new(r.w, r.x, r.y, r.z)),
+    )
+}
+
+fn flatten_compound(
+    parent: &Isometry<f32>,
+    body: &Body,
+    mesh: Option<&Mesh>,
+    shapes: &mut Vec<(Isometry<f32>, SharedShape)>,
+) -> Result<(), PhysicsError> {
+    match body {
+        Body::Compound(compound) => {
+            for (transform, body) in &compound.shapes {
+                flatten_compound(&(parent * isometry_from_transform(transform)), body, mesh, shapes)?;
+            }
+        }
+        _ => shapes.push((*parent, shape_from_body(body, mesh)?)),
+    }
+    Ok(())
+}
+
+// mesh is the mesh of the entity for Body::Mesh, queries have none
+fn shape_from_body(body: &Body, mesh: Option<&Mesh>) -> Result<SharedShape, PhysicsError> {
+    match body {
+        Body::Cuboid(cuboid) => Ok(SharedShape::cuboid(
+            cuboid.half_extent_x,
+            cuboid.half_extent_y,
+            cuboid.half_extent_z,
+        )),
+        Body::Sphere(sphere) => Ok(SharedShape::ball(sphere.radius)),
+        Body::Capsule(capsule) => Ok(SharedShape::capsule(
+            Point::new(0.0, -capsule.half_height, 0.0),
+            Point::new(0.0, capsule.half_height, 0.0),
+            capsule.radius,
+        )),
+        Body::Cylinder(cylinder) => Ok(SharedShape::cylinder(cylinder.half_height, cylinder.radius)),
+        Body::ConvexHull(convex_hull) => {
+            // parry panics on hulls without volume instead of failing
+            if !spans_volume(&convex_hull.points) {
+                return Err(PhysicsError::InvalidShape);
+            }
+            let points = convex_hull.points.iter().map(|p| Point::from(*p)).collect::<Vec<_>>();
+            SharedShape::convex_hull(points.as_slice()).ok_or(PhysicsError::InvalidShape)
+        }
+        Body::TriMesh(trimesh) => shape_from_trimesh(trimesh),
+        Body::Mesh => shape_from_trimesh(&TriMesh::from(mesh.ok_or(PhysicsError::MissingMesh)?)),
+        Body::Compound(_) => {
+            // parry does not allow nested compounds, so all sub shapes are moved into one compound
+            let mut shapes = Vec::new();
+            flatten_compound(&Isometry::identity(), body, mesh, &mut shapes)?;
+            if shapes.is_empty() {
+                Err(PhysicsError::InvalidShape)
+            } else {
+                Ok(SharedShape::compound(shapes))
+            }
+        }
+    }
+}
+
+// false when all points are on one plane
+fn spans_volume(points: &[[f32; 3]]) -> bool {
+    let epsilon = 1.0e-6;
+    let points = points.iter().map(|p| Vec3::from(*p)).collect::<Vec<_>>();
+    let first = match points.first() {
+        Some(first) => *first,
+        None => return false,
+    };
+    let edge = match points.iter().map(|p| *p - first).find(|edge| edge.length() > epsilon) {
+        Some(edge) => edge,
+        None => return false,
+    };
+    let normal = match points
+        .iter()
+        .map(|p| edge.cross(*p - first))
+        .find(|normal| normal.length() > epsilon)
+    {
+        Some(normal) => normal.normalize(),
+        None => return false,
+    };
+    points.iter().any(|p| normal.dot(*p - first).abs() > epsilon)
+}
+
+fn shape_from_trimesh(trimesh: &TriMesh) -> Result<SharedShape, PhysicsError> {
+    if trimesh.indices.is_empty() {
+        return Err(PhysicsError::InvalidShape);
+    }
+    Ok(SharedShape::trimesh(
+        trimesh.vertices.iter().map(|v| Point::from(*v)).collect(),
+        trimesh.indices.clone(),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::{
+        controllers::CharacterController,
+        entity::{propagate_transforms, Entities, Entity},
+        physics::{
+            collisionshape::{
+                Body, BodyParameters, BodyStatus, Capsule, CollisionGroups, CollisionShape, Compound, ConvexHull,
+                Cuboid, PhysicsMaterial, Sphere, TriMesh,
+            },
+            error::PhysicsError,
+            joint::{BallJoint, Joint, RevoluteJoint},
+            physics::shape_from_body,
+            Physics,
+        },
+        registry::{Assets, Handle},
+        renderer::DebugDraw,
+        transform::{GlobalTransform, Transform},
+    };
+    use glam::{Quat, Vec3};
     use rapier3d::{
-        dynamics::{IntegrationParameters, JointSet, RigidBodyBuilder, RigidBodySet},
+        dynamics::{CCDSolver, IntegrationParameters, JointSet, RigidBodyBuilder, RigidBodySet},
         geometry::{BroadPhase, ColliderBuilder, ColliderSet, NarrowPhase},
         na::Vector3,
         pipeline::PhysicsPipeline,
@@ -164,6 +733,7 @@ mod tests {
         let mut bodies = RigidBodySet::new();
         let mut colliders = ColliderSet::new();
         let mut joints = JointSet::new();
+        let mut ccd_solver = CCDSolver::new();
         let physics_hooks = ();
         let physics_events = ();
 
@@ -187,13 +757,14 @@ mod tests {
                .set_position(Isometry2::new(Vector2::new(0.0, 0.0), 0.0), true);
             */
             physics_pipeline.step(
-                &(Vector2::y() * 0.0),
+                &(Vector3::y() * 0.0),
                 &int_params,
                 &mut broad_phase,
                 &mut narrow_phase,
                 &mut bodies,
                 &mut colliders,
                 &mut joints,
+                &mut ccd_solver,
                 &physics_hooks,
                 &physics_events,
             );
@@ -202,4 +773,525 @@ mod tests {
             println!("{} {}", translation.x, translation.y);
         }
     }
+
+    #[test]
+    fn nested_compound_is_flattened() {
+        let body = Body::Compound(Compound {
+            shapes: vec![
+                (
+                    Transform::from_translation(Vec3::new(0.0, 1.0, 0.0)),
+                    Body::Capsule(Capsule {
+                        half_height: 0.5,
+                        radius: 0.25,
+                    }),
+                ),
+                (
+                    Transform::from_translation(Vec3::new(2.0, 0.0, 0.0)),
+                    Body::Compound(Compound {
+                        shapes: vec![
+                            (
+                                Transform::from_translation(Vec3::new(0.0, 0.0, 3.0)),
+                                Body::Sphere(Sphere { radius: 0.5 }),
+                            ),
+                            (
+                                Transform::identity(),
+                                Body::Cuboid(Cuboid {
+                                    half_extent_x: 0.5,
+                                    half_extent_y: 0.5,
+                                    half_extent_z: 0.5,
+                                }),
+                            ),
+                        ],
+                    }),
+                ),
+            ],
+        });
+        let shape = shape_from_body(&body, None).unwrap();
+        let compound = shape.as_compound().unwrap();
+        assert_eq!(3, compound.shapes().len());
+        let sphere_translation = compound.shapes()[1].0.translation;
+        assert_eq!(
+            [2.0, 0.0, 3.0],
+            [sphere_translation.x, sphere_translation.y, sphere_translation.z]
+        );
+    }
+
+    #[test]
+    fn shapes_that_can_not_be_built_are_errors() {
+        let mut physics = Physics::default();
+        let mut entities = Entities::new();
+        let meshes = Assets::new();
+        let flat_hull = spawn_with_body(
+            &mut entities,
+            BodyStatus::Dynamic,
+            Body::ConvexHull(ConvexHull {
+                points: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 1.0]],
+            }),
+            Vec3::zero(),
+        );
+        assert!(matches!(
+            physics.register(flat_hull, &entities, &meshes),
+            Err(PhysicsError::InvalidShape)
+        ));
+        let line_hull = spawn_with_body(
+            &mut entities,
+            BodyStatus::Dynamic,
+            Body::ConvexHull(ConvexHull {
+                points: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]],
+            }),
+            Vec3::zero(),
+        );
+        assert!(matches!(
+            physics.register(line_hull, &entities, &meshes),
+            Err(PhysicsError::InvalidShape)
+        ));
+        let without_mesh = spawn_with_body(&mut entities, BodyStatus::Static, Body::Mesh, Vec3::zero());
+        assert!(matches!(
+            physics.register(without_mesh, &entities, &meshes),
+            Err(PhysicsError::MissingMesh)
+        ));
+        assert!(!physics.is_registered(&flat_hull));
+        assert!(!physics.is_registered(&without_mesh));
+        assert_eq!(0, physics.bodies.len());
+    }
+
+    #[test]
+    fn queries_return_hit_entity() {
+        let mut physics = Physics::default();
+        let mut entities = Entities::new();
+        let meshes = Assets::new();
+        let cube = entities
+            .spawn()
+            .with(CollisionShape {
+                body_status: BodyStatus::Static,
+                body: Body::Cuboid(Cuboid {
+                    half_extent_x: 0.5,
+                    half_extent_y: 0.5,
+                    half_extent_z: 0.5,
+                }),
+                collision_groups: CollisionGroups::new(0b01, 0b11),
+                material: PhysicsMaterial::default(),
+                body_parameters: BodyParameters::default(),
+            })
+            .with(Transform::from_translation(Vec3::new(0.0, 0.0, 0.0)))
+            .id();
+        physics.register(cube, &entities, &meshes).unwrap();
+        physics.step(&mut entities, &CharacterController::default());
+
+        let hit = physics
+            .cast_ray(
+                Vec3::new(0.0, 5.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                100.0,
+                CollisionGroups::all(),
+            )
+            .unwrap();
+        assert_eq!(Some(cube), hit.entity);
+        assert!((hit.toi - 4.5).abs() < 0.001);
+        assert!((hit.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 0.001);
+        assert!(physics
+            .cast_ray(
+                Vec3::new(0.0, 5.0, 0.0),
+                Vec3::new(0.0, -1.0, 0.0),
+                100.0,
+                CollisionGroups::new(0b10, 0b10)
+            )
+            .is_none());
+
+        let sphere = Body::Sphere(Sphere { radius: 0.5 });
+        let hit = physics
+            .cast_shape(
+                &sphere,
+                &Transform::from_translation(Vec3::new(0.0, 5.0, 0.0)),
+                Vec3::new(0.0, -1.0, 0.0),
+                100.0,
+                CollisionGroups::all(),
+            )
+            .unwrap();
+        assert!((hit.toi - 4.0).abs() < 0.001);
+        assert!((hit.point - Vec3::new(0.0, 0.5, 0.0)).length() < 0.01);
+
+        let overlapping = physics.intersections_with_shape(
+            &sphere,
+            &Transform::from_translation(Vec3::new(0.0, 0.75, 0.0)),
+            CollisionGroups::all(),
+        );
+        assert_eq!(1, overlapping.len());
+        assert!(physics
+            .intersections_with_shape(
+                &sphere,
+                &Transform::from_translation(Vec3::new(0.0, 2.0, 0.0)),
+                CollisionGroups::all()
+            )
+            .is_empty());
+    }
+
+    #[test]
+    fn shape_cast_hits_are_in_world_space() {
+        let mut physics = Physics::default();
+        let mut entities = Entities::new();
+        let meshes = Assets::new();
+        let rotation = Quat::from_rotation_z(30.0f32.to_radians());
+        let slope = entities
+            .spawn()
+            .with(CollisionShape {
+                body_status: BodyStatus::Static,
+                body: Body::Cuboid(Cuboid {
+                    half_extent_x: 2.0,
+                    half_extent_y: 0.5,
+                    half_extent_z: 2.0,
+                }),
+                collision_groups: CollisionGroups::default(),
+                material: PhysicsMaterial::default(),
+                body_parameters: BodyParameters::default(),
+            })
+            .with(Transform::from_translation_rotation(
+                Vec3::new(3.0, 1.0, -2.0),
+                rotation,
+            ))
+            .id();
+        physics.register(slope, &entities, &meshes).unwrap();
+        physics.step(&mut entities, &CharacterController::default());
+
+        let hit = physics
+            .cast_shape(
+                &Body::Sphere(Sphere { radius: 0.25 }),
+                &Transform::from_translation(Vec3::new(3.0, 6.0, -2.0)),
+                Vec3::new(0.0, -1.0, 0.0),
+                100.0,
+                CollisionGroups::all(),
+            )
+            .unwrap();
+        // the sphere touches the top face, which is tilted by the rotation of the slope
+        let normal = rotation * Vec3::unit_y();
+        let center = Vec3::new(3.0, 6.0 - hit.toi, -2.0);
+        assert_eq!(Some(slope), hit.entity);
+        assert!((normal.dot(center - Vec3::new(3.0, 1.0, -2.0)) - 0.75).abs() < 0.001);
+        assert!((hit.normal - normal).length() < 0.001);
+        assert!((hit.point - (center - normal * 0.25)).length() < 0.001);
+    }
+
+    #[test]
+    fn cuboid_colliders_are_drawn_as_boxes() {
+        let mut physics = Physics::default();
+        let mut entities = Entities::new();
+        let meshes = Assets::new();
+        let position = Vec3::new(3.0, 1.0, -2.0);
+        let crate_entity = entities
+            .spawn()
+            .with(CollisionShape {
+                body_status: BodyStatus::Static,
+                body: Body::Cuboid(Cuboid {
+                    half_extent_x: 2.0,
+                    half_extent_y: 0.5,
+                    half_extent_z: 1.0,
+                }),
+                collision_groups: CollisionGroups::default(),
+                material: PhysicsMaterial::default(),
+                body_parameters: BodyParameters::default(),
+            })
+            .with(Transform::from_translation(position))
+            .id();
+        physics.register(crate_entity, &entities, &meshes).unwrap();
+        physics.step(&mut entities, &CharacterController::default());
+
+        let mut debug = DebugDraw::new();
+        physics.draw_colliders(&mut debug);
+        // 12 edges of two vertices, every corner is shared by three edges
+        assert_eq!(24, debug.lines().len());
+        let corners = debug
+            .lines()
+            .iter()
+            .map(|vertex| Vec3::from(vertex.position) - position)
+            .collect::<Vec<_>>();
+        assert!(corners
+            .iter()
+            .all(|corner| (corner.abs() - Vec3::new(2.0, 0.5, 1.0)).length() < 0.001));
+        let center = corners.iter().fold(Vec3::zero(), |sum, corner| sum + *corner) / 24.0;
+        assert!(center.length() < 0.001);
+    }
+
+    #[test]
+    fn body_parameters_are_applied() {
+        let mut physics = Physics::default();
+        let mut entities = Entities::new();
+        let meshes = Assets::new();
+        let ball = entities
+            .spawn()
+            .with(CollisionShape {
+                body_status: BodyStatus::Dynamic,
+                body: Body::Sphere(Sphere { radius: 0.5 }),
+                collision_groups: CollisionGroups::default(),
+                material: PhysicsMaterial {
+                    mass: Some(10.0),
+                    ..Default::default()
+                },
+                body_parameters: BodyParameters {
+                    gravity_scale: 0.0,
+                    ccd_enabled: true,
+                    ..Default::default()
+                },
+            })
+            .with(Transform::from_translation(Vec3::new(0.0, 5.0, 0.0)))
+            .id();
+        physics.register(ball, &entities, &meshes).unwrap();
+        for _ in 0..60 {
+            physics.step(&mut entities, &CharacterController::default());
+        }
+        let rigid_body = physics.bodies.get(physics.physics_objects[&ball].r).unwrap();
+        assert!((rigid_body.mass() - 10.0).abs() < 0.001);
+        assert!(rigid_body.is_ccd_enabled());
+        assert_eq!(5.0, rigid_body.position().translation.y);
+    }
+
+    #[test]
+    fn mass_of_bodies_without_volume_is_kept() {
+        let mut physics = Physics::default();
+        let mut entities = Entities::new();
+        let meshes = Assets::new();
+        let sheet = entities
+            .spawn()
+            .with(CollisionShape {
+                body_status: BodyStatus::Dynamic,
+                body: Body::TriMesh(TriMesh {
+                    vertices: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+                    indices: vec![[0, 2, 1]],
+                }),
+                collision_groups: CollisionGroups::default(),
+                material: PhysicsMaterial {
+                    mass: Some(5.0),
+                    ..Default::default()
+                },
+                body_parameters: BodyParameters::default(),
+            })
+            .with(Transform::identity())
+            .id();
+        physics.register(sheet, &entities, &meshes).unwrap();
+        let rigid_body = physics.bodies.get(physics.physics_objects[&sheet].r).unwrap();
+        assert!((rigid_body.mass() - 5.0).abs() < 0.001);
+    }
+
+    fn spawn_with_body(
+        entities: &mut Entities,
+        body_status: BodyStatus,
+        body: Body,
+        translation: Vec3,
+    ) -> Handle<Entity> {
+        entities
+            .spawn()
+            .with(CollisionShape {
+                body_status,
+                body,
+                collision_groups: CollisionGroups::default(),
+                material: PhysicsMaterial::default(),
+                body_parameters: BodyParameters::default(),
+            })
+            .with(Transform::from_translation(translation))
+            .id()
+    }
+
+    #[test]
+    fn ball_joint_keeps_lamp_hanging() {
+        let mut physics = Physics::default();
+        let mut entities = Entities::new();
+        let meshes = Assets::new();
+        let ceiling = spawn_with_body(
+            &mut entities,
+            BodyStatus::Static,
+            Body::Cuboid(Cuboid {
+                half_extent_x: 0.5,
+                half_extent_y: 0.1,
+                half_extent_z: 0.5,
+            }),
+            Vec3::new(0.0, 10.0, 0.0),
+        );
+        let lamp = spawn_with_body(
+            &mut entities,
+            BodyStatus::Dynamic,
+            Body::Sphere(Sphere { radius: 0.25 }),
+            Vec3::new(1.0, 8.0, 0.0),
+        );
+        physics.register(ceiling, &entities, &meshes).unwrap();
+        physics.register(lamp, &entities, &meshes).unwrap();
+        let joint = Joint::Ball(BallJoint {
+            anchor1: Vec3::new(0.0, -0.1, 0.0),
+            anchor2: Vec3::new(-1.0, 1.9, 0.0),
+        });
+        physics.add_joint(&ceiling, &lamp, &joint).unwrap();
+        for _ in 0..120 {
+            physics.step(&mut entities, &CharacterController::default());
+        }
+        let lamp_position = physics
+            .bodies
+            .get(physics.physics_objects[&lamp].r)
+            .unwrap()
+            .position()
+            .translation;
+        let rope_length =
+            (Vec3::new(lamp_position.x, lamp_position.y, lamp_position.z) - Vec3::new(0.0, 9.9, 0.0)).length();
+        assert!((rope_length - Vec3::new(1.0, 1.9, 0.0).length()).abs() < 0.05);
+
+        let lamp_physics_handle = physics.physics_objects[&lamp].clone();
+        physics.remove_physics_handle(&lamp_physics_handle);
+        assert!(physics.joints.is_empty());
+        assert!(!physics.is_registered(&lamp));
+    }
+
+    #[test]
+    fn revolute_joint_stays_within_limits() {
+        let mut physics = Physics::default();
+        let mut entities = Entities::new();
+        let meshes = Assets::new();
+        let frame = spawn_with_body(
+            &mut entities,
+            BodyStatus::Static,
+            Body::Sphere(Sphere { radius: 0.05 }),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+        let door = spawn_with_body(
+            &mut entities,
+            BodyStatus::Dynamic,
+            Body::Cuboid(Cuboid {
+                half_extent_x: 0.5,
+                half_extent_y: 1.0,
+                half_extent_z: 0.05,
+            }),
+            Vec3::new(0.6, 1.0, 0.0),
+        );
+        physics.register(frame, &entities, &meshes).unwrap();
+        physics.register(door, &entities, &meshes).unwrap();
+        let joint = Joint::Revolute(RevoluteJoint {
+            anchor1: Vec3::new(0.1, 0.0, 0.0),
+            axis1: Vec3::unit_y(),
+            anchor2: Vec3::new(-0.5, 0.0, 0.0),
+            axis2: Vec3::unit_y(),
+            limits: Some([-0.5, 0.5]),
+            motor: None,
+        });
+        physics.add_joint(&frame, &door, &joint).unwrap();
+        let door_body = physics.physics_objects[&door].r;
+        physics
+            .bodies
+            .get_mut(door_body)
+            .unwrap()
+            .set_angvel(Vector3::new(0.0, 5.0, 0.0), true);
+        for _ in 0..120 {
+            physics.step(&mut entities, &CharacterController::default());
+        }
+        let rotation = physics.bodies.get(door_body).unwrap().position().rotation;
+        assert!(rotation.angle() < 0.6);
+    }
+
+    #[test]
+    fn removed_entities_are_unregistered() {
+        let mut physics = Physics::default();
+        let mut entities = Entities::new();
+        let meshes = Assets::new();
+        let platform = spawn_with_body(
+            &mut entities,
+            BodyStatus::Kinematic,
+            Body::Cuboid(Cuboid {
+                half_extent_x: 1.0,
+                half_extent_y: 0.1,
+                half_extent_z: 1.0,
+            }),
+            Vec3::new(0.0, 0.0, 0.0),
+        );
+        let ball = spawn_with_body(
+            &mut entities,
+            BodyStatus::Dynamic,
+            Body::Sphere(Sphere { radius: 0.5 }),
+            Vec3::new(0.0, 5.0, 0.0),
+        );
+        physics.register(platform, &entities, &meshes).unwrap();
+        physics.register(ball, &entities, &meshes).unwrap();
+        physics.register(ball, &entities, &meshes).unwrap();
+        assert_eq!(2, physics.bodies.len());
+
+        entities.get_mut::<Transform>(&platform).unwrap().translation = Vec3::new(0.0, 1.0, 0.0);
+        physics.step(&mut entities, &CharacterController::default());
+        physics.step(&mut entities, &CharacterController::default());
+        let platform_position = physics
+            .bodies
+            .get(physics.physics_objects[&platform].r)
+            .unwrap()
+            .position()
+            .translation;
+        assert_eq!(1.0, platform_position.y);
+
+        physics.unregister(&platform);
+        assert!(!physics.is_registered(&platform));
+        entities.despawn(&ball);
+        physics.step(&mut entities, &CharacterController::default());
+        assert!(!physics.is_registered(&ball));
+        assert_eq!(0, physics.bodies.len());
+        assert_eq!(0, physics.colliders.len());
+    }
+
+    #[test]
+    fn dynamic_children_are_synced_relative_to_their_parent() {
+        let mut physics = Physics::default();
+        let mut entities = Entities::new();
+        let meshes = Assets::new();
+        let cart = entities
+            .spawn()
+            .with(Transform::from_translation(Vec3::new(5.0, 0.0, 0.0)))
+            .id();
+        let ball = spawn_with_body(
+            &mut entities,
+            BodyStatus::Dynamic,
+            Body::Sphere(Sphere { radius: 0.5 }),
+            Vec3::new(0.0, 5.0, 0.0),
+        );
+        entities.set_parent(&ball, &cart);
+        propagate_transforms(&mut entities);
+        physics.register(ball, &entities, &meshes).unwrap();
+        for _ in 0..30 {
+            physics.step(&mut entities, &CharacterController::default());
+        }
+        let local = entities.get::<Transform>(&ball).unwrap().translation;
+        assert!(local.x.abs() < 0.001);
+        assert!(local.y < 5.0);
+
+        propagate_transforms(&mut entities);
+        let world = entities.get::<GlobalTransform>(&ball).unwrap().0.translation;
+        let position = physics
+            .bodies
+            .get(physics.physics_objects[&ball].r)
+            .unwrap()
+            .position()
+            .translation;
+        assert!((world - Vec3::new(position.x, position.y, position.z)).length() < 0.001);
+        assert!((world.x - 5.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn despawned_entities_are_not_hit() {
+        let mut physics = Physics::default();
+        let mut entities = Entities::new();
+        let meshes = Assets::new();
+        let wall = spawn_with_body(
+            &mut entities,
+            BodyStatus::Static,
+            Body::Cuboid(Cuboid {
+                half_extent_x: 1.0,
+                half_extent_y: 1.0,
+                half_extent_z: 0.1,
+            }),
+            Vec3::new(0.0, 0.0, -5.0),
+        );
+        physics.register(wall, &entities, &meshes).unwrap();
+        physics.step(&mut entities, &CharacterController::default());
+        let origin = Vec3::zero();
+        let direction = Vec3::new(0.0, 0.0, -1.0);
+        assert!(physics
+            .cast_ray(origin, direction, 100.0, CollisionGroups::all())
+            .is_some());
+
+        physics.despawn(&wall, &mut entities);
+        assert!(!entities.contains(&wall));
+        assert!(physics
+            .cast_ray(origin, direction, 100.0, CollisionGroups::all())
+            .is_none());
+    }
 }
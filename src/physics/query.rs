@@ -0,0 +1,19 @@
+use crate::{entity::Entity, registry::Handle};
+use glam::Vec3;
+
+// entity is None when the hit collider is not owned by an entity, e.g. the trimesh of a world chunk
+#[derive(Clone)]
+pub struct RayHit {
+    pub entity: Option<Handle<Entity>>,
+    pub toi: f32,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+#[derive(Clone)]
+pub struct ShapeHit {
+    pub entity: Option<Handle<Entity>>,
+    pub toi: f32,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
@@ -0,0 +1,167 @@
+use crate::{
+    input::Events,
+    registry::{Handle, Registry},
+};
+use std::{collections::HashMap, ops::Deref, sync::Arc};
+
+// strong handle, the asset stays loaded as long as one of these exists
+pub struct AssetHandle<T> {
+    handle: Handle<T>,
+    reference: Arc<()>,
+}
+
+impl<T> Clone for AssetHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle,
+            reference: self.reference.clone(),
+        }
+    }
+}
+
+impl<T> Deref for AssetHandle<T> {
+    type Target = Handle<T>;
+
+    fn deref(&self) -> &Handle<T> {
+        &self.handle
+    }
+}
+
+impl<T> AssetHandle<T> {
+    pub fn downgrade(&self) -> Handle<T> {
+        self.handle
+    }
+}
+
+pub enum AssetEvent<T> {
+    Created(Handle<T>),
+    Modified(Handle<T>),
+    Removed(Handle<T>),
+}
+
+pub struct Assets<T> {
+    assets: Registry<T>,
+    references: HashMap<Handle<T>, Arc<()>>,
+    events: Events<AssetEvent<T>>,
+    pending_events: Events<AssetEvent<T>>,
+}
+
+impl<T> Default for Assets<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Assets<T> {
+    pub fn new() -> Self {
+        Self {
+            assets: Registry::new(),
+            references: HashMap::new(),
+            events: Events::default(),
+            pending_events: Events::default(),
+        }
+    }
+
+    pub fn add(&mut self, asset: T) -> AssetHandle<T> {
+        let handle = self.assets.add(asset);
+        let reference = Arc::new(());
+        self.references.insert(handle, reference.clone());
+        self.pending_events.send(AssetEvent::Created(handle));
+        AssetHandle { handle, reference }
+    }
+
+    pub fn upgrade(&self, handle: &Handle<T>) -> Option<AssetHandle<T>> {
+        self.references.get(handle).map(|reference| AssetHandle {
+            handle: *handle,
+            reference: reference.clone(),
+        })
+    }
+
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.assets.get(handle)
+    }
+
+    // the Modified event is conservative, it is sent for every mutable access even if the asset is left unchanged
+    pub fn get_mut(&mut self, handle: &Handle<T>) -> Option<&mut T> {
+        let asset = self.assets.get_mut(handle)?;
+        self.pending_events.send(AssetEvent::Modified(*handle));
+        Some(asset)
+    }
+
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    pub fn handles(&self) -> impl Iterator<Item = Handle<T>> + '_ {
+        self.assets.handles()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+        self.assets.iter()
+    }
+
+    // events sent between the last two updates, they stay readable for the whole frame after the update
+    pub fn events(&self) -> &Events<AssetEvent<T>> {
+        &self.events
+    }
+
+    // unloads every asset without strong handles left and publishes the events of the last frame, call once per frame
+    pub fn update(&mut self) {
+        let unreferenced = self
+            .references
+            .iter()
+            .filter(|(_, reference)| Arc::strong_count(reference) == 1)
+            .map(|(handle, _)| *handle)
+            .collect::<Vec<_>>();
+        for handle in unreferenced {
+            self.references.remove(&handle);
+            self.assets.remove(handle);
+            self.pending_events.send(AssetEvent::Removed(handle));
+        }
+        std::mem::swap(&mut self.events, &mut self.pending_events);
+        self.pending_events.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::{AssetEvent, Assets};
+
+    #[test]
+    fn asset_is_unloaded_after_last_strong_handle() {
+        let mut assets = Assets::new();
+        let strong = assets.add("tree");
+        let weak = strong.downgrade();
+        let other_strong = assets.upgrade(&weak).unwrap();
+        assert_eq!(0, assets.events().values().count());
+
+        assets.update();
+        assert!(matches!(assets.events().values().next(), Some(AssetEvent::Created(_))));
+        assets.update();
+        assert_eq!(0, assets.events().values().count());
+        drop(strong);
+        assets.update();
+        assert_eq!(Some(&"tree"), assets.get(&weak));
+
+        drop(other_strong);
+        assets.update();
+        assert!(assets.get(&weak).is_none());
+        assert!(assets.upgrade(&weak).is_none());
+        assert!(matches!(assets.events().values().next(), Some(AssetEvent::Removed(h)) if *h == weak));
+    }
+
+    #[test]
+    fn get_mut_sends_modified() {
+        let mut assets = Assets::new();
+        let strong = assets.add(1);
+        assets.update();
+        *assets.get_mut(&strong).unwrap() += 1;
+        assets.update();
+        assert!(matches!(assets.events().values().next(), Some(AssetEvent::Modified(h)) if *h == *strong));
+        assert_eq!(Some(&2), assets.get(&strong));
+    }
+}
@@ -1,24 +1,50 @@
-use std::marker::PhantomData;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
 
-#[derive(Copy, Hash)]
+// the generation is bumped every time a slot is freed, so a handle to a removed item never aliases a new one
 pub struct Handle<T> {
-    pub id: u64,
+    pub index: u32,
+    pub generation: u32,
     marker: PhantomData<T>,
 }
 
 impl<T> Clone for Handle<T> {
     fn clone(&self) -> Self {
-        Self {
-            id: self.id,
-            marker: PhantomData,
-        }
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({}v{})", self.index, self.generation)
     }
 }
 
 impl<T> Handle<T> {
-    pub fn new(id: u64) -> Self {
+    pub fn new(index: u32, generation: u32) -> Self {
         Self {
-            id,
+            index,
+            generation,
             marker: PhantomData,
         }
     }
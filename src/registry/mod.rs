@@ -1,5 +1,7 @@
+mod assets;
 mod handle;
 mod registry;
 
+pub use assets::{AssetEvent, AssetHandle, Assets};
 pub use handle::Handle;
 pub use registry::Registry;
@@ -1,39 +1,179 @@
 use crate::registry::handle::Handle;
-use std::collections::HashMap;
 
+#[derive(Debug, Clone)]
+struct Slot {
+    generation: u32,
+    dense_index: Option<usize>,
+}
+
+// slot map: handles point into slots, the items themselves are stored densely for fast iteration
 #[derive(Debug)]
 pub struct Registry<T> {
-    pub registry: HashMap<u64, T>,
-    last_id: Option<u64>,
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    items: Vec<T>,
+    handles: Vec<Handle<T>>,
+}
+
+impl<T> Default for Registry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
+
 impl<T> Registry<T> {
     pub fn new() -> Self {
         Registry {
-            registry: HashMap::default(),
-            last_id: None,
+            slots: Vec::new(),
+            free: Vec::new(),
+            items: Vec::new(),
+            handles: Vec::new(),
         }
     }
 
     pub fn add(&mut self, item: T) -> Handle<T> {
-        let id = if let Some(last_id) = self.last_id {
-            last_id + 1
-        } else {
-            0u64
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    dense_index: None,
+                });
+                (self.slots.len() - 1) as u32
+            }
         };
-        self.last_id = Some(id);
-        self.registry.insert(id, item);
-        Handle::new(id)
+        let slot = &mut self.slots[index as usize];
+        slot.dense_index = Some(self.items.len());
+        let handle = Handle::new(index, slot.generation);
+        self.items.push(item);
+        self.handles.push(handle);
+        handle
     }
 
-    pub fn remove(&mut self, handle: Handle<T>) {
-        self.registry.remove(&handle.id);
+    pub fn remove(&mut self, handle: Handle<T>) -> Option<T> {
+        let dense_index = self.dense_index(&handle)?;
+        let slot = &mut self.slots[handle.index as usize];
+        slot.dense_index = None;
+        // a slot whose generation can't be bumped anymore is retired instead of reused
+        if slot.generation < u32::MAX {
+            slot.generation += 1;
+            self.free.push(handle.index);
+        }
+        self.handles.swap_remove(dense_index);
+        let item = self.items.swap_remove(dense_index);
+        if let Some(moved) = self.handles.get(dense_index) {
+            self.slots[moved.index as usize].dense_index = Some(dense_index);
+        }
+        Some(item)
+    }
+
+    fn dense_index(&self, handle: &Handle<T>) -> Option<usize> {
+        let slot = self.slots.get(handle.index as usize)?;
+        if slot.generation == handle.generation {
+            slot.dense_index
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, handle: &Handle<T>) -> bool {
+        self.dense_index(handle).is_some()
     }
 
     pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
-        self.registry.get(&handle.id)
+        let dense_index = self.dense_index(handle)?;
+        self.items.get(dense_index)
     }
 
     pub fn get_mut(&mut self, handle: &Handle<T>) -> Option<&mut T> {
-        self.registry.get_mut(&handle.id)
+        let dense_index = self.dense_index(handle)?;
+        self.items.get_mut(dense_index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn handles(&self) -> impl Iterator<Item = Handle<T>> + '_ {
+        self.handles.iter().copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Handle<T>, &T)> {
+        self.handles.iter().copied().zip(self.items.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Handle<T>, &mut T)> {
+        self.handles.iter().copied().zip(self.items.iter_mut())
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = (Handle<T>, T)> + '_ {
+        for handle in &self.handles {
+            let slot = &mut self.slots[handle.index as usize];
+            slot.dense_index = None;
+            if slot.generation < u32::MAX {
+                slot.generation += 1;
+                self.free.push(handle.index);
+            }
+        }
+        self.handles.drain(..).zip(self.items.drain(..))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registry::{Handle, Registry};
+
+    #[test]
+    fn removed_handle_is_not_aliased() {
+        let mut registry = Registry::new();
+        let first = registry.add("first");
+        assert_eq!(Some("first"), registry.remove(first));
+        let second = registry.add("second");
+        assert_eq!(first.index, second.index);
+        assert_ne!(first, second);
+        assert_eq!(None, registry.get(&first));
+        assert_eq!(None, registry.remove(first));
+        assert_eq!(Some(&"second"), registry.get(&second));
+    }
+
+    #[test]
+    fn remove_keeps_other_handles_valid() {
+        let mut registry = Registry::new();
+        let handles = (0..5).map(|i| registry.add(i)).collect::<Vec<_>>();
+        registry.remove(handles[1]);
+        registry.remove(handles[3]);
+        assert_eq!(3, registry.len());
+        for (i, handle) in handles.iter().enumerate() {
+            if i == 1 || i == 3 {
+                assert!(!registry.contains(handle));
+            } else {
+                assert_eq!(Some(&i), registry.get(handle));
+            }
+        }
+        for (handle, item) in registry.iter_mut() {
+            *item *= 10;
+            assert_eq!(handles[*item / 10], handle);
+        }
+        let mut sum = registry.iter().map(|(_, item)| *item).collect::<Vec<_>>();
+        sum.sort();
+        assert_eq!(vec![0, 20, 40], sum);
+    }
+
+    #[test]
+    fn drain_invalidates_handles() {
+        let mut registry = Registry::new();
+        let a = registry.add('a');
+        let b = registry.add('b');
+        let drained = registry.drain().collect::<Vec<_>>();
+        assert_eq!(vec![(a, 'a'), (b, 'b')], drained);
+        assert!(registry.is_empty());
+        assert!(registry.get(&a).is_none());
+        let c = registry.add('c');
+        assert!(c != a && c != b);
+        assert!(registry.get(&Handle::new(7, 0)).is_none());
     }
 }
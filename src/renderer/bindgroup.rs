@@ -1,13 +1,25 @@
 use crate::{
-    registry::Registry,
+    entity::Entities,
+    mesh::VoxelMeshData,
     renderer::{
-        light::{MAX_NR_OF_DIRECTIONAL_LIGHTS, MAX_NR_OF_POINT_LIGHTS, MAX_NR_OF_SPOT_LIGHTS},
-        Camera, DirectionalProperties, Light, PointProperties, Renderer, SpotProperties,
+        chunk_buffers::{ChunkAllocation, ChunkBuffers},
+        clusters::{Cluster, LightClusters, CLUSTERS_X, CLUSTERS_Y, CLUSTERS_Z, NR_OF_CLUSTERS},
+        error::RendererError,
+        light::MAX_NR_OF_DIRECTIONAL_LIGHTS,
+        shadow::{Cascade, ShadowUniform},
+        storage_buffer::StorageBuffer,
+        Camera, DirectionalProperties, Light, PointProperties, Renderer, ShadowMap, SpotProperties,
     },
+    transform::GlobalTransform,
 };
 use glam::Mat4;
 
-const MAX_NR_OF_INSTANCES: usize = 50000;
+// the first instances belong to the loaded chunks, see MAX_NR_OF_CHUNK_INSTANCES
+pub const MAX_NR_OF_INSTANCES: u32 = 50000;
+// joint matrices of all skinned instances of a frame
+pub const MAX_NR_OF_JOINTS: u32 = 4096;
+// light buffers start with room for this many lights and grow when more are added
+const INITIAL_NR_OF_LIGHTS: usize = 16;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
@@ -20,13 +32,41 @@ pub struct Uniform {
     pub nr_of_directional_lights: u32,
     pub nr_of_spot_lights: u32,
     pub nr_of_point_lights: u32,
+    pub cluster_dimensions: [u32; 4],
+    // near, far and the number of depth slices divided by ln(far / near)
+    pub cluster_depth: [f32; 4],
+    pub viewport_size: [f32; 4],
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Instance {
     pub m: Mat4,
     pub inv_m: Mat4,
+    // multiplies the alpha of the mesh, only the transparent pass blends
+    pub opacity: f32,
+    // the joint matrices of skinned meshes start here in the joint buffer
+    pub first_joint: u32,
+    pub p1: f32,
+    pub p2: f32,
+}
+
+impl Instance {
+    pub fn new(m: Mat4, opacity: f32) -> Self {
+        Self {
+            m,
+            inv_m: m.inverse(),
+            opacity,
+            first_joint: 0,
+            p1: 0.0,
+            p2: 0.0,
+        }
+    }
+
+    pub fn with_first_joint(mut self, first_joint: u32) -> Self {
+        self.first_joint = first_joint;
+        self
+    }
 }
 
 unsafe impl bytemuck::Pod for Uniform {}
@@ -35,12 +75,23 @@ unsafe impl bytemuck::Zeroable for Uniform {}
 unsafe impl bytemuck::Pod for Instance {}
 unsafe impl bytemuck::Zeroable for Instance {}
 
+// spot and point lights have no maximum, every fragment only evaluates the lights of its cluster
+pub struct LightBuffers {
+    pub directional_lights: wgpu::Buffer,
+    pub spot_lights: StorageBuffer,
+    pub point_lights: StorageBuffer,
+    pub clusters: wgpu::Buffer,
+    pub light_indices: StorageBuffer,
+}
+
 pub struct BindGroup {
     pub uniform: wgpu::Buffer,
     pub instances: wgpu::Buffer,
-    pub directional_lights: wgpu::Buffer,
-    pub spot_lights: wgpu::Buffer,
-    pub point_lights: wgpu::Buffer,
+    pub joints: wgpu::Buffer,
+    pub chunks: ChunkBuffers,
+    pub lights: LightBuffers,
+    pub shadows: wgpu::Buffer,
+    pub shadow_map: ShadowMap,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
 }
@@ -60,24 +111,47 @@ impl BindGroup {
             size: (std::mem::size_of::<DirectionalProperties>() * MAX_NR_OF_DIRECTIONAL_LIGHTS) as u64,
             mapped_at_creation: false,
         });
-        let spot_lights = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+        let spot_lights = StorageBuffer::new(
+            &renderer.device,
+            (std::mem::size_of::<SpotProperties>() * INITIAL_NR_OF_LIGHTS) as u64,
+        );
+        let point_lights = StorageBuffer::new(
+            &renderer.device,
+            (std::mem::size_of::<PointProperties>() * INITIAL_NR_OF_LIGHTS) as u64,
+        );
+        let clusters = renderer.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-            size: (std::mem::size_of::<SpotProperties>() * MAX_NR_OF_SPOT_LIGHTS) as u64,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+            size: (std::mem::size_of::<Cluster>() * NR_OF_CLUSTERS) as u64,
             mapped_at_creation: false,
         });
+        let light_indices = StorageBuffer::new(&renderer.device, (std::mem::size_of::<u32>() * NR_OF_CLUSTERS) as u64);
+        let lights = LightBuffers {
+            directional_lights,
+            spot_lights,
+            point_lights,
+            clusters,
+            light_indices,
+        };
 
-        let point_lights = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+        let shadows = renderer.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
             usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
-            size: (std::mem::size_of::<PointProperties>() * MAX_NR_OF_POINT_LIGHTS) as u64,
+            size: std::mem::size_of::<ShadowUniform>() as u64,
             mapped_at_creation: false,
         });
+        let shadow_map = ShadowMap::new(&renderer.device, renderer.settings.shadows.resolution);
 
         let instances = renderer.device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
             usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
-            size: (std::mem::size_of::<Instance>() * MAX_NR_OF_INSTANCES) as u64,
+            size: std::mem::size_of::<Instance>() as u64 * MAX_NR_OF_INSTANCES as u64,
+            mapped_at_creation: false,
+        });
+        let joints = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+            size: std::mem::size_of::<Mat4>() as u64 * MAX_NR_OF_JOINTS as u64,
             mapped_at_creation: false,
         });
 
@@ -107,9 +181,9 @@ impl BindGroup {
                     },
                     wgpu::BindGroupLayoutEntry {
                         binding: 2,
-                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
                             min_binding_size: None,
                             has_dynamic_offset: false,
                         },
@@ -117,17 +191,77 @@ impl BindGroup {
                     },
                     wgpu::BindGroupLayoutEntry {
                         binding: 3,
-                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
                         ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
                             min_binding_size: None,
                             has_dynamic_offset: false,
                         },
                         count: None,
                     },
+                    // the fragment stage is limited to four storage buffers, which the lights use
                     wgpu::BindGroupLayoutEntry {
                         binding: 4,
-                        visibility: wgpu::ShaderStage::VERTEX | wgpu::ShaderStage::FRAGMENT,
+                        visibility: wgpu::ShaderStage::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            sample_type: wgpu::TextureSampleType::Depth,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: true,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 9,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 10,
+                        visibility: wgpu::ShaderStage::VERTEX,
                         ty: wgpu::BindingType::Buffer {
                             ty: wgpu::BufferBindingType::Storage { read_only: true },
                             min_binding_size: None,
@@ -139,69 +273,101 @@ impl BindGroup {
                 label: None,
             });
 
-        let bind_group = renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: uniform.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: directional_lights.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: spot_lights.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: point_lights.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: instances.as_entire_binding(),
-                },
-            ],
-        });
+        let bind_group = create_bind_group(
+            renderer,
+            &bind_group_layout,
+            &uniform,
+            (&instances, &joints),
+            &lights,
+            &shadows,
+            &shadow_map,
+        );
         Self {
             uniform,
             instances,
-            directional_lights,
-            spot_lights,
-            point_lights,
+            joints,
+            chunks: ChunkBuffers::new(renderer),
+            lights,
+            shadows,
+            shadow_map,
             bind_group_layout,
             bind_group,
         }
     }
 
-    pub fn update_instances(&self, renderer: &Renderer, transforms: &[Instance]) {
-        renderer
-            .queue
-            .write_buffer(&self.instances, 0, bytemuck::cast_slice(transforms));
+    // instances past the end of the buffer are dropped
+    pub fn update_instances(&self, renderer: &Renderer, first: u32, transforms: &[Instance]) {
+        let count = transforms.len().min(MAX_NR_OF_INSTANCES.saturating_sub(first) as usize);
+        if count == 0 {
+            return;
+        }
+        renderer.queue.write_buffer(
+            &self.instances,
+            first as u64 * std::mem::size_of::<Instance>() as u64,
+            bytemuck::cast_slice(&transforms[..count]),
+        );
+    }
+
+    // joints past the end of the buffer are dropped, the culling does not draw the instances that use them
+    pub fn update_joints(&self, renderer: &Renderer, joints: &[Mat4]) {
+        let joints = joints
+            .iter()
+            .take(MAX_NR_OF_JOINTS as usize)
+            .map(|joint| joint.to_cols_array())
+            .collect::<Vec<_>>();
+        if !joints.is_empty() {
+            renderer
+                .queue
+                .write_buffer(&self.joints, 0, bytemuck::cast_slice(joints.as_slice()));
+        }
+    }
+
+    // uploads the mesh and the instance of a chunk once, until it is freed
+    pub fn allocate_chunk(
+        &mut self,
+        renderer: &Renderer,
+        mesh_data: &VoxelMeshData,
+        m: Mat4,
+    ) -> Result<ChunkAllocation, RendererError> {
+        self.chunks.allocate(renderer, &self.instances, mesh_data, m)
     }
 
-    pub fn update_uniforms(&self, renderer: &Renderer, lights: &Registry<Light>, camera: &dyn Camera) {
+    pub fn free_chunk(&mut self, allocation: &ChunkAllocation) {
+        self.chunks.free(allocation);
+    }
+
+    // shadows are disabled when there are no cascades
+    pub fn update_uniforms(
+        &mut self,
+        renderer: &Renderer,
+        entities: &Entities,
+        camera: &dyn Camera,
+        cascades: &[Cascade],
+    ) {
         let mut directional_lights = Vec::new();
         let mut spot_lights = Vec::new();
         let mut point_lights = Vec::new();
-        for (_, light) in &lights.registry {
-            match light {
-                Light::Directional(properties) => {
-                    directional_lights.push(*properties);
+        for (entity, light) in entities.query::<Light>() {
+            match light.transformed(entities.get::<GlobalTransform>(&entity)) {
+                // the uniform only has room for the first ones, the first one also casts the shadows
+                Light::Directional(properties) if directional_lights.len() < MAX_NR_OF_DIRECTIONAL_LIGHTS => {
+                    directional_lights.push(properties);
                 }
+                Light::Directional(_) => {}
                 Light::Spot(properties) => {
-                    spot_lights.push(*properties);
+                    spot_lights.push(properties);
                 }
                 Light::Point(properties) => {
-                    point_lights.push(*properties);
+                    point_lights.push(properties);
                 }
             }
         }
-        assert!(directional_lights.len() <= MAX_NR_OF_DIRECTIONAL_LIGHTS);
-        assert!(spot_lights.len() <= MAX_NR_OF_SPOT_LIGHTS);
-        assert!(point_lights.len() <= MAX_NR_OF_POINT_LIGHTS);
+        let clusters = LightClusters::new(
+            camera.get_view(),
+            camera.get_projection(),
+            spot_lights.as_slice(),
+            point_lights.as_slice(),
+        );
 
         let uniform = Uniform {
             v: camera.get_view(),
@@ -217,21 +383,127 @@ impl BindGroup {
             nr_of_directional_lights: directional_lights.len() as u32,
             nr_of_spot_lights: spot_lights.len() as u32,
             nr_of_point_lights: point_lights.len() as u32,
+            cluster_dimensions: [CLUSTERS_X, CLUSTERS_Y, CLUSTERS_Z, 0],
+            cluster_depth: [
+                clusters.near,
+                clusters.far,
+                CLUSTERS_Z as f32 / (clusters.far / clusters.near).ln(),
+                0.0,
+            ],
+            viewport_size: [
+                renderer.swap_chain_descriptor.width as f32,
+                renderer.swap_chain_descriptor.height as f32,
+                0.0,
+                0.0,
+            ],
         };
         renderer
             .queue
             .write_buffer(&self.uniform, 0, bytemuck::cast_slice(&[uniform]));
 
         renderer.queue.write_buffer(
-            &self.directional_lights,
+            &self.lights.directional_lights,
             0,
             bytemuck::cast_slice(directional_lights.as_slice()),
         );
-        renderer
-            .queue
-            .write_buffer(&self.spot_lights, 0, bytemuck::cast_slice(spot_lights.as_slice()));
-        renderer
-            .queue
-            .write_buffer(&self.point_lights, 0, bytemuck::cast_slice(point_lights.as_slice()));
+        renderer.queue.write_buffer(
+            &self.lights.clusters,
+            0,
+            bytemuck::cast_slice(clusters.clusters.as_slice()),
+        );
+        let spot_lights_recreated = self
+            .lights
+            .spot_lights
+            .write(renderer, bytemuck::cast_slice(spot_lights.as_slice()));
+        let point_lights_recreated = self
+            .lights
+            .point_lights
+            .write(renderer, bytemuck::cast_slice(point_lights.as_slice()));
+        let light_indices_recreated = self
+            .lights
+            .light_indices
+            .write(renderer, bytemuck::cast_slice(clusters.light_indices.as_slice()));
+        // the cascades are snapped to the texels of the resolution in the settings, the shadow map follows it
+        let shadow_map_recreated = self.shadow_map.resolution != renderer.settings.shadows.resolution;
+        if shadow_map_recreated {
+            self.shadow_map = ShadowMap::new(&renderer.device, renderer.settings.shadows.resolution);
+        }
+        if spot_lights_recreated || point_lights_recreated || light_indices_recreated || shadow_map_recreated {
+            self.bind_group = create_bind_group(
+                renderer,
+                &self.bind_group_layout,
+                &self.uniform,
+                (&self.instances, &self.joints),
+                &self.lights,
+                &self.shadows,
+                &self.shadow_map,
+            );
+        }
+        renderer.queue.write_buffer(
+            &self.shadows,
+            0,
+            bytemuck::cast_slice(&[ShadowUniform::new(cascades, &renderer.settings.shadows)]),
+        );
     }
 }
+
+fn create_bind_group(
+    renderer: &Renderer,
+    layout: &wgpu::BindGroupLayout,
+    uniform: &wgpu::Buffer,
+    (instances, joints): (&wgpu::Buffer, &wgpu::Buffer),
+    lights: &LightBuffers,
+    shadows: &wgpu::Buffer,
+    shadow_map: &ShadowMap,
+) -> wgpu::BindGroup {
+    renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: lights.directional_lights.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: lights.spot_lights.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: lights.point_lights.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: instances.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: shadows.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(&shadow_map.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::Sampler(&shadow_map.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: lights.clusters.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: lights.light_indices.buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: joints.as_entire_binding(),
+            },
+        ],
+    })
+}
@@ -1,7 +1,12 @@
+use crate::renderer::Frustum;
 use glam::{Mat4, Vec3};
 
 pub trait Camera {
     fn get_position(&self) -> Vec3;
     fn get_projection(&self) -> Mat4;
     fn get_view(&self) -> Mat4;
+
+    fn get_frustum(&self) -> Frustum {
+        Frustum::from_view_proj(&(self.get_projection() * self.get_view()))
+    }
 }
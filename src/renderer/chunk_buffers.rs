@@ -0,0 +1,461 @@
+use crate::{
+    mesh::{Aabb, VoxelMeshData, VoxelVertex},
+    renderer::{bindgroup::Instance, error::RendererError, storage_buffer::StorageBuffer, Renderer},
+};
+use glam::Mat4;
+use std::ops::Range;
+
+// chunks keep their instance for as long as they are loaded, the instances of entities follow these
+pub const MAX_NR_OF_CHUNK_INSTANCES: u32 = 8192;
+// pools start with room for this many vertices and indices and double when a chunk does not fit
+const INITIAL_NR_OF_VERTICES: u32 = 1 << 20;
+const INITIAL_NR_OF_INDICES: u32 = 1 << 22;
+const INITIAL_NR_OF_LONG_INDICES: u32 = 1 << 16;
+
+// arguments of one indexed draw, laid out like the indirect buffer expects them
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct DrawIndexedIndirect {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+unsafe impl bytemuck::Pod for DrawIndexedIndirect {}
+unsafe impl bytemuck::Zeroable for DrawIndexedIndirect {}
+
+// first fit allocator of ranges in a pool, freed ranges are merged with their neighbours
+#[derive(Debug, Clone)]
+pub struct RangeAllocator {
+    // sorted and never adjacent
+    free: Vec<Range<u32>>,
+    capacity: u32,
+}
+
+impl RangeAllocator {
+    pub fn new(capacity: u32) -> Self {
+        Self {
+            free: if capacity > 0 { vec![0..capacity] } else { Vec::new() },
+            capacity,
+        }
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    pub fn allocate(&mut self, size: u32) -> Option<Range<u32>> {
+        if size == 0 {
+            return Some(0..0);
+        }
+        let index = self.free.iter().position(|range| range.end - range.start >= size)?;
+        let start = self.free[index].start;
+        self.free[index].start += size;
+        if self.free[index].is_empty() {
+            self.free.remove(index);
+        }
+        Some(start..start + size)
+    }
+
+    pub fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+        let index = self.free.partition_point(|free| free.start < range.start);
+        let merges_next = index < self.free.len() && self.free[index].start == range.end;
+        let merges_previous = index > 0 && self.free[index - 1].end == range.start;
+        match (merges_previous, merges_next) {
+            (true, true) => {
+                self.free[index - 1].end = self.free[index].end;
+                self.free.remove(index);
+            }
+            (true, false) => self.free[index - 1].end = range.end,
+            (false, true) => self.free[index].start = range.start,
+            (false, false) => self.free.insert(index, range),
+        }
+    }
+
+    // the added space is free
+    pub fn grow(&mut self, capacity: u32) {
+        if capacity > self.capacity {
+            let added = self.capacity..capacity;
+            self.capacity = capacity;
+            self.free(added);
+        }
+    }
+}
+
+// where a chunk mesh lives in the pooled buffers, it has to be freed with ChunkBuffers::free
+#[derive(Debug, Clone)]
+pub struct ChunkAllocation {
+    vertices: Range<u32>,
+    // in the pool of the index format, padded past the indices of the mesh
+    indices: Range<u32>,
+    index_format: wgpu::IndexFormat,
+    nr_of_indices: u32,
+    instance: u32,
+    // the indices after these are of transparent faces
+    nr_of_opaque_indices: u32,
+    // bounds in world space
+    pub aabb: Aabb,
+}
+
+impl ChunkAllocation {
+    pub fn opaque_draw(&self) -> DrawIndexedIndirect {
+        self.draw(self.indices.start..self.indices.start + self.nr_of_opaque_indices)
+    }
+
+    pub fn transparent_draw(&self) -> DrawIndexedIndirect {
+        self.draw(self.indices.start + self.nr_of_opaque_indices..self.indices.start + self.nr_of_indices)
+    }
+
+    pub fn index_format(&self) -> wgpu::IndexFormat {
+        self.index_format
+    }
+
+    fn draw(&self, indices: Range<u32>) -> DrawIndexedIndirect {
+        DrawIndexedIndirect {
+            index_count: indices.end - indices.start,
+            instance_count: 1,
+            first_index: indices.start,
+            base_vertex: self.vertices.start as i32,
+            first_instance: self.instance,
+        }
+    }
+}
+
+// draws of chunks from both index pools, in the indirect buffer the 16 bit ones come first
+#[derive(Debug, Clone, Default)]
+pub struct ChunkDraws {
+    short: Vec<DrawIndexedIndirect>,
+    long: Vec<DrawIndexedIndirect>,
+}
+
+impl ChunkDraws {
+    pub fn push(&mut self, index_format: wgpu::IndexFormat, draw: DrawIndexedIndirect) {
+        match index_format {
+            wgpu::IndexFormat::Uint16 => self.short.push(draw),
+            wgpu::IndexFormat::Uint32 => self.long.push(draw),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.short.len() + self.long.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // in the order write_draws expects them
+    pub fn iter(&self) -> impl Iterator<Item = &DrawIndexedIndirect> {
+        self.short.iter().chain(self.long.iter())
+    }
+}
+
+// a buffer with sub allocations that is copied into a bigger one when it is full
+struct Pool {
+    buffer: wgpu::Buffer,
+    allocator: RangeAllocator,
+    element_size: u64,
+    usage: wgpu::BufferUsage,
+}
+
+impl Pool {
+    fn new(device: &wgpu::Device, capacity: u32, element_size: u64, usage: wgpu::BufferUsage) -> Self {
+        let usage = usage | wgpu::BufferUsage::COPY_DST | wgpu::BufferUsage::COPY_SRC;
+        Self {
+            buffer: create_pool_buffer(device, capacity as u64 * element_size, usage),
+            allocator: RangeAllocator::new(capacity),
+            element_size,
+            usage,
+        }
+    }
+
+    // the range is padded like the write, so the next one starts aligned as well
+    fn allocate(&mut self, renderer: &Renderer, data: &[u8]) -> Range<u32> {
+        let padding = aligned_size(data.len() as u64) - data.len() as u64;
+        let size = ((data.len() as u64 + padding) / self.element_size) as u32;
+        let range = match self.allocator.allocate(size) {
+            Some(range) => range,
+            None => {
+                let mut capacity = self.allocator.capacity().max(1);
+                while capacity - self.allocator.capacity() < size {
+                    capacity *= 2;
+                }
+                self.grow(renderer, capacity);
+                self.allocator.allocate(size).unwrap()
+            }
+        };
+        if !range.is_empty() {
+            let offset = range.start as u64 * self.element_size;
+            if padding > 0 {
+                let mut padded = data.to_vec();
+                padded.resize(data.len() + padding as usize, 0);
+                renderer.queue.write_buffer(&self.buffer, offset, &padded);
+            } else {
+                renderer.queue.write_buffer(&self.buffer, offset, data);
+            }
+        }
+        range
+    }
+
+    fn grow(&mut self, renderer: &Renderer, capacity: u32) {
+        let buffer = create_pool_buffer(&renderer.device, capacity as u64 * self.element_size, self.usage);
+        let mut encoder = renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(
+            &self.buffer,
+            0,
+            &buffer,
+            0,
+            self.allocator.capacity() as u64 * self.element_size,
+        );
+        renderer.queue.submit(std::iter::once(encoder.finish()));
+        self.buffer = buffer;
+        self.allocator.grow(capacity);
+    }
+}
+
+// buffer writes are a multiple of 4 bytes
+fn aligned_size(size: u64) -> u64 {
+    let alignment = wgpu::COPY_BUFFER_ALIGNMENT;
+    size.div_ceil(alignment) * alignment
+}
+
+fn create_pool_buffer(device: &wgpu::Device, size: u64, usage: wgpu::BufferUsage) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        usage,
+        size,
+        mapped_at_creation: false,
+    })
+}
+
+// chunk meshes stay on the gpu in pooled buffers, so loading a chunk is the only time its data is uploaded
+pub struct ChunkBuffers {
+    vertices: Pool,
+    // meshes with at most 65536 vertices use 16 bit indices, the indices are relative to the first vertex
+    short_indices: Pool,
+    indices: Pool,
+    instances: RangeAllocator,
+    // culled draws of the frame, drawn with one call when multi draw indirect is supported
+    indirect: StorageBuffer,
+    multi_draw_indirect: bool,
+}
+
+impl ChunkBuffers {
+    pub fn new(renderer: &Renderer) -> Self {
+        Self {
+            vertices: Pool::new(
+                &renderer.device,
+                INITIAL_NR_OF_VERTICES,
+                std::mem::size_of::<VoxelVertex>() as u64,
+                wgpu::BufferUsage::VERTEX,
+            ),
+            short_indices: Pool::new(
+                &renderer.device,
+                INITIAL_NR_OF_INDICES,
+                std::mem::size_of::<u16>() as u64,
+                wgpu::BufferUsage::INDEX,
+            ),
+            // most chunk meshes are small, the pool of long indices grows when one is not
+            indices: Pool::new(
+                &renderer.device,
+                INITIAL_NR_OF_LONG_INDICES,
+                std::mem::size_of::<u32>() as u64,
+                wgpu::BufferUsage::INDEX,
+            ),
+            instances: RangeAllocator::new(MAX_NR_OF_CHUNK_INSTANCES),
+            indirect: StorageBuffer::new_indirect(
+                &renderer.device,
+                std::mem::size_of::<DrawIndexedIndirect>() as u64 * 1024,
+            ),
+            multi_draw_indirect: renderer.device.features().contains(wgpu::Features::MULTI_DRAW_INDIRECT),
+        }
+    }
+
+    // the instance of the chunk is written once, instances is the buffer of the scene bind group
+    // the instances of entities follow the ones of the chunks, so these can not grow like the pools
+    pub fn allocate(
+        &mut self,
+        renderer: &Renderer,
+        instances: &wgpu::Buffer,
+        mesh_data: &VoxelMeshData,
+        m: Mat4,
+    ) -> Result<ChunkAllocation, RendererError> {
+        let instance = self
+            .instances
+            .allocate(1)
+            .ok_or(RendererError::TooManyChunks(MAX_NR_OF_CHUNK_INSTANCES))?
+            .start;
+        let vertices = self
+            .vertices
+            .allocate(renderer, bytemuck::cast_slice(mesh_data.vertices.as_slice()));
+        let (indices, index_format) = if mesh_data.has_short_indices() {
+            let short_indices = mesh_data.indices.iter().map(|i| *i as u16).collect::<Vec<_>>();
+            (
+                self.short_indices
+                    .allocate(renderer, bytemuck::cast_slice(short_indices.as_slice())),
+                wgpu::IndexFormat::Uint16,
+            )
+        } else {
+            (
+                self.indices
+                    .allocate(renderer, bytemuck::cast_slice(mesh_data.indices.as_slice())),
+                wgpu::IndexFormat::Uint32,
+            )
+        };
+        renderer.queue.write_buffer(
+            instances,
+            instance as u64 * std::mem::size_of::<Instance>() as u64,
+            bytemuck::cast_slice(&[Instance::new(m, 1.0)]),
+        );
+        Ok(ChunkAllocation {
+            vertices,
+            indices,
+            index_format,
+            nr_of_indices: mesh_data.indices.len() as u32,
+            instance,
+            nr_of_opaque_indices: mesh_data.nr_of_opaque_indices as u32,
+            aabb: mesh_data.aabb().transformed(&m),
+        })
+    }
+
+    pub fn free(&mut self, allocation: &ChunkAllocation) {
+        self.vertices.allocator.free(allocation.vertices.clone());
+        match allocation.index_format {
+            wgpu::IndexFormat::Uint16 => self.short_indices.allocator.free(allocation.indices.clone()),
+            wgpu::IndexFormat::Uint32 => self.indices.allocator.free(allocation.indices.clone()),
+        }
+        self.instances.free(allocation.instance..allocation.instance + 1);
+    }
+
+    pub fn write_draws(&mut self, renderer: &Renderer, draws: &[DrawIndexedIndirect]) {
+        if self.multi_draw_indirect && !draws.is_empty() {
+            self.indirect.write(renderer, bytemuck::cast_slice(draws));
+        }
+    }
+
+    pub fn bind<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, index_format: wgpu::IndexFormat) {
+        let indices = match index_format {
+            wgpu::IndexFormat::Uint16 => &self.short_indices,
+            wgpu::IndexFormat::Uint32 => &self.indices,
+        };
+        render_pass.set_vertex_buffer(0, self.vertices.buffer.slice(..));
+        render_pass.set_index_buffer(indices.buffer.slice(..), index_format);
+    }
+
+    // draws were last written with write_draws starting at first, the pools of both index formats are bound
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, draws: &ChunkDraws, first: u32) {
+        let first_long = first + draws.short.len() as u32;
+        for &(index_format, draws, first) in &[
+            (wgpu::IndexFormat::Uint16, &draws.short, first),
+            (wgpu::IndexFormat::Uint32, &draws.long, first_long),
+        ] {
+            if draws.is_empty() {
+                continue;
+            }
+            self.bind(render_pass, index_format);
+            if self.multi_draw_indirect {
+                let offset = first as u64 * std::mem::size_of::<DrawIndexedIndirect>() as u64;
+                render_pass.multi_draw_indexed_indirect(&self.indirect.buffer, offset, draws.len() as u32);
+            } else {
+                for draw in draws {
+                    draw_indexed(render_pass, draw);
+                }
+            }
+        }
+    }
+}
+
+pub fn draw_indexed(render_pass: &mut wgpu::RenderPass, draw: &DrawIndexedIndirect) {
+    render_pass.draw_indexed(
+        draw.first_index..draw.first_index + draw.index_count,
+        draw.base_vertex,
+        draw.first_instance..draw.first_instance + draw.instance_count,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        mesh::Aabb,
+        renderer::{chunk_buffers::aligned_size, ChunkAllocation, ChunkDraws, DrawIndexedIndirect, RangeAllocator},
+    };
+    use glam::Vec3;
+
+    #[test]
+    fn freed_ranges_are_reused() {
+        let mut allocator = RangeAllocator::new(10);
+        assert_eq!(Some(0..4), allocator.allocate(4));
+        assert_eq!(Some(4..8), allocator.allocate(4));
+        assert_eq!(None, allocator.allocate(4));
+        allocator.free(0..4);
+        assert_eq!(Some(0..3), allocator.allocate(3));
+        assert_eq!(Some(8..10), allocator.allocate(2));
+        assert_eq!(Some(3..4), allocator.allocate(1));
+    }
+
+    #[test]
+    fn neighbouring_free_ranges_are_merged() {
+        let mut allocator = RangeAllocator::new(9);
+        let ranges = (0..3).map(|_| allocator.allocate(3).unwrap()).collect::<Vec<_>>();
+        allocator.free(ranges[0].clone());
+        allocator.free(ranges[2].clone());
+        assert_eq!(None, allocator.allocate(6));
+        allocator.free(ranges[1].clone());
+        assert_eq!(Some(0..9), allocator.allocate(9));
+    }
+
+    #[test]
+    fn grown_space_is_free() {
+        let mut allocator = RangeAllocator::new(4);
+        allocator.allocate(2).unwrap();
+        assert_eq!(None, allocator.allocate(4));
+        allocator.grow(8);
+        assert_eq!(8, allocator.capacity());
+        assert_eq!(Some(2..6), allocator.allocate(4));
+    }
+
+    #[test]
+    fn padding_of_short_indices_is_not_drawn() {
+        // 9 short indices take 18 bytes, padded to 20
+        assert_eq!(20, aligned_size(9 * 2));
+        assert_eq!(24, aligned_size(24));
+        let allocation = ChunkAllocation {
+            vertices: 100..110,
+            indices: 40..50,
+            index_format: wgpu::IndexFormat::Uint16,
+            nr_of_indices: 9,
+            instance: 3,
+            nr_of_opaque_indices: 6,
+            aabb: Aabb::new(Vec3::zero(), Vec3::one()),
+        };
+        let opaque = allocation.opaque_draw();
+        assert_eq!((40, 6, 100), (opaque.first_index, opaque.index_count, opaque.base_vertex));
+        let transparent = allocation.transparent_draw();
+        assert_eq!((46, 3), (transparent.first_index, transparent.index_count));
+    }
+
+    #[test]
+    fn short_chunk_draws_come_first() {
+        let draw = |first_instance| DrawIndexedIndirect {
+            first_instance,
+            ..Default::default()
+        };
+        let mut draws = ChunkDraws::default();
+        draws.push(wgpu::IndexFormat::Uint32, draw(0));
+        draws.push(wgpu::IndexFormat::Uint16, draw(1));
+        draws.push(wgpu::IndexFormat::Uint32, draw(2));
+        draws.push(wgpu::IndexFormat::Uint16, draw(3));
+        assert_eq!(4, draws.len());
+        assert_eq!(
+            vec![1, 3, 0, 2],
+            draws.iter().map(|draw| draw.first_instance).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use crate::renderer::{PointProperties, SpotProperties};
+use glam::{Mat4, Vec3};
+
+// the view frustum is split in tiles on screen and exponential slices in depth
+pub const CLUSTERS_X: u32 = 16;
+pub const CLUSTERS_Y: u32 = 9;
+pub const CLUSTERS_Z: u32 = 24;
+pub const NR_OF_CLUSTERS: usize = (CLUSTERS_X * CLUSTERS_Y * CLUSTERS_Z) as usize;
+
+// the light indices of a cluster start at offset, spot lights first and point lights after them
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Cluster {
+    pub offset: u32,
+    pub nr_of_spot_lights: u32,
+    pub nr_of_point_lights: u32,
+    pub p0: u32,
+}
+
+unsafe impl bytemuck::Pod for Cluster {}
+unsafe impl bytemuck::Zeroable for Cluster {}
+
+pub struct LightClusters {
+    pub near: f32,
+    pub far: f32,
+    pub clusters: Vec<Cluster>,
+    pub light_indices: Vec<u32>,
+}
+
+impl LightClusters {
+    // lights are bounded by a sphere of their range, the cone of a spot light is ignored
+    pub fn new(view: Mat4, projection: Mat4, spot_lights: &[SpotProperties], point_lights: &[PointProperties]) -> Self {
+        let inverse_projection = projection.inverse();
+        let near = -inverse_projection.transform_point3(Vec3::new(0.0, 0.0, 0.0)).z;
+        let far = -inverse_projection.transform_point3(Vec3::new(0.0, 0.0, 1.0)).z;
+        let mut spot_indices = vec![Vec::new(); NR_OF_CLUSTERS];
+        let mut point_indices = vec![Vec::new(); NR_OF_CLUSTERS];
+        for (i, light) in spot_lights.iter().enumerate() {
+            let position = view.transform_point3(Vec3::new(light.position[0], light.position[1], light.position[2]));
+            for cluster in clusters_of_sphere(projection, near, far, position, light.range()) {
+                spot_indices[cluster].push(i as u32);
+            }
+        }
+        for (i, light) in point_lights.iter().enumerate() {
+            let position = view.transform_point3(Vec3::new(light.position[0], light.position[1], light.position[2]));
+            for cluster in clusters_of_sphere(projection, near, far, position, light.range()) {
+                point_indices[cluster].push(i as u32);
+            }
+        }
+
+        let mut clusters = Vec::with_capacity(NR_OF_CLUSTERS);
+        let mut light_indices = Vec::new();
+        for (spots, points) in spot_indices.iter().zip(point_indices.iter()) {
+            clusters.push(Cluster {
+                offset: light_indices.len() as u32,
+                nr_of_spot_lights: spots.len() as u32,
+                nr_of_point_lights: points.len() as u32,
+                p0: 0,
+            });
+            light_indices.extend_from_slice(spots);
+            light_indices.extend_from_slice(points);
+        }
+        Self {
+            near,
+            far,
+            clusters,
+            light_indices,
+        }
+    }
+
+    // returns the spot and point light indices of a cluster
+    pub fn lights(&self, x: u32, y: u32, z: u32) -> (&[u32], &[u32]) {
+        let cluster = &self.clusters[cluster_index(x, y, z)];
+        let spots_end = (cluster.offset + cluster.nr_of_spot_lights) as usize;
+        let points_end = spots_end + cluster.nr_of_point_lights as usize;
+        (
+            &self.light_indices[cluster.offset as usize..spots_end],
+            &self.light_indices[spots_end..points_end],
+        )
+    }
+}
+
+// x and y are counted from the top left of the screen, like fragment coordinates
+pub fn cluster_index(x: u32, y: u32, z: u32) -> usize {
+    ((z * CLUSTERS_Y + y) * CLUSTERS_X + x) as usize
+}
+
+// same computation as in the shader, slices grow exponentially with the distance from the camera
+pub fn depth_slice(near: f32, far: f32, view_depth: f32) -> u32 {
+    let slice = (view_depth / near).ln() / (far / near).ln() * CLUSTERS_Z as f32;
+    (slice.max(0.0) as u32).min(CLUSTERS_Z - 1)
+}
+
+// indices of every cluster touched by the bounding box of a view space sphere
+fn clusters_of_sphere(projection: Mat4, near: f32, far: f32, center: Vec3, radius: f32) -> Vec<usize> {
+    let radius = radius.min(far);
+    let depth_min = -center.z - radius;
+    let depth_max = -center.z + radius;
+    if depth_max < near || depth_min > far {
+        return Vec::new();
+    }
+    let depth_min = depth_min.max(near);
+    let depth_max = depth_max.min(far);
+    // the box clipped to the depth range projects inside the convex hull of its projected corners
+    let mut min = [f32::MAX; 2];
+    let mut max = [f32::MIN; 2];
+    for x in &[center.x - radius, center.x + radius] {
+        for y in &[center.y - radius, center.y + radius] {
+            for depth in &[depth_min, depth_max] {
+                let p = projection.transform_point3(Vec3::new(*x, *y, -*depth));
+                min = [min[0].min(p.x), min[1].min(p.y)];
+                max = [max[0].max(p.x), max[1].max(p.y)];
+            }
+        }
+    }
+    if max[0] < -1.0 || min[0] > 1.0 || max[1] < -1.0 || min[1] > 1.0 {
+        return Vec::new();
+    }
+    let tile = |ndc: f32, count: u32| (((ndc + 1.0) * 0.5 * count as f32).max(0.0) as u32).min(count - 1);
+    let (x_min, x_max) = (tile(min[0], CLUSTERS_X), tile(max[0], CLUSTERS_X));
+    // ndc y points up, tiles are counted from the top
+    let (y_min, y_max) = (tile(-max[1], CLUSTERS_Y), tile(-min[1], CLUSTERS_Y));
+    let (z_min, z_max) = (depth_slice(near, far, depth_min), depth_slice(near, far, depth_max));
+    let mut clusters = Vec::new();
+    for z in z_min..=z_max {
+        for y in y_min..=y_max {
+            for x in x_min..=x_max {
+                clusters.push(cluster_index(x, y, z));
+            }
+        }
+    }
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::{
+        cluster_index, depth_slice, LightClusters, PointProperties, SpotProperties, CLUSTERS_X, CLUSTERS_Y, CLUSTERS_Z,
+        NR_OF_CLUSTERS,
+    };
+    use glam::{Mat4, Vec3};
+
+    fn camera() -> (Mat4, Mat4) {
+        (
+            Mat4::look_at_rh(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::unit_y()),
+            Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 16.0 / 9.0, 0.1, 1000.0),
+        )
+    }
+
+    // a light that reaches about one meter
+    fn point_light(position: Vec3) -> PointProperties {
+        PointProperties {
+            constant: 1.0,
+            linear: 0.0,
+            quadratic: 255.0,
+            ..PointProperties::new(position.extend(1.0).into())
+        }
+    }
+
+    fn clusters_with_light(clusters: &LightClusters, light: u32) -> Vec<(u32, u32, u32)> {
+        let mut found = Vec::new();
+        for z in 0..CLUSTERS_Z {
+            for y in 0..CLUSTERS_Y {
+                for x in 0..CLUSTERS_X {
+                    if clusters.lights(x, y, z).1.contains(&light) {
+                        found.push((x, y, z));
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn depth_slices_are_exponential() {
+        assert_eq!(0, depth_slice(0.1, 1000.0, 0.05));
+        assert_eq!(0, depth_slice(0.1, 1000.0, 0.1));
+        assert_eq!(CLUSTERS_Z / 2, depth_slice(0.1, 1000.0, 10.5));
+        assert_eq!(CLUSTERS_Z / 4, depth_slice(0.1, 1000.0, 1.1));
+        assert_eq!(CLUSTERS_Z - 1, depth_slice(0.1, 1000.0, 1000.0));
+        assert_eq!(CLUSTERS_Z - 1, depth_slice(0.1, 1000.0, 5000.0));
+    }
+
+    #[test]
+    fn lights_are_assigned_to_the_clusters_they_reach() {
+        let (view, projection) = camera();
+        let point_lights = [
+            // center of the screen
+            point_light(Vec3::new(0.0, 0.2, -10.0)),
+            // top left corner, partly outside the screen
+            point_light(Vec3::new(-17.5, 10.0, -10.0)),
+            // behind the camera
+            point_light(Vec3::new(0.0, 0.0, 10.0)),
+        ];
+        let clusters = LightClusters::new(view, projection, &[], &point_lights);
+        assert_eq!(NR_OF_CLUSTERS, clusters.clusters.len());
+
+        let center = clusters_with_light(&clusters, 0);
+        assert!(center.contains(&(CLUSTERS_X / 2, CLUSTERS_Y / 2, depth_slice(0.1, 1000.0, 10.0))));
+        assert!(center
+            .iter()
+            .all(|(x, y, _)| (7..=8).contains(x) && (3..=4).contains(y)));
+        assert!(center
+            .iter()
+            .all(|(_, _, z)| (depth_slice(0.1, 1000.0, 9.0)..=depth_slice(0.1, 1000.0, 11.0)).contains(z)));
+
+        let corner = clusters_with_light(&clusters, 1);
+        assert!(corner.contains(&(0, 0, depth_slice(0.1, 1000.0, 10.0))));
+        assert!(corner.iter().all(|(x, y, _)| *x <= 1 && *y <= 1));
+
+        assert!(clusters_with_light(&clusters, 2).is_empty());
+        // every light index is stored once per cluster it reaches
+        assert_eq!(center.len() + corner.len(), clusters.light_indices.len());
+    }
+
+    #[test]
+    fn spot_lights_come_before_point_lights() {
+        let (view, projection) = camera();
+        let spot = SpotProperties {
+            linear: 0.0,
+            quadratic: 255.0,
+            ..SpotProperties::new([0.0, 0.0, -10.0, 1.0], [0.0, 0.0, -1.0, 0.0])
+        };
+        let clusters = LightClusters::new(
+            view,
+            projection,
+            &[spot, spot],
+            &[point_light(Vec3::new(0.0, 0.0, -10.0))],
+        );
+        let (spots, points) = clusters.lights(CLUSTERS_X / 2, CLUSTERS_Y / 2, depth_slice(0.1, 1000.0, 10.0));
+        assert_eq!(&[0, 1], spots);
+        assert_eq!(&[0], points);
+        let cluster = clusters.clusters[cluster_index(0, 0, 0)];
+        assert_eq!((0, 0), (cluster.nr_of_spot_lights, cluster.nr_of_point_lights));
+    }
+}
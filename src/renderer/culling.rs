@@ -0,0 +1,316 @@
+use crate::{
+    animation::joint_matrices,
+    entity::Entities,
+    registry::{AssetHandle, Assets, Handle},
+    renderer::{
+        bindgroup::Instance, cascades, mesh::Mesh, ChunkDraws, DrawIndexedIndirect, Frame, Frustum, Light, Opacity,
+        MAX_NR_OF_CHUNK_INSTANCES, MAX_NR_OF_INSTANCES, MAX_NR_OF_JOINTS,
+    },
+    transform::GlobalTransform,
+};
+use glam::Vec3;
+use std::{cmp::Ordering, collections::HashMap, ops::Range};
+
+// instances of one mesh, chunks are drawn from the chunk buffers
+pub(crate) struct Draw {
+    pub mesh: Handle<Mesh>,
+    pub instances: Range<u32>,
+    pub indices: Range<u32>,
+}
+
+// transparent draws of entities and chunks are sorted together
+pub(crate) enum TransparentDraw {
+    Mesh(Draw),
+    Chunk(DrawIndexedIndirect, wgpu::IndexFormat),
+}
+
+// shadow casters of one cascade, culled against the view projection of the cascade
+#[derive(Default)]
+pub(crate) struct CasterDraws {
+    pub draws: Vec<Draw>,
+    pub chunk_draws: ChunkDraws,
+    // the chunk draws follow the ones of the camera in the indirect buffer
+    pub first_chunk_draw: u32,
+}
+
+// instances of entities and chunks that were drawn or culled in a frame
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct RenderStats {
+    pub drawn: u32,
+    pub culled: u32,
+}
+
+// culls the scene for the camera and the shadow casters for every cascade before the passes are prepared
+#[derive(Default)]
+pub(crate) struct Culling {
+    // instances of the entities that were uploaded last frame
+    instances: Vec<Instance>,
+}
+
+impl Culling {
+    pub fn cull(&mut self, frame: &mut Frame, shadows: bool) {
+        let (renderer, entities, meshes, camera) = (
+            frame.renderer,
+            frame.scene.entities,
+            frame.scene.meshes,
+            frame.scene.camera,
+        );
+        let shadow_settings = renderer.settings.shadows;
+        let cascades = match shadow_light_direction(entities) {
+            Some(light_direction) if shadows && shadow_settings.enabled => cascades(
+                camera.get_view(),
+                camera.get_projection(),
+                light_direction,
+                &shadow_settings,
+            ),
+            _ => Vec::new(),
+        };
+        frame.bind_group.update_uniforms(renderer, entities, camera, &cascades);
+        let frustum = camera.get_frustum();
+        // casters outside the view frustum can still throw a shadow into it, every cascade culls its own
+        let caster_frusta = cascades
+            .iter()
+            .map(|cascade| Frustum::from_view_proj(&cascade.view_proj))
+            .collect::<Vec<_>>();
+        let mut stats = RenderStats::default();
+        let camera_position = camera.get_position();
+        // entities are grouped by mesh in one pass, transparent ones get their own instance and cast no shadows
+        let mut opaque = HashMap::<Handle<Mesh>, Vec<Instance>>::new();
+        let mut casters = caster_frusta
+            .iter()
+            .map(|_| HashMap::<Handle<Mesh>, Vec<Instance>>::new())
+            .collect::<Vec<_>>();
+        let mut transparent = Vec::new();
+        let mut joints = Vec::new();
+        for (entity, mesh_handle, transform) in entities.query2::<AssetHandle<Mesh>, GlobalTransform>() {
+            let mesh = match meshes.get(mesh_handle) {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+            let m = transform.0.to_matrix();
+            let aabb = mesh.aabb.transformed(&m);
+            let opacity = entities.get::<Opacity>(&entity).map_or(1.0, |opacity| opacity.0);
+            let mut instance = Instance::new(mesh.instance_matrix(m), opacity.clamp(0.0, 1.0));
+            // skinned meshes are posed by the Skin of the entity, without it or room for its joints they are not drawn
+            if mesh.skinned {
+                match joint_matrices(entities, &entity) {
+                    Some(matrices) if joints.len() + matrices.len() <= MAX_NR_OF_JOINTS as usize => {
+                        instance = instance.with_first_joint(joints.len() as u32);
+                        joints.extend(matrices);
+                    }
+                    _ => continue,
+                }
+            }
+            // the faces of transparent voxel colors come after the opaque ones and are blended on their own
+            let has_opaque_faces = mesh.nr_of_opaque_indices > 0;
+            if opacity >= 1.0 && has_opaque_faces {
+                for (caster_frustum, casters) in caster_frusta.iter().zip(casters.iter_mut()) {
+                    if caster_frustum.intersects_aabb(&aabb) {
+                        casters.entry(**mesh_handle).or_default().push(instance);
+                    }
+                }
+            }
+            if !frustum.intersects_aabb(&aabb) {
+                stats.culled += 1;
+                continue;
+            }
+            stats.drawn += 1;
+            let distance = aabb.center().distance_squared(camera_position);
+            if opacity < 1.0 {
+                transparent.push((
+                    distance,
+                    Some((**mesh_handle, 0..mesh.len, instance)),
+                    Default::default(),
+                ));
+                continue;
+            }
+            if has_opaque_faces {
+                opaque.entry(**mesh_handle).or_default().push(instance);
+            }
+            if mesh.nr_of_opaque_indices < mesh.len {
+                transparent.push((
+                    distance,
+                    Some((**mesh_handle, mesh.nr_of_opaque_indices..mesh.len, instance)),
+                    Default::default(),
+                ));
+            }
+        }
+        // the chunk meshes and instances are already on the gpu, only the visible ones are drawn
+        let mut chunk_draws = ChunkDraws::default();
+        let mut chunk_casters = vec![ChunkDraws::default(); caster_frusta.len()];
+        for allocation in frame
+            .scene
+            .world
+            .get_within_view_chunks([frame.scene.position[0], frame.scene.position[2]])
+        {
+            let opaque_draw = allocation.opaque_draw();
+            if opaque_draw.index_count > 0 {
+                for (caster_frustum, casters) in caster_frusta.iter().zip(chunk_casters.iter_mut()) {
+                    if caster_frustum.intersects_aabb(&allocation.aabb) {
+                        casters.push(allocation.index_format(), opaque_draw);
+                    }
+                }
+            }
+            if !frustum.intersects_aabb(&allocation.aabb) {
+                stats.culled += 1;
+                continue;
+            }
+            stats.drawn += 1;
+            if opaque_draw.index_count > 0 {
+                chunk_draws.push(allocation.index_format(), opaque_draw);
+            }
+            let draw = allocation.transparent_draw();
+            if draw.index_count > 0 {
+                transparent.push((
+                    allocation.aabb.center().distance_squared(camera_position),
+                    None,
+                    (draw, allocation.index_format()),
+                ));
+            }
+        }
+
+        let mut transforms = Vec::new();
+        let draws = instanced_draws(meshes, opaque, &mut transforms);
+
+        // back to front, so every blended surface lands over the ones behind it
+        transparent.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        let transparent_draws = transparent
+            .into_iter()
+            .filter_map(|(_, mesh, draw)| match mesh {
+                Some((mesh, indices, instance)) => {
+                    let first = MAX_NR_OF_CHUNK_INSTANCES + transforms.len() as u32;
+                    transforms.push(instance);
+                    within_instance_buffer(first..first + 1).map(|instances| {
+                        TransparentDraw::Mesh(Draw {
+                            mesh,
+                            instances,
+                            indices,
+                        })
+                    })
+                }
+                None => Some(TransparentDraw::Chunk(draw.0, draw.1)),
+            })
+            .collect();
+
+        let mut indirect_draws = chunk_draws.iter().copied().collect::<Vec<_>>();
+        let casters = casters
+            .into_iter()
+            .zip(chunk_casters)
+            .map(|(instances, chunk_draws)| {
+                let first_chunk_draw = indirect_draws.len() as u32;
+                indirect_draws.extend(chunk_draws.iter());
+                CasterDraws {
+                    draws: instanced_draws(meshes, instances, &mut transforms),
+                    chunk_draws,
+                    first_chunk_draw,
+                }
+            })
+            .collect();
+
+        // the draws stop at the end of the instance buffer, so do the instances
+        transforms.truncate((MAX_NR_OF_INSTANCES - MAX_NR_OF_CHUNK_INSTANCES) as usize);
+        // only the instances that changed since the last frame are uploaded
+        if let Some(changed) = changed_range(&self.instances, &transforms) {
+            frame.bind_group.update_instances(
+                renderer,
+                MAX_NR_OF_CHUNK_INSTANCES + changed.start as u32,
+                &transforms[changed],
+            );
+        }
+        self.instances = transforms;
+        frame.bind_group.update_joints(renderer, &joints);
+        frame.bind_group.chunks.write_draws(renderer, &indirect_draws);
+        frame.stats = stats;
+        frame.draws = draws;
+        frame.chunk_draws = chunk_draws;
+        frame.transparent_draws = transparent_draws;
+        frame.casters = casters;
+        frame.cascades = cascades;
+    }
+}
+
+// one draw of the opaque faces per mesh in the order of the assets, the instances are appended to the ones of the frame
+fn instanced_draws(
+    meshes: &Assets<Mesh>,
+    mut instances: HashMap<Handle<Mesh>, Vec<Instance>>,
+    transforms: &mut Vec<Instance>,
+) -> Vec<Draw> {
+    let mut draws = Vec::new();
+    for mesh_handle in meshes.handles() {
+        if let Some(mesh_instances) = instances.remove(&mesh_handle) {
+            let start = MAX_NR_OF_CHUNK_INSTANCES + transforms.len() as u32;
+            transforms.extend(mesh_instances);
+            if let Some(instances) = within_instance_buffer(start..MAX_NR_OF_CHUNK_INSTANCES + transforms.len() as u32)
+            {
+                draws.push(Draw {
+                    mesh: mesh_handle,
+                    instances,
+                    indices: 0..meshes.get(&mesh_handle).unwrap().nr_of_opaque_indices,
+                });
+            }
+        }
+    }
+    draws
+}
+
+// instances past the end of the instance buffer are not drawn
+fn within_instance_buffer(instances: Range<u32>) -> Option<Range<u32>> {
+    let end = instances.end.min(MAX_NR_OF_INSTANCES);
+    if instances.start < end {
+        Some(instances.start..end)
+    } else {
+        None
+    }
+}
+
+// range of the new items that differ from the old ones, items past the end of the old ones differ
+fn changed_range<T: PartialEq>(old: &[T], new: &[T]) -> Option<Range<usize>> {
+    let differs = |i: &usize| old.get(*i) != Some(&new[*i]);
+    let start = (0..new.len()).find(differs)?;
+    let end = (start..new.len()).rev().find(differs).unwrap() + 1;
+    Some(start..end)
+}
+
+// the first directional light casts the shadows
+fn shadow_light_direction(entities: &Entities) -> Option<Vec3> {
+    entities.query::<Light>().find_map(|(entity, light)| {
+        match light.transformed(entities.get::<GlobalTransform>(&entity)) {
+            Light::Directional(properties) => Some(Vec3::new(
+                properties.direction[0],
+                properties.direction[1],
+                properties.direction[2],
+            )),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::{
+        culling::{changed_range, within_instance_buffer},
+        MAX_NR_OF_INSTANCES,
+    };
+
+    #[test]
+    fn only_changed_instances_are_uploaded() {
+        assert_eq!(None, changed_range(&[1, 2, 3], &[1, 2, 3]));
+        assert_eq!(None, changed_range(&[1, 2, 3], &[1, 2]));
+        assert_eq!(Some(1..3), changed_range(&[1, 2, 3, 4], &[1, 5, 6, 4]));
+        assert_eq!(Some(2..4), changed_range(&[1, 2], &[1, 2, 3, 4]));
+        assert_eq!(Some(0..2), changed_range(&[], &[1, 2]));
+    }
+
+    #[test]
+    fn instances_past_the_buffer_are_not_drawn() {
+        assert_eq!(Some(10..20), within_instance_buffer(10..20));
+        assert_eq!(
+            Some(MAX_NR_OF_INSTANCES - 2..MAX_NR_OF_INSTANCES),
+            within_instance_buffer(MAX_NR_OF_INSTANCES - 2..MAX_NR_OF_INSTANCES + 3)
+        );
+        assert_eq!(
+            None,
+            within_instance_buffer(MAX_NR_OF_INSTANCES..MAX_NR_OF_INSTANCES + 1)
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use crate::{mesh::Aabb, renderer::Light};
+use glam::{Mat4, Vec3};
+
+const CIRCLE_SEGMENTS: usize = 24;
+// glyphs are 2 units wide and 4 units high with 1 unit between them
+const GLYPH_ADVANCE: f32 = 3.0;
+const GLYPH_HEIGHT: f32 = 4.0;
+
+// segments of a sixteen segment display, as lines between grid points
+const GLYPH_SEGMENTS: [[f32; 4]; 16] = [
+    [0.0, 4.0, 1.0, 4.0], // top left
+    [1.0, 4.0, 2.0, 4.0], // top right
+    [2.0, 4.0, 2.0, 2.0], // right upper
+    [2.0, 2.0, 2.0, 0.0], // right lower
+    [1.0, 0.0, 2.0, 0.0], // bottom right
+    [0.0, 0.0, 1.0, 0.0], // bottom left
+    [0.0, 0.0, 0.0, 2.0], // left lower
+    [0.0, 2.0, 0.0, 4.0], // left upper
+    [0.0, 2.0, 1.0, 2.0], // middle left
+    [1.0, 2.0, 2.0, 2.0], // middle right
+    [0.0, 4.0, 1.0, 2.0], // diagonal upper left
+    [1.0, 4.0, 1.0, 2.0], // center upper
+    [2.0, 4.0, 1.0, 2.0], // diagonal upper right
+    [1.0, 2.0, 0.0, 0.0], // diagonal lower left
+    [1.0, 2.0, 1.0, 0.0], // center lower
+    [1.0, 2.0, 2.0, 0.0], // diagonal lower right
+];
+
+const A: u16 = 0b11;
+const B: u16 = 1 << 2;
+const C: u16 = 1 << 3;
+const D: u16 = 0b11 << 4;
+const D1: u16 = 1 << 5;
+const E: u16 = 1 << 6;
+const F: u16 = 1 << 7;
+const G: u16 = 0b11 << 8;
+const G1: u16 = 1 << 8;
+const G2: u16 = 1 << 9;
+const H: u16 = 1 << 10;
+const I: u16 = 1 << 11;
+const J: u16 = 1 << 12;
+const K: u16 = 1 << 13;
+const L: u16 = 1 << 14;
+const M: u16 = 1 << 15;
+
+// lower case letters are drawn as upper case ones, unknown characters as a question mark
+fn glyph(c: char) -> u16 {
+    match c.to_ascii_uppercase() {
+        ' ' => 0,
+        '0' => A | B | C | D | E | F | J | K,
+        '1' => B | C,
+        '2' => A | B | G | E | D,
+        '3' => A | B | G2 | C | D,
+        '4' => F | G | B | C,
+        '5' => A | F | G | C | D,
+        '6' => A | F | E | D | C | G,
+        '7' => A | B | C,
+        '8' => A | B | C | D | E | F | G,
+        '9' => A | B | C | D | F | G,
+        'A' => A | B | C | E | F | G,
+        'B' => A | B | C | D | I | L | G2,
+        'C' => A | F | E | D,
+        'D' => A | B | C | D | I | L,
+        'E' => A | F | E | D | G1,
+        'F' => A | F | E | G1,
+        'G' => A | F | E | D | C | G2,
+        'H' => F | E | B | C | G,
+        'I' => A | D | I | L,
+        'J' => B | C | D | E,
+        'K' => F | E | G1 | J | M,
+        'L' => F | E | D,
+        'M' => F | E | B | C | H | J,
+        'N' => F | E | B | C | H | M,
+        'O' => A | B | C | D | E | F,
+        'P' => A | B | F | E | G,
+        'Q' => A | B | C | D | E | F | M,
+        'R' => A | B | F | E | G | M,
+        'S' => A | F | G | C | D,
+        'T' => A | I | L,
+        'U' => F | E | D | C | B,
+        'V' => F | E | K | J,
+        'W' => F | E | B | C | K | M,
+        'X' => H | J | K | M,
+        'Y' => H | J | L,
+        'Z' => A | J | K | D,
+        '-' => G,
+        '+' => G | I | L,
+        '=' => G | D,
+        '_' => D,
+        '.' => D1,
+        ',' => K,
+        ':' => I | L,
+        '/' => J | K,
+        '(' => J | M,
+        ')' => H | K,
+        _ => A | B | G2 | L,
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DebugVertex {
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for DebugVertex {}
+unsafe impl bytemuck::Zeroable for DebugVertex {}
+
+// text centered above a position in world space, it always faces the camera
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugLabel {
+    pub position: Vec3,
+    pub text: String,
+    // height of a character in meters
+    pub size: f32,
+    pub color: [f32; 4],
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct DebugSettings {
+    // drawn by Physics::draw_colliders, the physics is not part of the scene the renderer sees
+    pub colliders: bool,
+    pub chunk_grid: bool,
+    // the camera frustum and the shadow cascades
+    pub frusta: bool,
+    pub light_volumes: bool,
+}
+
+// immediate mode lines and labels, they are drawn in the frame after they are added until they are cleared
+#[derive(Debug, Clone, Default)]
+pub struct DebugDraw {
+    lines: Vec<DebugVertex>,
+    labels: Vec<DebugLabel>,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.labels.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.labels.is_empty()
+    }
+
+    // every two vertices are a line
+    pub fn lines(&self) -> &[DebugVertex] {
+        &self.lines
+    }
+
+    pub fn labels(&self) -> &[DebugLabel] {
+        &self.labels
+    }
+
+    pub fn line(&mut self, from: Vec3, to: Vec3, color: [f32; 4]) {
+        self.lines.push(DebugVertex {
+            position: from.into(),
+            color,
+        });
+        self.lines.push(DebugVertex {
+            position: to.into(),
+            color,
+        });
+    }
+
+    pub fn aabb(&mut self, aabb: &Aabb, color: [f32; 4]) {
+        self.cuboid(Mat4::from_translation(aabb.center()), aabb.half_extents(), color);
+    }
+
+    // box around the origin of the space that m transforms from
+    pub fn cuboid(&mut self, m: Mat4, half_extents: Vec3, color: [f32; 4]) {
+        let corners = box_corners(-1.0, 1.0).map(|corner| m.transform_point3(corner * half_extents));
+        self.box_edges(&corners, color);
+    }
+
+    // everything view_proj projects into clip space, like a camera or a shadow cascade
+    pub fn frustum(&mut self, view_proj: Mat4, color: [f32; 4]) {
+        let inverse = view_proj.inverse();
+        let corners = box_corners(0.0, 1.0).map(|corner| inverse.transform_point3(corner));
+        self.box_edges(&corners, color);
+    }
+
+    pub fn circle(&mut self, center: Vec3, normal: Vec3, radius: f32, color: [f32; 4]) {
+        let (u, v) = perpendiculars(normal);
+        let point = |i: usize| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for i in 0..CIRCLE_SEGMENTS {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: [f32; 4]) {
+        self.circle(center, Vec3::unit_x(), radius, color);
+        self.circle(center, Vec3::unit_y(), radius, color);
+        self.circle(center, Vec3::unit_z(), radius, color);
+    }
+
+    pub fn arrow(&mut self, from: Vec3, to: Vec3, color: [f32; 4]) {
+        self.line(from, to, color);
+        let length = (to - from).length();
+        if length == 0.0 {
+            return;
+        }
+        let direction = (to - from) / length;
+        let (u, v) = perpendiculars(direction);
+        let head = length.min(1.0) * 0.2;
+        let base = to - direction * head;
+        for side in [u, -u, v, -v].iter() {
+            self.line(to, base + *side * head * 0.5, color);
+        }
+    }
+
+    // angle is between the direction and the side of the cone
+    pub fn cone(&mut self, apex: Vec3, direction: Vec3, length: f32, angle: f32, color: [f32; 4]) {
+        let direction = direction.normalize();
+        let center = apex + direction * length;
+        let radius = length * angle.tan();
+        self.circle(center, direction, radius, color);
+        let (u, v) = perpendiculars(direction);
+        for side in [u, -u, v, -v].iter() {
+            self.line(apex, center + *side * radius, color);
+        }
+    }
+
+    // point lights are drawn as the sphere and spot lights as the cone they reach
+    pub fn light(&mut self, light: &Light, color: [f32; 4]) {
+        match light {
+            Light::Point(properties) => {
+                let [x, y, z, _] = properties.position;
+                self.sphere(Vec3::new(x, y, z), properties.range(), color);
+            }
+            Light::Spot(properties) => {
+                let [x, y, z, _] = properties.position;
+                let [dx, dy, dz, _] = properties.direction;
+                self.cone(
+                    Vec3::new(x, y, z),
+                    Vec3::new(dx, dy, dz),
+                    properties.range(),
+                    properties.cut_off_outer.acos(),
+                    color,
+                );
+            }
+            Light::Directional(_) => {}
+        }
+    }
+
+    pub fn text(&mut self, position: Vec3, text: &str, size: f32, color: [f32; 4]) {
+        self.labels.push(DebugLabel {
+            position,
+            text: text.to_string(),
+            size,
+            color,
+        });
+    }
+
+    // the labels as lines in the plane of right and up, usually the axes of the camera
+    pub fn label_lines(&self, right: Vec3, up: Vec3) -> Vec<DebugVertex> {
+        let mut label_lines = DebugDraw::new();
+        for label in &self.labels {
+            let unit = label.size / GLYPH_HEIGHT;
+            let width = label.text.chars().count() as f32 * GLYPH_ADVANCE - 1.0;
+            let origin = label.position - right * width * 0.5 * unit;
+            for (i, c) in label.text.chars().enumerate() {
+                let glyph = glyph(c);
+                let offset = i as f32 * GLYPH_ADVANCE;
+                for (segment, &[x0, y0, x1, y1]) in GLYPH_SEGMENTS.iter().enumerate() {
+                    if glyph & (1 << segment) != 0 {
+                        label_lines.line(
+                            origin + (right * (offset + x0) + up * y0) * unit,
+                            origin + (right * (offset + x1) + up * y1) * unit,
+                            label.color,
+                        );
+                    }
+                }
+            }
+        }
+        label_lines.lines
+    }
+
+    // the first four corners are one face and the last four the opposite face
+    fn box_edges(&mut self, corners: &[Vec3; 8], color: [f32; 4]) {
+        for i in 0..4 {
+            self.line(corners[i], corners[(i + 1) % 4], color);
+            self.line(corners[i + 4], corners[(i + 1) % 4 + 4], color);
+            self.line(corners[i], corners[i + 4], color);
+        }
+    }
+}
+
+fn box_corners(near: f32, far: f32) -> [Vec3; 8] {
+    let mut corners = [Vec3::zero(); 8];
+    for (i, (x, y)) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter().enumerate() {
+        corners[i] = Vec3::new(*x, *y, near);
+        corners[i + 4] = Vec3::new(*x, *y, far);
+    }
+    corners
+}
+
+// two unit vectors perpendicular to the direction and to each other
+fn perpendiculars(direction: Vec3) -> (Vec3, Vec3) {
+    let direction = direction.normalize();
+    let other = if direction.x.abs() < 0.9 {
+        Vec3::unit_x()
+    } else {
+        Vec3::unit_y()
+    };
+    let u = direction.cross(other).normalize();
+    (u, direction.cross(u))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mesh::Aabb, renderer::DebugDraw};
+    use glam::{Mat4, Vec3};
+
+    const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+    #[test]
+    fn shapes_are_drawn_as_lines() {
+        let mut debug = DebugDraw::new();
+        debug.line(Vec3::zero(), Vec3::one(), WHITE);
+        assert_eq!(2, debug.lines().len());
+        debug.aabb(&Aabb::new(Vec3::zero(), Vec3::one()), WHITE);
+        assert_eq!(2 + 24, debug.lines().len());
+        assert!(debug
+            .lines()
+            .iter()
+            .skip(2)
+            .all(|v| v.position.iter().all(|p| *p == 0.0 || *p == 1.0)));
+        debug.clear();
+        debug.sphere(Vec3::zero(), 2.0, WHITE);
+        assert!(debug
+            .lines()
+            .iter()
+            .all(|v| (Vec3::from(v.position).length() - 2.0).abs() < 1e-5));
+        debug.clear();
+        assert!(debug.is_empty());
+    }
+
+    #[test]
+    fn frustum_corners_are_on_the_clip_space_box() {
+        let view_proj = Mat4::perspective_rh(1.0, 1.5, 0.1, 100.0) * Mat4::from_translation(Vec3::new(0.0, 0.0, -5.0));
+        let mut debug = DebugDraw::new();
+        debug.frustum(view_proj, WHITE);
+        assert_eq!(24, debug.lines().len());
+        for vertex in debug.lines() {
+            let clip = view_proj.transform_point3(Vec3::from(vertex.position));
+            assert!(clip.x.abs() < 1.0 + 1e-3 && clip.y.abs() < 1.0 + 1e-3);
+            assert!(clip.z.abs() < 1e-3 || (clip.z - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn labels_face_the_camera() {
+        let mut debug = DebugDraw::new();
+        debug.text(Vec3::new(0.0, 0.0, 3.0), "H1", 0.4, WHITE);
+        let lines = debug.label_lines(Vec3::unit_x(), Vec3::unit_y());
+        // 6 segments of the h and 2 of the 1
+        assert_eq!(16, lines.len());
+        assert!(lines.iter().all(|v| v.position[2] == 3.0));
+        assert!(lines
+            .iter()
+            .all(|v| v.position[1] >= 0.0 && v.position[1] <= 0.4 + 1e-6));
+        // centered around the position
+        let min_x = lines.iter().map(|v| v.position[0]).fold(f32::MAX, f32::min);
+        let max_x = lines.iter().map(|v| v.position[0]).fold(f32::MIN, f32::max);
+        assert!((min_x + max_x).abs() < 1e-6);
+    }
+}
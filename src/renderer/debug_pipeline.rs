@@ -0,0 +1,259 @@
+use crate::{
+    renderer::{
+        debug_draw::{DebugDraw, DebugVertex},
+        depth_texture::DepthTexture,
+        error::RendererError,
+        Frame, Light, RenderPass, Renderer, DEPTH, TARGET,
+    },
+    transform::GlobalTransform,
+};
+use std::borrow::Cow;
+
+const FRUSTUM_COLOR: [f32; 4] = [1.0, 1.0, 0.0, 1.0];
+const CASCADE_COLORS: [[f32; 4]; 4] = [
+    [1.0, 0.2, 0.2, 1.0],
+    [0.2, 1.0, 0.2, 1.0],
+    [0.2, 0.2, 1.0, 1.0],
+    [1.0, 0.2, 1.0, 1.0],
+];
+const LIGHT_VOLUME_COLOR: [f32; 4] = [1.0, 0.8, 0.4, 1.0];
+const CHUNK_GRID_COLOR: [f32; 4] = [0.4, 0.8, 1.0, 1.0];
+
+// draws the debug lines of the scene and the enabled built in ones over the post processed frame
+pub struct DebugPipeline {
+    render_pipeline: wgpu::RenderPipeline,
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // the enabled built in lines, rebuilt every frame next to the lines of the scene
+    built_in: DebugDraw,
+    vertex_buffer: Option<wgpu::Buffer>,
+    // in vertices, the buffer grows to the next power of two when the lines do not fit
+    capacity: usize,
+    nr_of_vertices: u32,
+}
+
+impl DebugPipeline {
+    pub async fn new(renderer: &Renderer) -> Result<Self, RendererError> {
+        let uniform = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            size: std::mem::size_of::<glam::Mat4>() as u64,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = renderer
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        min_binding_size: None,
+                        has_dynamic_offset: false,
+                    },
+                    count: None,
+                }],
+                label: None,
+            });
+        let bind_group = renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            }],
+        });
+        let shader = renderer.device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/debug.wgsl"))),
+            flags: wgpu::ShaderFlags::EXPERIMENTAL_TRANSLATION,
+        });
+        let render_pipeline_layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[DebugVertex::desc()],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            // hidden by the scene but they do not hide each other
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DepthTexture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState {
+                    front: wgpu::StencilFaceState::IGNORE,
+                    back: wgpu::StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: wgpu::DepthBiasState {
+                    constant: 0,
+                    slope_scale: 0.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[wgpu::ColorTargetState {
+                    format: renderer.swap_chain_descriptor.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrite::ALL,
+                }],
+            }),
+        });
+        Ok(Self {
+            render_pipeline,
+            uniform,
+            bind_group,
+            built_in: DebugDraw::new(),
+            vertex_buffer: None,
+            capacity: 0,
+            nr_of_vertices: 0,
+        })
+    }
+}
+
+impl RenderPass for DebugPipeline {
+    fn name(&self) -> &'static str {
+        "debug"
+    }
+
+    fn inputs(&self) -> &[&'static str] {
+        &[DEPTH]
+    }
+
+    fn outputs(&self) -> &[&'static str] {
+        &[TARGET]
+    }
+
+    fn prepare(&mut self, frame: &mut Frame) {
+        let settings = frame.renderer.settings.debug;
+        let camera = frame.scene.camera;
+        let debug = &mut self.built_in;
+        debug.clear();
+        if settings.chunk_grid {
+            frame.scene.world.draw_chunk_grid(
+                [frame.scene.position[0], frame.scene.position[2]],
+                debug,
+                CHUNK_GRID_COLOR,
+            );
+        }
+        if settings.frusta {
+            debug.frustum(camera.get_projection() * camera.get_view(), FRUSTUM_COLOR);
+            for (cascade, color) in frame.cascades.iter().zip(CASCADE_COLORS.iter().cycle()) {
+                debug.frustum(cascade.view_proj, *color);
+            }
+        }
+        if settings.light_volumes {
+            let entities = frame.scene.entities;
+            for (entity, light) in entities.query::<Light>() {
+                debug.light(
+                    &light.transformed(entities.get::<GlobalTransform>(&entity)),
+                    LIGHT_VOLUME_COLOR,
+                );
+            }
+        }
+
+        let inverse_view = camera.get_view().inverse();
+        let (right, up) = (inverse_view.x_axis.truncate(), inverse_view.y_axis.truncate());
+        let mut label_lines = frame.scene.debug.label_lines(right, up);
+        label_lines.extend(self.built_in.label_lines(right, up));
+        let slices = [frame.scene.debug.lines(), self.built_in.lines(), label_lines.as_slice()];
+        let nr_of_vertices = slices.iter().map(|slice| slice.len()).sum::<usize>();
+        self.nr_of_vertices = nr_of_vertices as u32;
+        if nr_of_vertices == 0 {
+            return;
+        }
+        if nr_of_vertices > self.capacity {
+            self.capacity = nr_of_vertices.next_power_of_two();
+            self.vertex_buffer = Some(frame.renderer.device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+                size: (std::mem::size_of::<DebugVertex>() * self.capacity) as u64,
+                mapped_at_creation: false,
+            }));
+        }
+        let queue = &frame.renderer.queue;
+        let vertex_buffer = self.vertex_buffer.as_ref().unwrap();
+        let mut offset = 0;
+        for slice in slices.iter().filter(|slice| !slice.is_empty()) {
+            queue.write_buffer(
+                vertex_buffer,
+                (offset * std::mem::size_of::<DebugVertex>()) as u64,
+                bytemuck::cast_slice(slice),
+            );
+            offset += slice.len();
+        }
+        let view_proj = camera.get_projection() * camera.get_view();
+        queue.write_buffer(&self.uniform, 0, bytemuck::cast_slice(&view_proj.to_cols_array()));
+    }
+
+    fn record(&self, frame: &Frame, encoder: &mut wgpu::CommandEncoder) {
+        let vertex_buffer = match &self.vertex_buffer {
+            Some(vertex_buffer) if self.nr_of_vertices > 0 => vertex_buffer,
+            _ => return,
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: frame.target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &frame.renderer.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+        render_pass.draw(0..self.nr_of_vertices, 0..1);
+    }
+}
+
+impl DebugVertex {
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
@@ -3,6 +3,14 @@ pub enum RendererError {
     String(String),
     IOError(std::io::Error),
     RequestAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    // a headless renderer has no swap chain
+    Headless,
+    SwapChain(wgpu::SwapChainError),
+    BufferAsync(wgpu::BufferAsyncError),
+    RenderGraph(String),
+    // all chunk instances are in use, the chunk is not drawn
+    TooManyChunks(u32),
 }
 
 impl From<String> for RendererError {
@@ -16,3 +24,21 @@ impl From<std::io::Error> for RendererError {
         RendererError::IOError(e)
     }
 }
+
+impl From<wgpu::SwapChainError> for RendererError {
+    fn from(e: wgpu::SwapChainError) -> RendererError {
+        RendererError::SwapChain(e)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for RendererError {
+    fn from(e: wgpu::BufferAsyncError) -> RendererError {
+        RendererError::BufferAsync(e)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for RendererError {
+    fn from(e: wgpu::RequestDeviceError) -> RendererError {
+        RendererError::RequestDevice(e)
+    }
+}
@@ -0,0 +1,82 @@
+use crate::mesh::Aabb;
+use glam::{Mat4, Vec3, Vec4};
+
+// planes are stored as (normal, distance) with the normals pointing inside
+#[derive(Debug, Copy, Clone)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // view projection with a depth range of 0 to 1
+    pub fn from_view_proj(view_proj: &Mat4) -> Self {
+        let m = view_proj.to_cols_array_2d();
+        let row = |i: usize| Vec4::new(m[0][i], m[1][i], m[2][i], m[3][i]);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ];
+        let mut normalized = [Vec4::zero(); 6];
+        for (i, plane) in planes.iter().enumerate() {
+            normalized[i] = *plane / plane.truncate().length();
+        }
+        Self { planes: normalized }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(point) + plane.w >= 0.0)
+    }
+
+    // conservative, boxes near a corner of the frustum can intersect without being visible
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center();
+        let half_extents = aabb.half_extents();
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            normal.dot(center) + plane.w + normal.abs().dot(half_extents) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{mesh::Aabb, renderer::Frustum};
+    use glam::{Mat4, Vec3};
+
+    fn frustum() -> Frustum {
+        // camera at the origin looking along -z
+        let view = Mat4::look_at_rh(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0), Vec3::unit_y());
+        let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, 0.1, 100.0);
+        Frustum::from_view_proj(&(projection * view))
+    }
+
+    #[test]
+    fn points_inside_and_outside() {
+        let frustum = frustum();
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -10.0)));
+        assert!(frustum.contains_point(Vec3::new(9.0, -9.0, -10.0)));
+        assert!(!frustum.contains_point(Vec3::new(11.0, 0.0, -10.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 10.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -0.05)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -101.0)));
+    }
+
+    #[test]
+    fn boxes_inside_crossing_and_outside() {
+        let frustum = frustum();
+        let unit_box = |center: Vec3| Aabb::new(center - Vec3::one(), center + Vec3::one());
+        assert!(frustum.intersects_aabb(&unit_box(Vec3::new(0.0, 0.0, -10.0))));
+        // the center is outside but the box reaches into the frustum
+        assert!(frustum.intersects_aabb(&unit_box(Vec3::new(10.5, 0.0, -10.0))));
+        assert!(frustum.intersects_aabb(&unit_box(Vec3::new(0.0, 0.0, 0.5))));
+        assert!(!frustum.intersects_aabb(&unit_box(Vec3::new(13.0, 0.0, -10.0))));
+        assert!(!frustum.intersects_aabb(&unit_box(Vec3::new(0.0, 0.0, 5.0))));
+        assert!(!frustum.intersects_aabb(&unit_box(Vec3::new(0.0, 0.0, -102.0))));
+    }
+}
@@ -1,9 +1,14 @@
+use crate::transform::GlobalTransform;
+use glam::Vec3;
+use serde::{Deserialize, Serialize};
+
+// directional lights live in a uniform, the fragment stage has no storage buffer left for them
 pub const MAX_NR_OF_DIRECTIONAL_LIGHTS: usize = 1;
-pub const MAX_NR_OF_SPOT_LIGHTS: usize = 10;
-pub const MAX_NR_OF_POINT_LIGHTS: usize = 10;
+// attenuated light below this intensity is ignored, it decides how far spot and point lights reach
+const LIGHT_CUTOFF: f32 = 1.0 / 256.0;
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct DirectionalProperties {
     pub direction: [f32; 4],
     pub ambient: [f32; 4],
@@ -23,7 +28,7 @@ impl DirectionalProperties {
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct SpotProperties {
     pub position: [f32; 4],
     pub direction: [f32; 4],
@@ -35,8 +40,11 @@ pub struct SpotProperties {
     pub quadratic: f32,
     pub cut_off_inner: f32,
     pub cut_off_outer: f32,
+    #[serde(skip)]
     pub p0: f32,
+    #[serde(skip)]
     pub p1: f32,
+    #[serde(skip)]
     pub p2: f32,
 }
 
@@ -61,7 +69,7 @@ impl SpotProperties {
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct PointProperties {
     pub position: [f32; 4],
     pub ambient: [f32; 4],
@@ -70,6 +78,7 @@ pub struct PointProperties {
     pub constant: f32,
     pub linear: f32,
     pub quadratic: f32,
+    #[serde(skip)]
     pub p0: f32,
 }
 
@@ -88,15 +97,110 @@ impl PointProperties {
     }
 }
 
+#[derive(Copy, Clone, Serialize, Deserialize)]
 pub enum Light {
     Directional(DirectionalProperties),
     Spot(SpotProperties),
     Point(PointProperties),
 }
 
+impl Light {
+    // spot and point lights attached to an entity with a GlobalTransform move along with it
+    pub fn transformed(&self, transform: Option<&GlobalTransform>) -> Light {
+        match (self, transform) {
+            (Light::Spot(properties), Some(transform)) => {
+                let direction = transform.0.rotation
+                    * Vec3::new(
+                        properties.direction[0],
+                        properties.direction[1],
+                        properties.direction[2],
+                    );
+                Light::Spot(SpotProperties {
+                    position: transform.0.translation.extend(1.0).into(),
+                    direction: direction.extend(properties.direction[3]).into(),
+                    ..*properties
+                })
+            }
+            (Light::Point(properties), Some(transform)) => Light::Point(PointProperties {
+                position: transform.0.translation.extend(1.0).into(),
+                ..*properties
+            }),
+            _ => *self,
+        }
+    }
+}
+
+// distance where the brightest channel of the attenuated light drops below the cutoff
+fn attenuation_range(constant: f32, linear: f32, quadratic: f32, colors: &[[f32; 4]]) -> f32 {
+    let brightest = colors
+        .iter()
+        .flat_map(|color| color[..3].iter())
+        .fold(0.0f32, |brightest, channel| brightest.max(*channel));
+    // solve constant + linear * d + quadratic * d^2 = brightest / cutoff
+    let c = constant - brightest / LIGHT_CUTOFF;
+    if c >= 0.0 {
+        0.0
+    } else if quadratic > 0.0 {
+        (-linear + (linear * linear - 4.0 * quadratic * c).sqrt()) / (2.0 * quadratic)
+    } else if linear > 0.0 {
+        -c / linear
+    } else {
+        f32::INFINITY
+    }
+}
+
+impl SpotProperties {
+    pub fn range(&self) -> f32 {
+        attenuation_range(
+            self.constant,
+            self.linear,
+            self.quadratic,
+            &[self.ambient, self.diffuse, self.specular],
+        )
+    }
+}
+
+impl PointProperties {
+    pub fn range(&self) -> f32 {
+        attenuation_range(
+            self.constant,
+            self.linear,
+            self.quadratic,
+            &[self.ambient, self.diffuse, self.specular],
+        )
+    }
+}
+
 unsafe impl bytemuck::Pod for DirectionalProperties {}
 unsafe impl bytemuck::Zeroable for DirectionalProperties {}
 unsafe impl bytemuck::Pod for SpotProperties {}
 unsafe impl bytemuck::Zeroable for SpotProperties {}
 unsafe impl bytemuck::Pod for PointProperties {}
 unsafe impl bytemuck::Zeroable for PointProperties {}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::{PointProperties, SpotProperties};
+
+    #[test]
+    fn range_is_where_attenuation_reaches_the_cutoff() {
+        let point = PointProperties::new([0.0, 0.0, 0.0, 1.0]);
+        let range = point.range();
+        let attenuation = 1.0 / (point.constant + point.linear * range + point.quadratic * range * range);
+        assert!((attenuation - 1.0 / 256.0).abs() < 1e-6);
+        assert!(range > 80.0 && range < 100.0, "{}", range);
+
+        let dark = PointProperties {
+            diffuse: [0.0; 4],
+            specular: [0.0; 4],
+            ambient: [0.001, 0.001, 0.001, 1.0],
+            ..point
+        };
+        assert_eq!(0.0, dark.range());
+        let linear = SpotProperties {
+            quadratic: 0.0,
+            ..SpotProperties::new([0.0, 0.0, 0.0, 1.0], [0.0, -1.0, 0.0, 0.0])
+        };
+        assert!((linear.range() - 255.0 / 0.09).abs() < 1e-2);
+    }
+}
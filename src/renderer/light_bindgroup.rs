@@ -1,9 +1,8 @@
-use crate::renderer::{
-    light::{MAX_NR_OF_POINT_LIGHTS, MAX_NR_OF_SPOT_LIGHTS},
-    Camera, Renderer,
-};
+use crate::renderer::{storage_buffer::StorageBuffer, Camera, Renderer};
 use glam::Mat4;
 
+const INITIAL_NR_OF_INSTANCES: usize = 16;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct Uniform {
@@ -26,7 +25,7 @@ unsafe impl bytemuck::Zeroable for Instance {}
 
 pub struct LightBindGroup {
     pub uniform: wgpu::Buffer,
-    pub instances: wgpu::Buffer,
+    pub instances: StorageBuffer,
     pub bind_group_layout: wgpu::BindGroupLayout,
     pub bind_group: wgpu::BindGroup,
 }
@@ -40,12 +39,10 @@ impl LightBindGroup {
             mapped_at_creation: false,
         });
 
-        let instances = renderer.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            usage: wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
-            size: (std::mem::size_of::<Instance>() * MAX_NR_OF_SPOT_LIGHTS * MAX_NR_OF_POINT_LIGHTS) as u64,
-            mapped_at_creation: false,
-        });
+        let instances = StorageBuffer::new(
+            &renderer.device,
+            (std::mem::size_of::<Instance>() * INITIAL_NR_OF_INSTANCES) as u64,
+        );
 
         let bind_group_layout = renderer
             .device
@@ -75,20 +72,7 @@ impl LightBindGroup {
                 label: None,
             });
 
-        let bind_group = renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: uniform.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: instances.as_entire_binding(),
-                },
-            ],
-        });
+        let bind_group = create_bind_group(renderer, &bind_group_layout, &uniform, &instances);
         Self {
             uniform,
             instances,
@@ -107,9 +91,31 @@ impl LightBindGroup {
             .write_buffer(&self.uniform, 0, bytemuck::cast_slice(&[view_projection]));
     }
 
-    pub fn update_instances(&self, renderer: &Renderer, transforms: &[Instance]) {
-        renderer
-            .queue
-            .write_buffer(&self.instances, 0, bytemuck::cast_slice(transforms));
+    pub fn update_instances(&mut self, renderer: &Renderer, transforms: &[Instance]) {
+        if self.instances.write(renderer, bytemuck::cast_slice(transforms)) {
+            self.bind_group = create_bind_group(renderer, &self.bind_group_layout, &self.uniform, &self.instances);
+        }
     }
 }
+
+fn create_bind_group(
+    renderer: &Renderer,
+    layout: &wgpu::BindGroupLayout,
+    uniform: &wgpu::Buffer,
+    instances: &StorageBuffer,
+) -> wgpu::BindGroup {
+    renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: instances.buffer.as_entire_binding(),
+            },
+        ],
+    })
+}
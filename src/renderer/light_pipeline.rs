@@ -1,20 +1,26 @@
 use crate::{
     mesh::Vertex,
-    registry::{Handle, Registry},
+    registry::AssetHandle,
     renderer::{
-        depth_texture::DepthTexture, error::RendererError, light_bindgroup::Instance, Camera, Light, LightBindGroup,
-        Mesh, Renderer,
+        depth_texture::DepthTexture, error::RendererError, hdr_texture::HdrTexture, light_bindgroup::Instance, Frame,
+        Light, LightBindGroup, Mesh, RenderPass, Renderer, COLOR, DEPTH,
     },
+    transform::GlobalTransform,
 };
 use glam::{Mat4, Vec3};
 use std::borrow::Cow;
 
+// draws a small mesh at every spot and point light
 pub struct LightPipeline {
     render_pipeline: wgpu::RenderPipeline,
+    bind_group: LightBindGroup,
+    light_mesh: AssetHandle<Mesh>,
+    nr_of_instances: u32,
 }
 
 impl LightPipeline {
-    pub async fn new(renderer: &Renderer, bind_group: &LightBindGroup) -> Result<Self, RendererError> {
+    pub async fn new(renderer: &Renderer, light_mesh: AssetHandle<Mesh>) -> Result<Self, RendererError> {
+        let bind_group = LightBindGroup::new(renderer);
         let shader = renderer.device.create_shader_module(&wgpu::ShaderModuleDescriptor {
             label: None,
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/light_shader.wgsl"))),
@@ -63,26 +69,37 @@ impl LightPipeline {
             fragment: Some(wgpu::FragmentState {
                 module: &shader,
                 entry_point: "fs_main",
-                targets: &[renderer.swap_chain_descriptor.format.into()],
+                targets: &[HdrTexture::HDR_FORMAT.into()],
             }),
         });
-        Ok(Self { render_pipeline })
+        Ok(Self {
+            render_pipeline,
+            bind_group,
+            light_mesh,
+            nr_of_instances: 0,
+        })
+    }
+}
+
+impl RenderPass for LightPipeline {
+    fn name(&self) -> &'static str {
+        "lights"
+    }
+
+    fn inputs(&self) -> &[&'static str] {
+        &[COLOR, DEPTH]
+    }
+
+    fn outputs(&self) -> &[&'static str] {
+        &[COLOR, DEPTH]
     }
 
-    pub fn render(
-        &self,
-        light_handle: &Handle<Mesh>,
-        lights: &Registry<Light>,
-        bindgroup: &LightBindGroup,
-        camera: &dyn Camera,
-        meshes: &Registry<Mesh>,
-        renderer: &mut Renderer,
-        target: &wgpu::TextureView,
-    ) {
-        bindgroup.update_uniforms(&renderer, camera);
+    fn prepare(&mut self, frame: &mut Frame) {
+        let entities = frame.scene.entities;
+        self.bind_group.update_uniforms(frame.renderer, frame.scene.camera);
         let mut transforms = Vec::new();
-        for (_, light) in &lights.registry {
-            match light {
+        for (entity, light) in entities.query::<Light>() {
+            match light.transformed(entities.get::<GlobalTransform>(&entity)) {
                 Light::Spot(properties) => {
                     let m = Mat4::from_translation(Vec3::new(
                         properties.position[0],
@@ -104,37 +121,35 @@ impl LightPipeline {
                 _ => (),
             }
         }
-        bindgroup.update_instances(&renderer, transforms.as_slice());
-        let mut encoder = renderer
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &target,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &renderer.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: true,
-                    }),
-                    stencil_ops: None,
+        self.bind_group.update_instances(frame.renderer, transforms.as_slice());
+        self.nr_of_instances = transforms.len() as u32;
+    }
+
+    fn record(&self, frame: &Frame, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &frame.renderer.hdr_texture.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &frame.renderer.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
                 }),
-            });
-            let vb = meshes.get(light_handle).unwrap();
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_vertex_buffer(0, vb.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(vb.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            render_pass.set_bind_group(0, &bindgroup.bind_group, &[]);
-            render_pass.draw_indexed(0..vb.len, 0, 0..transforms.len() as u32);
-        }
-        renderer.queue.submit(std::iter::once(encoder.finish()));
+                stencil_ops: None,
+            }),
+        });
+        let vb = frame.scene.meshes.get(&self.light_mesh).unwrap();
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_vertex_buffer(0, vb.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(vb.index_buffer.slice(..), vb.index_format);
+        render_pass.set_bind_group(0, &self.bind_group.bind_group, &[]);
+        render_pass.draw_indexed(0..vb.len, 0, 0..self.nr_of_instances);
     }
 }
@@ -1,7 +1,8 @@
 use crate::{
-    mesh::{MeshData, Vertex},
-    renderer::Renderer,
+    mesh::{Aabb, MeshData, SkinnedMeshData, SkinnedVertex, Vertex, VoxelMeshData, VoxelVertex},
+    renderer::{Renderer, Texture},
 };
+use glam::{Mat4, Vec3};
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
@@ -9,25 +10,157 @@ use wgpu::util::DeviceExt;
 pub struct Mesh {
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
+    pub index_format: wgpu::IndexFormat,
     pub len: u32,
+    // the indices after these are of transparent faces
+    pub nr_of_opaque_indices: u32,
+    // bounds in model space
+    pub aabb: Aabb,
+    // optional uvs of imported meshes, bound after the vertex buffer by the textured pipelines
+    pub uv_buffer: Option<wgpu::Buffer>,
+    // base color texture, meshes with uvs and a material are drawn with the textured pipelines
+    pub material: Option<wgpu::BindGroup>,
+    // voxel meshes keep their positions in voxels on the gpu and are drawn with the voxel pipelines
+    pub voxel_size: Option<f32>,
+    // skinned meshes have joints and weights in their vertices and are posed by the Skin of their entity
+    pub skinned: bool,
+    // the triangles in model space stay on the cpu for colliders built from the mesh
+    pub positions: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+fn create_buffer(renderer: &Renderer, contents: &[u8], usage: wgpu::BufferUsage) -> wgpu::Buffer {
+    renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents,
+        usage,
+    })
 }
 
 impl Mesh {
     pub fn from_mesh_data(renderer: &Renderer, mesh_data: MeshData) -> Self {
-        let vertex_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(mesh_data.vertices.as_slice()),
-            usage: wgpu::BufferUsage::VERTEX,
-        });
-        let index_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(mesh_data.indices.as_slice()),
-            usage: wgpu::BufferUsage::INDEX,
-        });
+        let material = match (&mesh_data.uvs, &mesh_data.base_color_texture) {
+            (Some(_), Some(image)) => Some(Texture::from_image(renderer, image).material_bind_group(renderer)),
+            _ => None,
+        };
+        Self {
+            vertex_buffer: create_buffer(
+                renderer,
+                bytemuck::cast_slice(mesh_data.vertices.as_slice()),
+                wgpu::BufferUsage::VERTEX,
+            ),
+            index_buffer: create_buffer(
+                renderer,
+                bytemuck::cast_slice(mesh_data.indices.as_slice()),
+                wgpu::BufferUsage::INDEX,
+            ),
+            index_format: wgpu::IndexFormat::Uint32,
+            len: mesh_data.indices.len() as u32,
+            nr_of_opaque_indices: mesh_data.indices.len() as u32,
+            aabb: mesh_data.aabb(),
+            uv_buffer: mesh_data.uvs.map(|uvs| {
+                create_buffer(
+                    renderer,
+                    bytemuck::cast_slice(uvs.as_slice()),
+                    wgpu::BufferUsage::VERTEX,
+                )
+            }),
+            material,
+            voxel_size: None,
+            skinned: false,
+            positions: mesh_data.vertices.iter().map(|v| v.position).collect(),
+            indices: mesh_data.indices,
+        }
+    }
+}
+
+impl Mesh {
+    pub fn from_skinned_mesh_data(renderer: &Renderer, mesh_data: SkinnedMeshData) -> Self {
         Self {
-            vertex_buffer,
+            vertex_buffer: create_buffer(
+                renderer,
+                bytemuck::cast_slice(mesh_data.vertices.as_slice()),
+                wgpu::BufferUsage::VERTEX,
+            ),
+            index_buffer: create_buffer(
+                renderer,
+                bytemuck::cast_slice(mesh_data.indices.as_slice()),
+                wgpu::BufferUsage::INDEX,
+            ),
+            index_format: wgpu::IndexFormat::Uint32,
+            len: mesh_data.indices.len() as u32,
+            nr_of_opaque_indices: mesh_data.indices.len() as u32,
+            aabb: mesh_data.aabb(),
+            uv_buffer: None,
+            material: None,
+            voxel_size: None,
+            skinned: true,
+            positions: mesh_data.vertices.iter().map(|v| v.position).collect(),
+            indices: mesh_data.indices,
+        }
+    }
+}
+
+impl Mesh {
+    // uses 16 bit indices when the mesh is small enough, the bounds and positions are in meters
+    pub fn from_voxel_mesh_data(renderer: &Renderer, mesh_data: &VoxelMeshData, voxel_size: f32) -> Self {
+        let (index_buffer, index_format) = if mesh_data.has_short_indices() {
+            let indices = mesh_data.indices.iter().map(|i| *i as u16).collect::<Vec<_>>();
+            (
+                create_buffer(
+                    renderer,
+                    bytemuck::cast_slice(indices.as_slice()),
+                    wgpu::BufferUsage::INDEX,
+                ),
+                wgpu::IndexFormat::Uint16,
+            )
+        } else {
+            (
+                create_buffer(
+                    renderer,
+                    bytemuck::cast_slice(mesh_data.indices.as_slice()),
+                    wgpu::BufferUsage::INDEX,
+                ),
+                wgpu::IndexFormat::Uint32,
+            )
+        };
+        Self {
+            vertex_buffer: create_buffer(
+                renderer,
+                bytemuck::cast_slice(mesh_data.vertices.as_slice()),
+                wgpu::BufferUsage::VERTEX,
+            ),
             index_buffer,
+            index_format,
             len: mesh_data.indices.len() as u32,
+            nr_of_opaque_indices: mesh_data.nr_of_opaque_indices as u32,
+            aabb: mesh_data.aabb().transformed(&Mat4::from_scale(Vec3::splat(voxel_size))),
+            uv_buffer: None,
+            material: None,
+            voxel_size: Some(voxel_size),
+            skinned: false,
+            positions: mesh_data.positions(voxel_size),
+            indices: mesh_data.indices.clone(),
+        }
+    }
+
+    // the model matrix of an instance, voxel meshes are scaled from voxels to meters
+    pub fn instance_matrix(&self, m: Mat4) -> Mat4 {
+        match self.voxel_size {
+            Some(voxel_size) => m * Mat4::from_scale(Vec3::splat(voxel_size)),
+            None => m,
+        }
+    }
+
+    pub fn uv_desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[wgpu::VertexAttribute {
+                offset: 0,
+                shader_location: 3,
+                format: wgpu::VertexFormat::Float32x2,
+            }],
         }
     }
 }
@@ -59,5 +192,68 @@ impl Vertex {
     }
 }
 
+impl SkinnedVertex {
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: 2 * mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: 3 * mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Uint32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: (3 * mem::size_of::<[f32; 3]>() + mem::size_of::<[u32; 4]>()) as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+impl VoxelVertex {
+    pub fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::InputStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Uint16x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[u16; 4]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Uint8x4,
+                },
+            ],
+        }
+    }
+}
+
 unsafe impl bytemuck::Pod for Vertex {}
 unsafe impl bytemuck::Zeroable for Vertex {}
+unsafe impl bytemuck::Pod for SkinnedVertex {}
+unsafe impl bytemuck::Zeroable for SkinnedVertex {}
+unsafe impl bytemuck::Pod for VoxelVertex {}
+unsafe impl bytemuck::Zeroable for VoxelVertex {}
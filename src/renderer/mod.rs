@@ -1,19 +1,63 @@
 mod bindgroup;
 mod camera;
+mod chunk_buffers;
+mod clusters;
+mod culling;
+mod debug_draw;
+mod debug_pipeline;
 mod depth_texture;
 mod error;
+mod frustum;
+mod hdr_texture;
 mod light;
 mod light_bindgroup;
 mod light_pipeline;
 mod mesh;
+mod offscreen;
 mod pipeline;
+mod post_process;
+mod render_graph;
 mod renderer;
+mod settings;
+mod shadow;
+mod shadow_map;
+mod shadow_pipeline;
+mod sky;
+mod sky_pipeline;
+mod storage_buffer;
+mod texture;
+mod transparent_pipeline;
 
-pub use bindgroup::{BindGroup, Instance};
+pub use bindgroup::{BindGroup, Instance, LightBuffers, MAX_NR_OF_INSTANCES, MAX_NR_OF_JOINTS};
 pub use camera::Camera;
-pub use light::{DirectionalProperties, Light, PointProperties, SpotProperties};
+pub use chunk_buffers::{
+    ChunkAllocation, ChunkBuffers, ChunkDraws, DrawIndexedIndirect, RangeAllocator, MAX_NR_OF_CHUNK_INSTANCES,
+};
+pub use clusters::{
+    cluster_index, depth_slice, Cluster, LightClusters, CLUSTERS_X, CLUSTERS_Y, CLUSTERS_Z, NR_OF_CLUSTERS,
+};
+pub use culling::RenderStats;
+pub use debug_draw::{DebugDraw, DebugLabel, DebugSettings, DebugVertex};
+pub use debug_pipeline::DebugPipeline;
+pub use frustum::Frustum;
+pub use light::{DirectionalProperties, Light, PointProperties, SpotProperties, MAX_NR_OF_DIRECTIONAL_LIGHTS};
 pub use light_bindgroup::LightBindGroup;
 pub use light_pipeline::LightPipeline;
 pub use mesh::Mesh;
+pub use offscreen::{image_difference, OffscreenTarget};
 pub use pipeline::Pipeline;
+pub use post_process::{fog_factor, tonemap, BloomSettings, FogSettings, PostProcessPipeline, PostProcessSettings};
+pub use render_graph::{Frame, RenderGraph, RenderPass, SceneView, COLOR, DEPTH, SHADOW_MAP, TARGET};
+pub use error::RendererError;
 pub use renderer::Renderer;
+pub use settings::RendererSettings;
+pub use shadow::{
+    cascade_splits, cascade_view_proj, cascades, frustum_corners, Cascade, ShadowSettings, NR_OF_CASCADES,
+};
+pub use shadow_map::ShadowMap;
+pub use shadow_pipeline::ShadowPipeline;
+pub use sky::{apply_sky, Sky, SkySettings, TimeOfDay};
+pub use sky_pipeline::SkyPipeline;
+pub use storage_buffer::StorageBuffer;
+pub use texture::Texture;
+pub use transparent_pipeline::{Opacity, TransparentPipeline};
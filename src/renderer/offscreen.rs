@@ -0,0 +1,305 @@
+use crate::renderer::{error::RendererError, Renderer};
+use image::RgbaImage;
+use std::num::NonZeroU32;
+
+// color target with the size and format of the swap chain that can be read back
+pub struct OffscreenTarget {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+}
+
+impl OffscreenTarget {
+    pub fn new(renderer: &Renderer) -> Self {
+        let width = renderer.swap_chain_descriptor.width;
+        let height = renderer.swap_chain_descriptor.height;
+        let format = renderer.swap_chain_descriptor.format;
+        let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            // sampled as well, the gl backend can only copy textures and not render buffers to buffers
+            usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::COPY_SRC | wgpu::TextureUsage::SAMPLED,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            texture,
+            view,
+            width,
+            height,
+            format,
+        }
+    }
+
+    // waits until everything rendered into the target is done
+    pub fn read(&self, renderer: &Renderer) -> Result<RgbaImage, RendererError> {
+        // rows of a texture to buffer copy are aligned to 256 bytes
+        let bytes_per_row = self.width * 4;
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_bytes_per_row = bytes_per_row.div_ceil(alignment) * alignment;
+        let buffer = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_bytes_per_row * self.height) as u64,
+            usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        renderer.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let mapped = slice.map_async(wgpu::MapMode::Read);
+        renderer.device.poll(wgpu::Maintain::Wait);
+        futures::executor::block_on(mapped)?;
+        let mut pixels = Vec::with_capacity((bytes_per_row * self.height) as usize);
+        for row in slice.get_mapped_range().chunks(padded_bytes_per_row as usize) {
+            pixels.extend_from_slice(&row[..bytes_per_row as usize]);
+        }
+        buffer.unmap();
+        if let wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb = self.format {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(RgbaImage::from_raw(self.width, self.height, pixels).unwrap())
+    }
+}
+
+// mean absolute difference of all channels from 0.0 to 1.0, images of different sizes differ completely
+pub fn image_difference(a: &RgbaImage, b: &RgbaImage) -> f32 {
+    if a.dimensions() != b.dimensions() {
+        return 1.0;
+    }
+    let sum = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw().iter())
+        .map(|(a, b)| a.abs_diff(*b) as u64)
+        .sum::<u64>();
+    sum as f32 / (a.as_raw().len() as f32 * 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cameras::StaticCamera,
+        entity::{propagate_transforms, Entities},
+        mesh::{Cube, MeshData, VoxelMeshData, VoxelVertex},
+        registry::Assets,
+        renderer::{
+            culling::Culling, image_difference, BindGroup, DebugDraw, DirectionalProperties, Frame, Light, Mesh,
+            OffscreenTarget, PointProperties, RenderGraph, Renderer, SceneView, Sky, NR_OF_CASCADES,
+        },
+        transform::Transform,
+        world::World,
+    };
+    use glam::{Mat4, Vec3};
+    use image::{Rgba, RgbaImage};
+
+    // rows of 768 bytes need no padding, which the gl backend ignores when copying
+    const WIDTH: u32 = 192;
+    const HEIGHT: u32 = 144;
+    // software and hardware rasterizers differ slightly at edges
+    const TOLERANCE: f32 = 0.01;
+
+    const GOLDEN_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/res/golden");
+    // set to write the rendered images as the new golden images instead of comparing them
+    const UPDATE_GOLDEN: &str = "UPDATE_GOLDEN";
+
+    // the golden tests are ignored by default, run them with cargo test -- --ignored on a machine with an adapter
+    fn headless_renderer() -> Renderer {
+        let renderer =
+            futures::executor::block_on(Renderer::new_headless(WIDTH, HEIGHT)).expect("no adapter to render with");
+        // without a logger the validation errors would not be shown
+        renderer.device.on_uncaptured_error(|e| panic!("{}", e));
+        renderer
+    }
+
+    fn assert_matches_golden(image: &RgbaImage, name: &str) {
+        let path = format!("{}/{}.png", GOLDEN_DIRECTORY, name);
+        if std::env::var_os(UPDATE_GOLDEN).is_some() {
+            std::fs::create_dir_all(GOLDEN_DIRECTORY).unwrap();
+            image.save(&path).unwrap();
+            return;
+        }
+        let golden = match image::open(&path) {
+            Ok(golden) => golden.to_rgba8(),
+            Err(e) => panic!("could not open {}, {} writes it: {:?}", path, UPDATE_GOLDEN, e),
+        };
+        let difference = image_difference(image, &golden);
+        if difference > TOLERANCE {
+            let failed_directory = concat!(env!("CARGO_MANIFEST_DIR"), "/target");
+            std::fs::create_dir_all(failed_directory).unwrap();
+            let failed = format!("{}/{}-failed.png", failed_directory, name);
+            image.save(&failed).unwrap();
+            panic!("{} differs from {} by {}, see {}", name, path, difference, failed);
+        }
+    }
+
+    fn camera() -> StaticCamera {
+        StaticCamera::new(Vec3::new(3.0, 3.0, 5.0), Vec3::zero(), WIDTH as f32 / HEIGHT as f32)
+    }
+
+    #[test]
+    fn image_difference_of_images() {
+        let black = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 255]));
+        let white = RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255]));
+        let mut dot = black.clone();
+        dot.put_pixel(1, 1, Rgba([255, 255, 255, 255]));
+        assert_eq!(0.0, image_difference(&black, &black));
+        assert!((image_difference(&black, &white) - 0.75).abs() < 1e-6);
+        assert!((image_difference(&black, &dot) - 0.75 / 16.0).abs() < 1e-6);
+        assert_eq!(1.0, image_difference(&black, &RgbaImage::new(2, 2)));
+    }
+
+    fn render(
+        renderer: &Renderer,
+        graph: &mut RenderGraph,
+        bind_group: &mut BindGroup,
+        entities: &Entities,
+        meshes: &Assets<Mesh>,
+    ) -> RgbaImage {
+        let target = OffscreenTarget::new(renderer);
+        let world = World::new();
+        let camera = camera();
+        let scene = SceneView {
+            world: &world,
+            entities,
+            meshes,
+            camera: &camera,
+            position: [0.0, 0.0, 0.0],
+            sky: Sky::default(),
+            debug: &DebugDraw::new(),
+        };
+        graph.execute(&mut Frame::new(renderer, &target.view, scene, bind_group));
+        target.read(renderer).unwrap()
+    }
+
+    #[test]
+    #[ignore]
+    fn casters_are_culled_without_the_opaque_pass() {
+        let renderer = headless_renderer();
+        let mut bind_group = BindGroup::new(&renderer);
+        let mut meshes = Assets::new();
+        let mut entities = Entities::new();
+        let cube = meshes.add(Mesh::from_mesh_data(&renderer, MeshData::from(Cube::new(1.0))));
+        entities.spawn().with(cube).with(Transform::default());
+        entities
+            .spawn()
+            .with(Light::Directional(DirectionalProperties::new([-0.4, -1.0, -0.2, 0.0])));
+        propagate_transforms(&mut entities);
+
+        let target = OffscreenTarget::new(&renderer);
+        let world = World::new();
+        let camera = camera();
+        let debug = DebugDraw::new();
+        let scene = SceneView {
+            world: &world,
+            entities: &entities,
+            meshes: &meshes,
+            camera: &camera,
+            position: [0.0, 0.0, 0.0],
+            sky: Sky::default(),
+            debug: &debug,
+        };
+        // what the render graph does before a graph with only the shadow pass is prepared
+        let mut frame = Frame::new(&renderer, &target.view, scene, &mut bind_group);
+        Culling::default().cull(&mut frame, true);
+        assert_eq!(NR_OF_CASCADES, frame.cascades.len());
+        assert!(frame.casters.iter().all(|casters| casters.draws.len() == 1));
+        // without a shadow pass there is nothing to cull for the cascades
+        Culling::default().cull(&mut frame, false);
+        assert!(frame.cascades.is_empty() && frame.casters.is_empty());
+        assert_eq!(1, frame.draws.len());
+    }
+
+    #[test]
+    #[ignore]
+    fn chunks_are_uploaded_with_the_indices_they_fit_in() {
+        let renderer = headless_renderer();
+        let mut bind_group = BindGroup::new(&renderer);
+        let mesh_data = |nr_of_vertices: usize| VoxelMeshData {
+            vertices: vec![VoxelVertex::new([0, 0, 0], 0, 0, [1.0, 1.0, 1.0], 0); nr_of_vertices],
+            // an odd number of 16 bit indices is not a multiple of 4 bytes
+            indices: vec![0, 1, 2],
+            nr_of_opaque_indices: 3,
+        };
+        let first = bind_group
+            .allocate_chunk(&renderer, &mesh_data(3), Mat4::identity())
+            .unwrap();
+        let second = bind_group
+            .allocate_chunk(&renderer, &mesh_data(3), Mat4::identity())
+            .unwrap();
+        let long = bind_group
+            .allocate_chunk(&renderer, &mesh_data(u16::MAX as usize + 2), Mat4::identity())
+            .unwrap();
+        assert_eq!(wgpu::IndexFormat::Uint16, first.index_format());
+        assert_eq!(wgpu::IndexFormat::Uint32, long.index_format());
+        assert_eq!(0, first.opaque_draw().first_index);
+        assert_eq!(4, second.opaque_draw().first_index);
+        assert_eq!(3, second.opaque_draw().index_count);
+        renderer.device.poll(wgpu::Maintain::Wait);
+    }
+
+    #[test]
+    #[ignore]
+    fn shadow_map_follows_the_resolution_setting() {
+        let mut renderer = headless_renderer();
+        let mut bind_group = BindGroup::new(&renderer);
+        assert_eq!(renderer.settings.shadows.resolution, bind_group.shadow_map.resolution);
+        renderer.settings.shadows.resolution = 512;
+        bind_group.update_uniforms(&renderer, &Entities::new(), &camera(), &[]);
+        assert_eq!(512, bind_group.shadow_map.resolution);
+    }
+
+    #[test]
+    #[ignore]
+    fn light_pipeline_matches_golden() {
+        let renderer = headless_renderer();
+        let mut bind_group = BindGroup::new(&renderer);
+        let mut meshes = Assets::new();
+        let mut entities = Entities::new();
+        let light_mesh = meshes.add(Mesh::from_mesh_data(&renderer, MeshData::from(Cube::new(0.25))));
+        // the opaque pass clears the target before the lights are drawn
+        let mut graph = futures::executor::block_on(RenderGraph::standard(&renderer, &bind_group, light_mesh)).unwrap();
+        entities
+            .spawn()
+            .with(Light::Point(PointProperties::new([1.0, 0.5, 0.0, 1.0])));
+        entities
+            .spawn()
+            .with(Light::Point(PointProperties::new([-1.0, -0.5, 0.0, 1.0])));
+
+        let image = render(&renderer, &mut graph, &mut bind_group, &entities, &meshes);
+        assert_matches_golden(&image, "light");
+    }
+}
@@ -1,17 +1,17 @@
 use crate::{
-    entity::Entity,
-    mesh::Vertex,
-    registry::{Handle, Registry},
+    mesh::{SkinnedVertex, Vertex, VoxelVertex},
     renderer::{
-        bindgroup::Instance, depth_texture::DepthTexture, error::RendererError, mesh::Mesh, BindGroup, Camera, Light,
-        Renderer,
+        depth_texture::DepthTexture, error::RendererError, hdr_texture::HdrTexture, mesh::Mesh, BindGroup, Frame,
+        RenderPass, Renderer, COLOR, DEPTH, SHADOW_MAP,
     },
-    world::World,
 };
 use std::borrow::Cow;
 
 pub struct Pipeline {
     render_pipeline: wgpu::RenderPipeline,
+    textured_render_pipeline: wgpu::RenderPipeline,
+    voxel_render_pipeline: wgpu::RenderPipeline,
+    skinned_render_pipeline: wgpu::RenderPipeline,
 }
 
 impl Pipeline {
@@ -26,135 +26,174 @@ impl Pipeline {
             bind_group_layouts: &[&bind_group.bind_group_layout],
             push_constant_ranges: &[],
         });
-
-        let render_pipeline = renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let textured_render_pipeline_layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                clamp_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: DepthTexture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState {
-                    front: wgpu::StencilFaceState::IGNORE,
-                    back: wgpu::StencilFaceState::IGNORE,
-                    read_mask: 0,
-                    write_mask: 0,
-                },
-                bias: wgpu::DepthBiasState {
-                    constant: 0,
-                    slope_scale: 0.0,
-                    clamp: 0.0,
-                },
-            }),
-            multisample: wgpu::MultisampleState::default(),
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[renderer.swap_chain_descriptor.format.into()],
-            }),
+            bind_group_layouts: &[&bind_group.bind_group_layout, &renderer.material_bind_group_layout],
+            push_constant_ranges: &[],
         });
-        Ok(Self { render_pipeline })
+
+        let render_pipeline = create_render_pipeline(
+            renderer,
+            &render_pipeline_layout,
+            &shader,
+            ("vs_main", "fs_main"),
+            &[Vertex::desc()],
+            false,
+        );
+        let textured_render_pipeline = create_render_pipeline(
+            renderer,
+            &textured_render_pipeline_layout,
+            &shader,
+            ("vs_textured", "fs_textured"),
+            &[Vertex::desc(), Mesh::uv_desc()],
+            false,
+        );
+        // chunk meshes use the compact voxel vertex
+        let voxel_render_pipeline = create_render_pipeline(
+            renderer,
+            &render_pipeline_layout,
+            &shader,
+            ("vs_voxel", "fs_main"),
+            &[VoxelVertex::desc()],
+            false,
+        );
+        let skinned_render_pipeline = create_render_pipeline(
+            renderer,
+            &render_pipeline_layout,
+            &shader,
+            ("vs_skinned", "fs_main"),
+            &[SkinnedVertex::desc()],
+            false,
+        );
+        Ok(Self {
+            render_pipeline,
+            textured_render_pipeline,
+            voxel_render_pipeline,
+            skinned_render_pipeline,
+        })
     }
+}
 
-    pub fn render(
-        &self,
-        world: &World,
-        entities: &Registry<Entity>,
-        meshes: &mut Registry<Mesh>,
-        lights: &Registry<Light>,
-        bindgroup: &BindGroup,
-        camera: &dyn Camera,
-        renderer: &mut Renderer,
-        target: &wgpu::TextureView,
-        position: [f32; 3],
-    ) {
-        bindgroup.update_uniforms(&renderer, &lights, camera);
-        let mut instance_map = Vec::new();
-        let mut start_range = 0;
-        let mut transforms = Vec::new();
-        for (id, _) in &mut meshes.registry {
-            transforms.extend_from_slice(
-                entities
-                    .registry
-                    .iter()
-                    .filter_map(|(_, v)| {
-                        if v.mesh_handle.id == *id {
-                            let m = v.transform.to_matrix();
-                            let inv_m = m.inverse();
-                            Some(Instance { m, inv_m })
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<Vec<_>>()
-                    .as_slice(),
-            );
-            instance_map.push((Handle::<Mesh>::new(*id), start_range..transforms.len() as u32));
-            start_range = transforms.len() as u32;
-        }
-        for (handle, transform) in world.get_within_view_mesh_transform([position[0], position[2]]) {
-            let m = transform.to_matrix();
-            let inv_m = m.inverse();
-            transforms.push(Instance { m, inv_m });
-            instance_map.push((handle, start_range..start_range + 1));
-            start_range = transforms.len() as u32;
-        }
+// draws the opaque instances and chunks that the render graph culled
+impl RenderPass for Pipeline {
+    fn name(&self) -> &'static str {
+        "opaque"
+    }
 
-        bindgroup.update_instances(&renderer, transforms.as_slice());
-        let mut encoder = renderer
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[wgpu::RenderPassColorAttachment {
-                    view: &target,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: true,
-                    },
-                }],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &renderer.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: true,
+    fn inputs(&self) -> &[&'static str] {
+        &[SHADOW_MAP]
+    }
+
+    fn outputs(&self) -> &[&'static str] {
+        &[COLOR, DEPTH]
+    }
+
+    fn record(&self, frame: &Frame, encoder: &mut wgpu::CommandEncoder) {
+        let meshes = frame.scene.meshes;
+        // the sky pass draws over the background, without it the horizon color is left
+        let [r, g, b] = frame.scene.sky.horizon_color;
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &frame.renderer.hdr_texture.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: r as f64,
+                        g: g as f64,
+                        b: b as f64,
+                        a: 1.0,
                     }),
-                    stencil_ops: None,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &frame.renderer.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: true,
                 }),
-            });
+                stencil_ops: None,
+            }),
+        });
 
-            for (mesh_handle, instance_range) in instance_map {
-                if !instance_range.is_empty() {
-                    let mesh = meshes.get(&mesh_handle).unwrap();
-                    render_pass.set_pipeline(&self.render_pipeline);
-                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-                    render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-                    render_pass.set_bind_group(0, &bindgroup.bind_group, &[]);
-                    render_pass.draw_indexed(0..mesh.len, 0, instance_range);
+        render_pass.set_bind_group(0, &frame.bind_group.bind_group, &[]);
+        for draw in &frame.draws {
+            let mesh = meshes.get(&draw.mesh).unwrap();
+            match (&mesh.material, &mesh.uv_buffer) {
+                _ if mesh.voxel_size.is_some() => render_pass.set_pipeline(&self.voxel_render_pipeline),
+                _ if mesh.skinned => render_pass.set_pipeline(&self.skinned_render_pipeline),
+                (Some(material), Some(uv_buffer)) => {
+                    render_pass.set_pipeline(&self.textured_render_pipeline);
+                    render_pass.set_bind_group(1, material, &[]);
+                    render_pass.set_vertex_buffer(1, uv_buffer.slice(..));
                 }
+                _ => render_pass.set_pipeline(&self.render_pipeline),
             }
+            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+            render_pass.draw_indexed(draw.indices.clone(), 0, draw.instances.clone());
         }
-        renderer.queue.submit(std::iter::once(encoder.finish()));
+        render_pass.set_pipeline(&self.voxel_render_pipeline);
+        frame.bind_group.chunks.draw(&mut render_pass, &frame.chunk_draws, 0);
     }
 }
+
+// transparent pipelines blend over the scene and test against its depth without writing it
+pub(crate) fn create_render_pipeline(
+    renderer: &Renderer,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    (vertex_entry_point, fragment_entry_point): (&str, &str),
+    buffers: &[wgpu::VertexBufferLayout],
+    transparent: bool,
+) -> wgpu::RenderPipeline {
+    renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: vertex_entry_point,
+            buffers,
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            clamp_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DepthTexture::DEPTH_FORMAT,
+            depth_write_enabled: !transparent,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState {
+                front: wgpu::StencilFaceState::IGNORE,
+                back: wgpu::StencilFaceState::IGNORE,
+                read_mask: 0,
+                write_mask: 0,
+            },
+            bias: wgpu::DepthBiasState {
+                constant: 0,
+                slope_scale: 0.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fragment_entry_point,
+            targets: &[wgpu::ColorTargetState {
+                format: HdrTexture::HDR_FORMAT,
+                blend: if transparent {
+                    Some(wgpu::BlendState::ALPHA_BLENDING)
+                } else {
+                    None
+                },
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+        }),
+    })
+}
@@ -0,0 +1,460 @@
+use crate::renderer::{
+    error::RendererError, hdr_texture::HdrTexture, Frame, RenderPass, Renderer, COLOR, DEPTH, TARGET,
+};
+use glam::Mat4;
+use std::borrow::Cow;
+
+// tonemapped image that fxaa reads
+const LDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+#[derive(Debug, Copy, Clone)]
+pub struct BloomSettings {
+    pub enabled: bool,
+    // luminance above which colors bloom
+    pub threshold: f32,
+    pub intensity: f32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct FogSettings {
+    pub enabled: bool,
+    pub color: [f32; 3],
+    // distance from the camera where the fog starts and where it is opaque
+    pub start: f32,
+    pub end: f32,
+    // above this height the fog thins out by the falloff per meter
+    pub height: f32,
+    pub height_falloff: f32,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct PostProcessSettings {
+    pub exposure: f32,
+    // colors are clamped without tonemapping
+    pub tonemapping: bool,
+    pub bloom: BloomSettings,
+    pub fog: FogSettings,
+    pub fxaa: bool,
+}
+
+impl Default for PostProcessSettings {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            tonemapping: true,
+            bloom: BloomSettings {
+                enabled: true,
+                threshold: 1.0,
+                intensity: 0.5,
+            },
+            fog: FogSettings {
+                enabled: true,
+                color: [0.5, 0.6, 0.7],
+                start: 20.0,
+                end: 32.0,
+                height: 8.0,
+                height_falloff: 0.1,
+            },
+            fxaa: true,
+        }
+    }
+}
+
+// same as fog_factor in post_process.wgsl, 0.0 is no fog and 1.0 only fog
+pub fn fog_factor(settings: &FogSettings, distance: f32, height: f32) -> f32 {
+    let t = ((distance - settings.start) / (settings.end - settings.start))
+        .max(0.0)
+        .min(1.0);
+    let height_factor = (-(height - settings.height).max(0.0) * settings.height_falloff).exp();
+    t * t * (3.0 - 2.0 * t) * height_factor
+}
+
+// same as tonemap in post_process.wgsl
+pub fn tonemap(color: [f32; 3]) -> [f32; 3] {
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    let curve = |x: f32| ((x * (a * x + b)) / (x * (c * x + d) + e)).max(0.0).min(1.0);
+    [curve(color[0]), curve(color[1]), curve(color[2])]
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct PostProcessUniform {
+    inverse_view_proj: Mat4,
+    camera_position: [f32; 4],
+    fog_color: [f32; 4],
+    fog: [f32; 4],
+    parameters: [f32; 4],
+    flags: [u32; 4],
+    texel_size: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for PostProcessUniform {}
+unsafe impl bytemuck::Zeroable for PostProcessUniform {}
+
+// textures and bind groups that depend on the size of the swap chain
+struct Targets {
+    width: u32,
+    height: u32,
+    // the bright pass and the vertical blur write the first, the horizontal blur the second
+    bloom: [(wgpu::Texture, wgpu::TextureView); 2],
+    ldr: (wgpu::Texture, wgpu::TextureView),
+    bright_bind_group: wgpu::BindGroup,
+    blur_horizontal_bind_group: wgpu::BindGroup,
+    blur_vertical_bind_group: wgpu::BindGroup,
+    composite_bind_group: wgpu::BindGroup,
+    fxaa_bind_group: wgpu::BindGroup,
+}
+
+// resolves the hdr texture into the target with fog, bloom, exposure, tonemapping and fxaa
+pub struct PostProcessPipeline {
+    uniform: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bright_pipeline: wgpu::RenderPipeline,
+    blur_horizontal_pipeline: wgpu::RenderPipeline,
+    blur_vertical_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    composite_ldr_pipeline: wgpu::RenderPipeline,
+    fxaa_pipeline: wgpu::RenderPipeline,
+    targets: Option<Targets>,
+    settings: PostProcessSettings,
+}
+
+impl PostProcessPipeline {
+    pub async fn new(renderer: &Renderer) -> Result<Self, RendererError> {
+        let uniform = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            size: std::mem::size_of::<PostProcessUniform>() as u64,
+            mapped_at_creation: false,
+        });
+        let sampler = renderer.device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let texture_entry = |binding: u32, sample_type: wgpu::TextureSampleType| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStage::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type,
+            },
+            count: None,
+        };
+        let bind_group_layout = renderer
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                    texture_entry(1, wgpu::TextureSampleType::Float { filterable: true }),
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStage::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler {
+                            filtering: true,
+                            comparison: false,
+                        },
+                        count: None,
+                    },
+                    texture_entry(3, wgpu::TextureSampleType::Float { filterable: false }),
+                    texture_entry(4, wgpu::TextureSampleType::Float { filterable: true }),
+                ],
+                label: None,
+            });
+        let shader = renderer.device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/post_process.wgsl"))),
+            flags: wgpu::ShaderFlags::EXPERIMENTAL_TRANSLATION,
+        });
+        let layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point: &str, format: wgpu::TextureFormat| {
+            create_render_pipeline(renderer, &layout, &shader, entry_point, format)
+        };
+        Ok(Self {
+            uniform,
+            sampler,
+            bright_pipeline: pipeline("fs_bright", HdrTexture::HDR_FORMAT),
+            blur_horizontal_pipeline: pipeline("fs_blur_horizontal", HdrTexture::HDR_FORMAT),
+            blur_vertical_pipeline: pipeline("fs_blur_vertical", HdrTexture::HDR_FORMAT),
+            composite_pipeline: pipeline("fs_composite", renderer.swap_chain_descriptor.format),
+            composite_ldr_pipeline: pipeline("fs_composite", LDR_FORMAT),
+            fxaa_pipeline: pipeline("fs_fxaa", renderer.swap_chain_descriptor.format),
+            bind_group_layout,
+            targets: None,
+            settings: renderer.settings.post_process,
+        })
+    }
+
+    fn create_targets(&self, renderer: &Renderer) -> Targets {
+        let width = renderer.swap_chain_descriptor.width;
+        let height = renderer.swap_chain_descriptor.height;
+        let texture = |width: u32, height: u32, format: wgpu::TextureFormat| {
+            let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsage::RENDER_ATTACHMENT | wgpu::TextureUsage::SAMPLED,
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            (texture, view)
+        };
+        // bloom is blurred at half the resolution
+        let (bloom_width, bloom_height) = ((width / 2).max(1), (height / 2).max(1));
+        let bloom = [
+            texture(bloom_width, bloom_height, HdrTexture::HDR_FORMAT),
+            texture(bloom_width, bloom_height, HdrTexture::HDR_FORMAT),
+        ];
+        let ldr = texture(width, height, LDR_FORMAT);
+        // a texture that is rendered into is bound as the unused bloom texture of the other passes
+        let bind_group = |input: &wgpu::TextureView, bloom: &wgpu::TextureView| {
+            renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(input),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::TextureView(&renderer.depth_texture.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(bloom),
+                    },
+                ],
+            })
+        };
+        let hdr = &renderer.hdr_texture.view;
+        Targets {
+            width,
+            height,
+            bright_bind_group: bind_group(hdr, &bloom[1].1),
+            blur_horizontal_bind_group: bind_group(&bloom[0].1, &bloom[0].1),
+            blur_vertical_bind_group: bind_group(&bloom[1].1, &bloom[1].1),
+            composite_bind_group: bind_group(hdr, &bloom[0].1),
+            fxaa_bind_group: bind_group(&ldr.1, &bloom[0].1),
+            bloom,
+            ldr,
+        }
+    }
+}
+
+impl RenderPass for PostProcessPipeline {
+    fn name(&self) -> &'static str {
+        "post_process"
+    }
+
+    fn inputs(&self) -> &[&'static str] {
+        &[COLOR, DEPTH]
+    }
+
+    fn outputs(&self) -> &[&'static str] {
+        &[TARGET]
+    }
+
+    fn prepare(&mut self, frame: &mut Frame) {
+        let renderer = frame.renderer;
+        let (width, height) = (
+            renderer.swap_chain_descriptor.width,
+            renderer.swap_chain_descriptor.height,
+        );
+        let resized = match &self.targets {
+            Some(targets) => targets.width != width || targets.height != height,
+            None => true,
+        };
+        if resized {
+            self.targets = Some(self.create_targets(renderer));
+        }
+        let settings = renderer.settings.post_process;
+        let camera = frame.scene.camera;
+        let position = camera.get_position();
+        let fog = settings.fog;
+        let bloom_size = [(width / 2).max(1) as f32, (height / 2).max(1) as f32];
+        let uniform = PostProcessUniform {
+            inverse_view_proj: (camera.get_projection() * camera.get_view()).inverse(),
+            camera_position: [position.x, position.y, position.z, 1.0],
+            fog_color: [fog.color[0], fog.color[1], fog.color[2], 1.0],
+            fog: [fog.start, fog.end, fog.height, fog.height_falloff],
+            parameters: [
+                settings.exposure,
+                settings.bloom.threshold,
+                settings.bloom.intensity,
+                0.0,
+            ],
+            flags: [
+                settings.tonemapping as u32,
+                settings.bloom.enabled as u32,
+                fog.enabled as u32,
+                0,
+            ],
+            texel_size: [
+                1.0 / width as f32,
+                1.0 / height as f32,
+                1.0 / bloom_size[0],
+                1.0 / bloom_size[1],
+            ],
+        };
+        renderer
+            .queue
+            .write_buffer(&self.uniform, 0, bytemuck::cast_slice(&[uniform]));
+        self.settings = settings;
+    }
+
+    fn record(&self, frame: &Frame, encoder: &mut wgpu::CommandEncoder) {
+        let targets = self.targets.as_ref().unwrap();
+        if self.settings.bloom.enabled {
+            draw_fullscreen(
+                encoder,
+                &self.bright_pipeline,
+                &targets.bright_bind_group,
+                &targets.bloom[0].1,
+            );
+            draw_fullscreen(
+                encoder,
+                &self.blur_horizontal_pipeline,
+                &targets.blur_horizontal_bind_group,
+                &targets.bloom[1].1,
+            );
+            draw_fullscreen(
+                encoder,
+                &self.blur_vertical_pipeline,
+                &targets.blur_vertical_bind_group,
+                &targets.bloom[0].1,
+            );
+        }
+        if self.settings.fxaa {
+            draw_fullscreen(
+                encoder,
+                &self.composite_ldr_pipeline,
+                &targets.composite_bind_group,
+                &targets.ldr.1,
+            );
+            draw_fullscreen(encoder, &self.fxaa_pipeline, &targets.fxaa_bind_group, frame.target);
+        } else {
+            draw_fullscreen(
+                encoder,
+                &self.composite_pipeline,
+                &targets.composite_bind_group,
+                frame.target,
+            );
+        }
+    }
+}
+
+fn draw_fullscreen(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    target: &wgpu::TextureView,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[wgpu::RenderPassColorAttachment {
+            view: target,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: None,
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+}
+
+fn create_render_pipeline(
+    renderer: &Renderer,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    fragment_entry_point: &str,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            clamp_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: fragment_entry_point,
+            targets: &[format.into()],
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::{fog_factor, tonemap, PostProcessSettings};
+
+    #[test]
+    fn fog_grows_with_distance_and_thins_out_above_its_height() {
+        let fog = PostProcessSettings::default().fog;
+        assert_eq!(0.0, fog_factor(&fog, fog.start, 0.0));
+        assert_eq!(1.0, fog_factor(&fog, fog.end, fog.height));
+        let middle = fog_factor(&fog, (fog.start + fog.end) / 2.0, fog.height);
+        assert!((middle - 0.5).abs() < 1e-6);
+        assert!(fog_factor(&fog, fog.end, fog.height + 10.0) < 1.0);
+        assert_eq!(1.0, fog_factor(&fog, 10.0 * fog.end, 0.0));
+    }
+
+    #[test]
+    fn tonemapping_keeps_colors_in_range() {
+        assert_eq!([0.0, 0.0, 0.0], tonemap([0.0, 0.0, 0.0]));
+        let bright = tonemap([100.0, 1.0, 0.1]);
+        assert!(bright[0] <= 1.0 && bright[0] > 0.99);
+        assert!(bright[1] > bright[2] && bright[1] < bright[0]);
+    }
+}
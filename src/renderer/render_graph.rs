@@ -0,0 +1,250 @@
+use crate::{
+    entity::Entities,
+    registry::{AssetHandle, Assets},
+    renderer::{
+        culling::{CasterDraws, Culling, Draw, RenderStats, TransparentDraw},
+        error::RendererError,
+        shadow::Cascade,
+        BindGroup, Camera, ChunkDraws, DebugDraw, DebugPipeline, LightPipeline, Mesh, Pipeline, PostProcessPipeline,
+        Renderer, ShadowPipeline, Sky, SkyPipeline, TransparentPipeline,
+    },
+    world::World,
+};
+
+// slots are the textures passes read and write, a pass that writes a slot runs before every pass that only reads it
+pub const SHADOW_MAP: &str = "shadow_map";
+pub const COLOR: &str = "color";
+pub const DEPTH: &str = "depth";
+// the view a frame is rendered into, written by the post processing
+pub const TARGET: &str = "target";
+
+// what is rendered in a frame
+pub struct SceneView<'a> {
+    pub world: &'a World,
+    pub entities: &'a Entities,
+    pub meshes: &'a Assets<Mesh>,
+    pub camera: &'a dyn Camera,
+    // chunks are drawn around this position
+    pub position: [f32; 3],
+    pub sky: Sky,
+    pub debug: &'a DebugDraw,
+}
+
+pub struct Frame<'a> {
+    pub renderer: &'a Renderer,
+    // the scene passes render into the hdr texture of the renderer
+    pub target: &'a wgpu::TextureView,
+    pub scene: SceneView<'a>,
+    // uniforms, instances, lights and the shadow map that the scene passes share
+    pub bind_group: &'a mut BindGroup,
+    pub stats: RenderStats,
+    pub(crate) draws: Vec<Draw>,
+    pub(crate) chunk_draws: ChunkDraws,
+    pub(crate) transparent_draws: Vec<TransparentDraw>,
+    pub(crate) cascades: Vec<Cascade>,
+    // one per cascade
+    pub(crate) casters: Vec<CasterDraws>,
+}
+
+impl<'a> Frame<'a> {
+    pub fn new(
+        renderer: &'a Renderer,
+        target: &'a wgpu::TextureView,
+        scene: SceneView<'a>,
+        bind_group: &'a mut BindGroup,
+    ) -> Self {
+        Self {
+            renderer,
+            target,
+            scene,
+            bind_group,
+            stats: RenderStats::default(),
+            draws: Vec::new(),
+            chunk_draws: ChunkDraws::default(),
+            transparent_draws: Vec::new(),
+            cascades: Vec::new(),
+            casters: Vec::new(),
+        }
+    }
+}
+
+pub trait RenderPass {
+    fn name(&self) -> &'static str;
+    fn inputs(&self) -> &[&'static str];
+    fn outputs(&self) -> &[&'static str];
+    // called for every pass in order before anything is recorded, buffer writes land before the submission
+    fn prepare(&mut self, _frame: &mut Frame) {}
+    fn record(&self, frame: &Frame, encoder: &mut wgpu::CommandEncoder);
+}
+
+// passes are ordered by their slots and recorded into one command buffer per frame
+#[derive(Default)]
+pub struct RenderGraph {
+    passes: Vec<Box<dyn RenderPass>>,
+    order: Vec<usize>,
+    culling: Culling,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // shadows, the opaque scene, the light gizmos, the sky, the transparent scene, the post processing and the debug lines
+    pub async fn standard(
+        renderer: &Renderer,
+        bind_group: &BindGroup,
+        light_mesh: AssetHandle<Mesh>,
+    ) -> Result<Self, RendererError> {
+        let mut graph = Self::new();
+        graph.add_pass(ShadowPipeline::new(renderer, &bind_group.instances, &bind_group.joints))?;
+        graph.add_pass(Pipeline::new(renderer, bind_group).await?)?;
+        graph.add_pass(LightPipeline::new(renderer, light_mesh).await?)?;
+        graph.add_pass(SkyPipeline::new(renderer).await?)?;
+        graph.add_pass(TransparentPipeline::new(renderer, bind_group).await?)?;
+        graph.add_pass(PostProcessPipeline::new(renderer).await?)?;
+        graph.add_pass(DebugPipeline::new(renderer).await?)?;
+        Ok(graph)
+    }
+
+    // passes writing the same slot keep the order in which they are added
+    pub fn add_pass(&mut self, pass: impl RenderPass + 'static) -> Result<(), RendererError> {
+        self.passes.push(Box::new(pass));
+        match execution_order(&self.passes) {
+            Ok(order) => {
+                self.order = order;
+                Ok(())
+            }
+            Err(e) => {
+                self.passes.pop();
+                Err(e)
+            }
+        }
+    }
+
+    pub fn pass_names(&self) -> Vec<&'static str> {
+        self.order.iter().map(|i| self.passes[*i].name()).collect()
+    }
+
+    // the scene is culled once for all passes, the cascades only when a pass renders the shadow map
+    pub fn execute(&mut self, frame: &mut Frame) {
+        let shadows = self.passes.iter().any(|pass| pass.outputs().contains(&SHADOW_MAP));
+        self.culling.cull(frame, shadows);
+        for i in &self.order {
+            self.passes[*i].prepare(frame);
+        }
+        let mut encoder = frame
+            .renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        for i in &self.order {
+            self.passes[*i].record(frame, &mut encoder);
+        }
+        frame.renderer.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+// topological order that prefers the order in which passes were added
+fn execution_order(passes: &[Box<dyn RenderPass>]) -> Result<Vec<usize>, RendererError> {
+    let writes = |pass: usize, slot: &str| passes[pass].outputs().contains(&slot);
+    let mut dependencies = vec![Vec::new(); passes.len()];
+    for (i, pass) in passes.iter().enumerate() {
+        for slot in pass.inputs().iter().chain(pass.outputs().iter()) {
+            for writer in 0..passes.len() {
+                let earlier_writer = writer < i && writes(writer, slot);
+                let writer_of_input = !writes(i, slot) && writes(writer, slot);
+                if writer != i && (earlier_writer || writer_of_input) && !dependencies[i].contains(&writer) {
+                    dependencies[i].push(writer);
+                }
+            }
+        }
+    }
+    let mut order = Vec::with_capacity(passes.len());
+    while order.len() < passes.len() {
+        let next = (0..passes.len())
+            .find(|i| !order.contains(i) && dependencies[*i].iter().all(|dependency| order.contains(dependency)));
+        match next {
+            Some(i) => order.push(i),
+            None => {
+                return Err(RendererError::RenderGraph(format!(
+                    "cycle between the passes {:?}",
+                    (0..passes.len())
+                        .filter(|i| !order.contains(i))
+                        .map(|i| passes[i].name())
+                        .collect::<Vec<_>>()
+                )))
+            }
+        }
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::renderer::{Frame, RenderGraph, RenderPass, COLOR, DEPTH, SHADOW_MAP};
+
+    struct TestPass {
+        name: &'static str,
+        inputs: Vec<&'static str>,
+        outputs: Vec<&'static str>,
+    }
+
+    impl RenderPass for TestPass {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn inputs(&self) -> &[&'static str] {
+            &self.inputs
+        }
+
+        fn outputs(&self) -> &[&'static str] {
+            &self.outputs
+        }
+
+        fn record(&self, _frame: &Frame, _encoder: &mut wgpu::CommandEncoder) {}
+    }
+
+    fn pass(name: &'static str, inputs: &[&'static str], outputs: &[&'static str]) -> TestPass {
+        TestPass {
+            name,
+            inputs: inputs.to_vec(),
+            outputs: outputs.to_vec(),
+        }
+    }
+
+    #[test]
+    fn passes_run_after_the_writers_of_their_inputs() {
+        let mut graph = RenderGraph::new();
+        graph.add_pass(pass("post_process", &[COLOR], &["swap_chain"])).unwrap();
+        graph.add_pass(pass("opaque", &[SHADOW_MAP], &[COLOR, DEPTH])).unwrap();
+        graph
+            .add_pass(pass("lights", &[COLOR, DEPTH], &[COLOR, DEPTH]))
+            .unwrap();
+        graph.add_pass(pass("ui", &["swap_chain"], &["swap_chain"])).unwrap();
+        graph.add_pass(pass("shadow", &[], &[SHADOW_MAP])).unwrap();
+        assert_eq!(
+            vec!["shadow", "opaque", "lights", "post_process", "ui"],
+            graph.pass_names()
+        );
+    }
+
+    #[test]
+    fn independent_passes_keep_their_order() {
+        let mut graph = RenderGraph::new();
+        graph.add_pass(pass("a", &[], &["a"])).unwrap();
+        graph.add_pass(pass("b", &[], &["b"])).unwrap();
+        graph.add_pass(pass("c", &["a", "b"], &[COLOR])).unwrap();
+        graph.add_pass(pass("d", &[], &["d"])).unwrap();
+        assert_eq!(vec!["a", "b", "c", "d"], graph.pass_names());
+    }
+
+    #[test]
+    fn cycles_are_rejected() {
+        let mut graph = RenderGraph::new();
+        graph.add_pass(pass("a", &["y"], &["x"])).unwrap();
+        assert!(graph.add_pass(pass("b", &["x"], &["y"])).is_err());
+        // the graph is unchanged
+        assert_eq!(vec!["a"], graph.pass_names());
+    }
+}
@@ -1,13 +1,20 @@
-use crate::renderer::{depth_texture::DepthTexture, error::RendererError};
+use crate::renderer::{
+    depth_texture::DepthTexture, error::RendererError, hdr_texture::HdrTexture, RendererSettings, Texture,
+};
 use winit::window::Window;
 
 pub struct Renderer {
-    surface: wgpu::Surface,
+    // headless renderers have no surface and no swap chain, they render into an OffscreenTarget
+    surface: Option<wgpu::Surface>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub swap_chain_descriptor: wgpu::SwapChainDescriptor,
-    pub swap_chain: wgpu::SwapChain,
+    pub swap_chain: Option<wgpu::SwapChain>,
     pub depth_texture: DepthTexture,
+    pub hdr_texture: HdrTexture,
+    // meshes create the bind groups of their textures with it
+    pub material_bind_group_layout: wgpu::BindGroupLayout,
+    pub settings: RendererSettings,
 }
 
 impl Renderer {
@@ -26,43 +33,106 @@ impl Renderer {
                 return Err(RendererError::RequestAdapter);
             }
         };
-        let (device, queue) = adapter
+        let (device, queue) = Self::request_device(&adapter).await?;
+        let swap_chain_descriptor = Self::swap_chain_descriptor(window.inner_size().width, window.inner_size().height);
+        let depth_texture = DepthTexture::create_depth_texture(&device, &swap_chain_descriptor);
+        let hdr_texture = HdrTexture::create_hdr_texture(&device, &swap_chain_descriptor);
+        let material_bind_group_layout = Texture::material_bind_group_layout(&device);
+        let swap_chain = device.create_swap_chain(&surface, &swap_chain_descriptor);
+        Ok(Self {
+            surface: Some(surface),
+            device,
+            queue,
+            swap_chain_descriptor,
+            swap_chain: Some(swap_chain),
+            depth_texture,
+            hdr_texture,
+            material_bind_group_layout,
+            settings: RendererSettings::default(),
+        })
+    }
+
+    // falls back to a software adapter when there is no gpu
+    pub async fn new_headless(width: u32, height: u32) -> Result<Self, RendererError> {
+        let instance = wgpu::Instance::new(wgpu::BackendBit::all());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: Default::default(),
+                compatible_surface: None,
+            })
+            .await;
+        let adapter = match adapter {
+            Some(adapter) => adapter,
+            None => {
+                // prefer a software adapter, like lavapipe or warp
+                let mut adapters = instance.enumerate_adapters(wgpu::BackendBit::all()).collect::<Vec<_>>();
+                adapters.sort_by_key(|adapter| adapter.get_info().device_type != wgpu::DeviceType::Cpu);
+                match adapters.into_iter().next() {
+                    Some(adapter) => adapter,
+                    None => return Err(RendererError::RequestAdapter),
+                }
+            }
+        };
+        let (device, queue) = Self::request_device(&adapter).await?;
+        let swap_chain_descriptor = Self::swap_chain_descriptor(width, height);
+        let depth_texture = DepthTexture::create_depth_texture(&device, &swap_chain_descriptor);
+        let hdr_texture = HdrTexture::create_hdr_texture(&device, &swap_chain_descriptor);
+        let material_bind_group_layout = Texture::material_bind_group_layout(&device);
+        Ok(Self {
+            surface: None,
+            device,
+            queue,
+            swap_chain_descriptor,
+            swap_chain: None,
+            depth_texture,
+            hdr_texture,
+            material_bind_group_layout,
+            settings: RendererSettings::default(),
+        })
+    }
+
+    // chunks are drawn with multi draw indirect when the adapter supports it
+    async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue), RendererError> {
+        Ok(adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: Default::default(),
+                    features: adapter.features() & wgpu::Features::MULTI_DRAW_INDIRECT,
                     limits: Default::default(),
                 },
                 None,
             )
-            .await
-            .unwrap();
+            .await?)
+    }
 
-        let swap_chain_descriptor = wgpu::SwapChainDescriptor {
+    // also describes offscreen targets, so pipelines work for both
+    fn swap_chain_descriptor(width: u32, height: u32) -> wgpu::SwapChainDescriptor {
+        wgpu::SwapChainDescriptor {
             usage: wgpu::TextureUsage::RENDER_ATTACHMENT,
             format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            width: window.inner_size().width,
-            height: window.inner_size().height,
+            width,
+            height,
             present_mode: wgpu::PresentMode::Fifo, // performance: change to Immediate for no vsync
-        };
-        let depth_texture = DepthTexture::create_depth_texture(&device, &swap_chain_descriptor);
-        let swap_chain = device.create_swap_chain(&surface, &swap_chain_descriptor);
-        Ok(Self {
-            surface,
-            device,
-            queue,
-            swap_chain_descriptor,
-            swap_chain,
-            depth_texture,
-        })
+        }
+    }
+
+    pub fn get_current_frame(&self) -> Result<wgpu::SwapChainFrame, RendererError> {
+        match &self.swap_chain {
+            Some(swap_chain) => Ok(swap_chain.get_current_frame()?),
+            None => Err(RendererError::Headless),
+        }
     }
 
     pub async fn resize(&mut self, width: u32, height: u32) {
-        self.swap_chain_descriptor.width = width;
-        self.swap_chain_descriptor.height = height;
-        self.depth_texture = DepthTexture::create_depth_texture(&self.device, &self.swap_chain_descriptor);
-        self.swap_chain = self
-            .device
-            .create_swap_chain(&self.surface, &self.swap_chain_descriptor);
+        // passes keep bind groups of the targets as long as the size does not change
+        if width != self.swap_chain_descriptor.width || height != self.swap_chain_descriptor.height {
+            self.swap_chain_descriptor.width = width;
+            self.swap_chain_descriptor.height = height;
+            self.depth_texture = DepthTexture::create_depth_texture(&self.device, &self.swap_chain_descriptor);
+            self.hdr_texture = HdrTexture::create_hdr_texture(&self.device, &self.swap_chain_descriptor);
+        }
+        if let Some(surface) = &self.surface {
+            self.swap_chain = Some(self.device.create_swap_chain(surface, &self.swap_chain_descriptor));
+        }
     }
 }
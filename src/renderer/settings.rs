@@ -0,0 +1,9 @@
+use crate::renderer::{DebugSettings, PostProcessSettings, ShadowSettings, SkySettings};
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RendererSettings {
+    pub shadows: ShadowSettings,
+    pub post_process: PostProcessSettings,
+    pub sky: SkySettings,
+    pub debug: DebugSettings,
+}
@@ -47,7 +47,7 @@ fn vs_main([[builtin(instance_index)]] instance_idx: u32, [[location(0)]] model_
 [[stage(fragment)]]
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
     let normal = normalize(in.world_normal);
-    let result = vec3<f32>(1.0, 1.0, 1.0);
-    let gamma: f32 = 2.2;
-    return vec4<f32>(pow(result, vec3<f32>(1.0 / gamma)), 1.0);
+    // brighter than white so the lights bloom
+    let result = vec3<f32>(4.0, 4.0, 4.0);
+    return vec4<f32>(result, 1.0);
 }
@@ -0,0 +1,168 @@
+// validate shader cargo run --features wgsl-in -- ../../xp-vox-engine/src/renderer/shaders/post_process.wgsl
+[[block]]
+struct PostProcess {
+    inverse_view_proj: mat4x4<f32>;
+    camera_position: vec4<f32>;
+    fog_color: vec4<f32>;
+    // start, end, height and height falloff
+    fog: vec4<f32>;
+    // exposure, bloom threshold, bloom intensity
+    parameters: vec4<f32>;
+    // tonemapping, bloom and fog are enabled when not 0
+    flags: vec4<u32>;
+    // texel size of the hdr texture in xy, of the bloom textures in zw
+    texel_size: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> post_process: PostProcess;
+[[group(0), binding(1)]]
+var t_input: texture_2d<f32>;
+[[group(0), binding(2)]]
+var s_linear: sampler;
+// bound as a float texture, the glsl backend can not load from depth textures
+[[group(0), binding(3)]]
+var t_depth: texture_2d<f32>;
+[[group(0), binding(4)]]
+var t_bloom: texture_2d<f32>;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] uv: vec2<f32>;
+};
+
+// one triangle that covers the screen
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let x = f32((vertex_index << 1u) & 2u);
+    let y = f32(vertex_index & 2u);
+    var out: VertexOutput;
+    out.position = vec4<f32>(x * 2.0 - 1.0, 1.0 - y * 2.0, 0.0, 1.0);
+    out.uv = vec2<f32>(x, y);
+    return out;
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// downsamples to the bloom resolution and keeps what is brighter than the threshold
+[[stage(fragment)]]
+fn fs_bright(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let offset = post_process.texel_size.xy * 0.5;
+    let color = 0.25 * (textureSample(t_input, s_linear, in.uv + vec2<f32>(-offset.x, -offset.y)).rgb
+        + textureSample(t_input, s_linear, in.uv + vec2<f32>(offset.x, -offset.y)).rgb
+        + textureSample(t_input, s_linear, in.uv + vec2<f32>(-offset.x, offset.y)).rgb
+        + textureSample(t_input, s_linear, in.uv + vec2<f32>(offset.x, offset.y)).rgb);
+    let brightness = luminance(color);
+    let bright = max(brightness - post_process.parameters.y, 0.0) / max(brightness, 0.0001);
+    return vec4<f32>(color * bright, 1.0);
+}
+
+// 9 tap gaussian with linear sampling between the taps
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    let texel_step = direction * post_process.texel_size.zw;
+    var color: vec3<f32> = textureSample(t_input, s_linear, uv).rgb * 0.2270270270;
+    color = color + textureSample(t_input, s_linear, uv + texel_step * 1.3846153846).rgb * 0.3162162162;
+    color = color + textureSample(t_input, s_linear, uv - texel_step * 1.3846153846).rgb * 0.3162162162;
+    color = color + textureSample(t_input, s_linear, uv + texel_step * 3.2307692308).rgb * 0.0702702703;
+    color = color + textureSample(t_input, s_linear, uv - texel_step * 3.2307692308).rgb * 0.0702702703;
+    return vec4<f32>(color, 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_blur_horizontal(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return blur(in.uv, vec2<f32>(1.0, 0.0));
+}
+
+[[stage(fragment)]]
+fn fs_blur_vertical(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return blur(in.uv, vec2<f32>(0.0, 1.0));
+}
+
+// same as fog_factor in post_process.rs
+fn fog_factor(view_distance: f32, height: f32) -> f32 {
+    let fog = post_process.fog;
+    let height_factor = exp(-max(height - fog.z, 0.0) * fog.w);
+    return smoothStep(fog.x, fog.y, view_distance) * height_factor;
+}
+
+// aces filmic curve fitted by krzysztof narkowicz, same as tonemap in post_process.rs
+fn tonemap(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0));
+}
+
+// fog, bloom, exposure and tonemapping of the hdr texture
+[[stage(fragment)]]
+fn fs_composite(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    var color: vec3<f32> = textureSample(t_input, s_linear, in.uv).rgb;
+    let bloom = textureSample(t_bloom, s_linear, in.uv).rgb;
+    let depth = textureLoad(t_depth, vec2<i32>(i32(in.position.x), i32(in.position.y)), 0).x;
+
+    // the sky at the far plane is not fogged, the fog color matches its horizon
+    if (post_process.flags.z != 0u && depth < 1.0) {
+        // the scene fades into the sky, which hides where the chunks end
+        let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth, 1.0);
+        let world = post_process.inverse_view_proj * ndc;
+        let world_position = world.xyz / world.w;
+        let view_distance = distance(world_position, post_process.camera_position.xyz);
+        let fog = fog_factor(view_distance, world_position.y);
+        color = mix(color, post_process.fog_color.rgb, vec3<f32>(fog, fog, fog));
+    }
+    if (post_process.flags.y != 0u) {
+        color = color + bloom * post_process.parameters.z;
+    }
+    color = color * post_process.parameters.x;
+    if (post_process.flags.x != 0u) {
+        color = tonemap(color);
+    } else {
+        color = clamp(color, vec3<f32>(0.0, 0.0, 0.0), vec3<f32>(1.0, 1.0, 1.0));
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(luminance(color));
+}
+
+// fast approximate anti aliasing of the tonemapped image
+[[stage(fragment)]]
+fn fs_fxaa(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let reduce_min = 1.0 / 128.0;
+    let reduce_mul = 1.0 / 8.0;
+    let span_max = 8.0;
+    let texel = post_process.texel_size.xy;
+
+    let rgb_nw = textureSample(t_input, s_linear, in.uv + vec2<f32>(-1.0, -1.0) * texel).rgb;
+    let rgb_ne = textureSample(t_input, s_linear, in.uv + vec2<f32>(1.0, -1.0) * texel).rgb;
+    let rgb_sw = textureSample(t_input, s_linear, in.uv + vec2<f32>(-1.0, 1.0) * texel).rgb;
+    let rgb_se = textureSample(t_input, s_linear, in.uv + vec2<f32>(1.0, 1.0) * texel).rgb;
+    let rgb_m = textureSample(t_input, s_linear, in.uv).rgb;
+    let luma_nw = luma(rgb_nw);
+    let luma_ne = luma(rgb_ne);
+    let luma_sw = luma(rgb_sw);
+    let luma_se = luma(rgb_se);
+    let luma_m = luma(rgb_m);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // blur along the edge
+    let edge = vec2<f32>(-((luma_nw + luma_ne) - (luma_sw + luma_se)), (luma_nw + luma_sw) - (luma_ne + luma_se));
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * reduce_mul, reduce_min);
+    let scale = 1.0 / (min(abs(edge.x), abs(edge.y)) + reduce);
+    let direction = clamp(edge * scale, vec2<f32>(-span_max, -span_max), vec2<f32>(span_max, span_max)) * texel;
+
+    let rgb_a = 0.5 * (textureSample(t_input, s_linear, in.uv + direction * (1.0 / 3.0 - 0.5)).rgb
+        + textureSample(t_input, s_linear, in.uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+    let rgb_b = rgb_a * 0.5 + 0.25 * (textureSample(t_input, s_linear, in.uv - direction * 0.5).rgb
+        + textureSample(t_input, s_linear, in.uv + direction * 0.5).rgb);
+    let luma_b = luma(rgb_b);
+    // the wider blur crossed another edge
+    let outside = luma_b < luma_min || luma_b > luma_max;
+    return vec4<f32>(select(rgb_b, rgb_a, outside), 1.0);
+}
@@ -45,6 +45,10 @@ struct Globals {
     nr_of_directional_lights: u32;
     nr_of_spot_lights: u32;
     nr_of_point_lights: u32;
+    cluster_dimensions: vec4<u32>;
+    // near, far and the number of depth slices divided by log(far / near)
+    cluster_depth: vec4<f32>;
+    viewport_size: vec4<f32>;
 };
 
 [[group(0), binding(0)]]
@@ -52,7 +56,7 @@ var<uniform> u_globals: Globals;
 
 [[block]]
 struct DirectionalLights {
-    lights: array<DirectionalLight, 1>;
+    lights: array<DirectionalLight, 1u>;
 };
 
 [[group(0), binding(1)]]
@@ -60,23 +64,27 @@ var<uniform> directional_lights: DirectionalLights;
 
 [[block]]
 struct SpotLights {
-    lights: array<SpotLight, 10>;
+    lights: array<SpotLight>;
 };
 
 [[group(0), binding(2)]]
-var<uniform> spot_lights: SpotLights;
+var<storage> spot_lights: [[access(read)]] SpotLights;
 
 [[block]]
 struct PointLights {
-    lights: array<PointLight, 10>;
+    lights: array<PointLight>;
 };
 
 [[group(0), binding(3)]]
-var<uniform> point_lights: PointLights;
+var<storage> point_lights: [[access(read)]] PointLights;
 
 struct Instance {
     model: mat4x4<f32>;
     inverse_model: mat4x4<f32>;
+    opacity: f32;
+    first_joint: u32;
+    p1: f32;
+    p2: f32;
 };
 
 [[block]]
@@ -87,17 +95,150 @@ struct Instances {
 [[group(0), binding(4)]]
 var<storage> models: [[access(read)]] Instances;
 
+// the glsl backend only writes arrays of scalars and structs in blocks
+struct CascadeViewProj {
+    m: mat4x4<f32>;
+};
+
+[[block]]
+struct Shadows {
+    view_proj: array<CascadeViewProj, 4u>;
+    far: vec4<f32>;
+    texel_size: vec4<f32>;
+    nr_of_cascades: u32;
+    depth_bias: f32;
+    normal_bias: f32;
+    pcf_texel: f32;
+};
+
+[[group(0), binding(5)]]
+var<uniform> shadows: Shadows;
+
+[[group(0), binding(6)]]
+var shadow_map: texture_depth_2d_array;
+
+[[group(0), binding(7)]]
+var shadow_sampler: sampler_comparison;
+
+// the light indices of a cluster start at offset, spot lights first and point lights after them
+struct Cluster {
+    offset: u32;
+    nr_of_spot_lights: u32;
+    nr_of_point_lights: u32;
+    p0: u32;
+};
+
+[[block]]
+struct Clusters {
+    clusters: array<Cluster>;
+};
+
+[[group(0), binding(8)]]
+var<storage> clusters: [[access(read)]] Clusters;
+
+[[block]]
+struct LightIndices {
+    indices: array<u32>;
+};
+
+[[group(0), binding(9)]]
+var<storage> light_indices: [[access(read)]] LightIndices;
+
+// joint matrices in the model space of the skinned instances, every instance starts at its first_joint
+struct Joint {
+    m: mat4x4<f32>;
+};
+
+[[block]]
+struct Joints {
+    joints: array<Joint>;
+};
+
+[[group(0), binding(10)]]
+var<storage> joints: [[access(read)]] Joints;
+
+// base color of the textured pipelines
+[[group(1), binding(0)]]
+var base_color_texture: texture_2d<f32>;
+
+[[group(1), binding(1)]]
+var base_color_sampler: sampler;
+
 struct VertexOutput {
     [[builtin(position)]] proj_position: vec4<f32>;
     [[location(0)]] world_position: vec3<f32>;
     [[location(1)]] world_normal: vec3<f32>;
     [[location(2)]] color: vec3<f32>;
+    [[location(3)]] emission: f32;
+    [[location(4)]] alpha: f32;
+    [[location(5)]] uv: vec2<f32>;
 };
 
+fn vertex(instance_idx: u32, model_position: vec3<f32>, model_normal: vec3<f32>, color: vec3<f32>) -> VertexOutput {
+    let view = u_globals.view;
+    let proj = u_globals.proj;
+    let model = models.models[instance_idx].model;
+    let inverse_transpose = transpose(models.models[instance_idx].inverse_model);
+    var out: VertexOutput;
+    out.proj_position = proj * view * model * vec4<f32>(model_position, 1.0);
+    out.world_position = (model * vec4<f32>(model_position, 1.0)).xyz;
+    out.world_normal = (inverse_transpose * vec4<f32>(model_normal, 1.0)).xyz;
+    out.color = color;
+    out.emission = 0.0;
+    out.alpha = models.models[instance_idx].opacity;
+    out.uv = vec2<f32>(0.0, 0.0);
+    return out;
+}
+
 [[stage(vertex)]]
 fn vs_main([[builtin(instance_index)]] instance_idx: u32, [[location(0)]] model_position: vec3<f32>,
            [[location(1)]] model_normal: vec3<f32>,
            [[location(2)]] color: vec3<f32>) -> VertexOutput {
+    return vertex(instance_idx, model_position, model_normal, color);
+}
+
+// the uvs come from a second vertex buffer
+[[stage(vertex)]]
+fn vs_textured([[builtin(instance_index)]] instance_idx: u32, [[location(0)]] model_position: vec3<f32>,
+               [[location(1)]] model_normal: vec3<f32>,
+               [[location(2)]] color: vec3<f32>,
+               [[location(3)]] uv: vec2<f32>) -> VertexOutput {
+    var out: VertexOutput = vertex(instance_idx, model_position, model_normal, color);
+    out.uv = uv;
+    return out;
+}
+
+// same as skin_vertex, the weights of a vertex add up to 1.0
+fn skin(first_joint: u32, joint_indices: vec4<u32>, weights: vec4<f32>, v: vec4<f32>) -> vec4<f32> {
+    return (joints.joints[first_joint + joint_indices.x].m * v) * weights.x
+        + (joints.joints[first_joint + joint_indices.y].m * v) * weights.y
+        + (joints.joints[first_joint + joint_indices.z].m * v) * weights.z
+        + (joints.joints[first_joint + joint_indices.w].m * v) * weights.w;
+}
+
+[[stage(vertex)]]
+fn vs_skinned([[builtin(instance_index)]] instance_idx: u32, [[location(0)]] model_position: vec3<f32>,
+              [[location(1)]] model_normal: vec3<f32>,
+              [[location(2)]] color: vec3<f32>,
+              [[location(3)]] joint_indices: vec4<u32>,
+              [[location(4)]] weights: vec4<f32>) -> VertexOutput {
+    let first_joint = models.models[instance_idx].first_joint;
+    let position = skin(first_joint, joint_indices, weights, vec4<f32>(model_position, 1.0));
+    let normal = skin(first_joint, joint_indices, weights, vec4<f32>(model_normal, 0.0));
+    return vertex(instance_idx, position.xyz, normal.xyz, color);
+}
+
+// position.w packs the normal index in bits 0..3, the ambient occlusion in bits 3..5, the translucency in bits 5..8
+// and the emission in bits 8..16, color.w is the palette index
+[[stage(vertex)]]
+fn vs_voxel([[builtin(instance_index)]] instance_idx: u32, [[location(0)]] position: vec4<u32>,
+            [[location(1)]] color: vec4<u32>) -> VertexOutput {
+    let normal_index = position.w & 7u;
+    let axis = normal_index / 2u;
+    let direction = 1.0 - 2.0 * f32(normal_index % 2u);
+    let model_normal = vec3<f32>(select(direction, 0.0, axis == 0u), select(direction, 0.0, axis == 1u), select(direction, 0.0, axis == 2u));
+    let ao = 0.4 + 0.6 * f32((position.w >> 3u) & 3u) / 3.0;
+    let model_position = vec3<f32>(f32(position.x), f32(position.y), f32(position.z));
     let view = u_globals.view;
     let proj = u_globals.proj;
     let model = models.models[instance_idx].model;
@@ -105,12 +246,61 @@ fn vs_main([[builtin(instance_index)]] instance_idx: u32, [[location(0)]] model_
     var out: VertexOutput;
     out.proj_position = proj * view * model * vec4<f32>(model_position, 1.0);
     out.world_position = (model * vec4<f32>(model_position, 1.0)).xyz;
-    out.world_normal = (inverse_transpose * vec4<f32>(model_normal, 1.0)).xyz;
-    out.color = color;
+    out.world_normal = (inverse_transpose * vec4<f32>(model_normal, 0.0)).xyz;
+    out.color = vec3<f32>(f32(color.x), f32(color.y), f32(color.z)) / 255.0 * ao;
+    // same scale as VOXEL_EMISSION_MAX
+    out.emission = f32(position.w >> 8u) / 255.0 * 8.0;
+    // same scale as VOXEL_TRANSLUCENCY_MAX
+    out.alpha = (1.0 - f32((position.w >> 5u) & 7u) / 8.0) * models.models[instance_idx].opacity;
+    out.uv = vec2<f32>(0.0, 0.0);
     return out;
 }
 
-fn calculate_directional_light(normal: vec3<f32>, view_direction: vec3<f32>, light: DirectionalLight, material_specular: vec3<f32>, material_shininess: f32, in_color: vec3<f32>) -> vec3<f32>
+// 1.0 is fully lit, the cascade is chosen by the view distance of the fragment
+fn calculate_shadow(world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if (shadows.nr_of_cascades == 0u) {
+        return 1.0;
+    }
+    let view_depth = -(u_globals.view * vec4<f32>(world_position, 1.0)).z;
+    var cascade: u32 = 0u;
+    loop {
+        if (cascade + 1u >= shadows.nr_of_cascades || view_depth < shadows.far[cascade]) {
+            break;
+        }
+        cascade = cascade + 1u;
+    }
+    if (view_depth > shadows.far[cascade]) {
+        return 1.0;
+    }
+    let offset_position = world_position + normal * shadows.normal_bias * shadows.texel_size[cascade];
+    let light_position = shadows.view_proj[cascade].m * vec4<f32>(offset_position, 1.0);
+    let coords = light_position.xyz / light_position.w;
+    // clip space y points up, texture v points down
+    let uv = vec2<f32>(coords.x * 0.5 + 0.5, coords.y * -0.5 + 0.5);
+    let depth = coords.z - shadows.depth_bias;
+    // 3x3 pcf
+    var lit: f32 = 0.0;
+    for(var x: i32 = -1; x <= 1; x = x + 1) {
+        for(var y: i32 = -1; y <= 1; y = y + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * shadows.pcf_texel;
+            lit = lit + textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, i32(cascade), depth);
+        }
+    }
+    return lit / 9.0;
+}
+
+// tiles are counted from the top left like fragment coordinates, depth slices grow exponentially
+fn find_cluster(frag_coord: vec4<f32>, world_position: vec3<f32>) -> Cluster {
+    let dimensions = u_globals.cluster_dimensions;
+    let view_depth = -(u_globals.view * vec4<f32>(world_position, 1.0)).z;
+    let x = min(u32(frag_coord.x / u_globals.viewport_size.x * f32(dimensions.x)), dimensions.x - 1u);
+    let y = min(u32(frag_coord.y / u_globals.viewport_size.y * f32(dimensions.y)), dimensions.y - 1u);
+    let slice = log(view_depth / u_globals.cluster_depth.x) * u_globals.cluster_depth.z;
+    let z = min(u32(max(slice, 0.0)), dimensions.z - 1u);
+    return clusters.clusters[(z * dimensions.y + y) * dimensions.x + x];
+}
+
+fn calculate_directional_light(normal: vec3<f32>, view_direction: vec3<f32>, light: DirectionalLight, material_specular: vec3<f32>, material_shininess: f32, in_color: vec3<f32>, shadow: f32) -> vec3<f32>
 {
     // negate light direction -> we want direction towards light
     let light_direction = normalize(-light.direction.xyz);
@@ -124,7 +314,7 @@ fn calculate_directional_light(normal: vec3<f32>, view_direction: vec3<f32>, lig
     let diffuse = light.diffuse.xyz * diff * in_color;
     let specular = light.specular.xyz * spec * material_specular.xyz;
 
-    return ambient + diffuse + specular;
+    return ambient + shadow * (diffuse + specular);
 }
 
 fn calculate_spot_light(normal: vec3<f32>, view_direction: vec3<f32>, frag_position: vec3<f32>, light: SpotLight,  material_specular: vec3<f32>, material_shininess: f32, in_color: vec3<f32>) -> vec3<f32>
@@ -149,7 +339,7 @@ fn calculate_spot_light(normal: vec3<f32>, view_direction: vec3<f32>, frag_posit
     return ambient + diffuse + specular;
 }
 
-fn calculate_point_light(normal: vec3<f32>, view_direction: vec3<f32>, frag_position: vec3<f32>, light: SpotLight,  material_specular: vec3<f32>, material_shininess: f32, in_color: vec3<f32>) -> vec3<f32> {
+fn calculate_point_light(normal: vec3<f32>, view_direction: vec3<f32>, frag_position: vec3<f32>, light: PointLight,  material_specular: vec3<f32>, material_shininess: f32, in_color: vec3<f32>) -> vec3<f32> {
     let light_direction = normalize(light.position.xyz - frag_position);
 
     // diffuse
@@ -167,26 +357,69 @@ fn calculate_point_light(normal: vec3<f32>, view_direction: vec3<f32>, frag_posi
     return ambient + diffuse + specular;
 }
 
-[[stage(fragment)]]
-fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+// lit color of a fragment, shared by the opaque and the transparent pass
+fn shade(in: VertexOutput) -> vec3<f32> {
     let normal = normalize(in.world_normal);
     let view_direction = normalize(u_globals.world_camera_position.xyz - in.world_position);
 
     var result: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
 
+    // only the first directional light casts shadows
+    let shadow = calculate_shadow(in.world_position, normal);
     for(var i: u32 = 0u; i < u_globals.nr_of_directional_lights; i = i + 1u) {
-        result = result + calculate_directional_light(normal, view_direction, directional_lights.lights[i], u_globals.material_specular.xyz, u_globals.material_shininess, in.color);
+        var light_shadow: f32 = 1.0;
+        if (i == 0u) {
+            light_shadow = shadow;
+        }
+        result = result + calculate_directional_light(normal, view_direction, directional_lights.lights[i], u_globals.material_specular.xyz, u_globals.material_shininess, in.color, light_shadow);
     }
 
-    for(var i: u32 = 0u; i < u_globals.nr_of_spot_lights; i = i + 1u) {
-        result = result + calculate_spot_light(normal, view_direction, in.world_position, spot_lights.lights[i], u_globals.material_specular.xyz, u_globals.material_shininess, in.color);
+    let cluster = find_cluster(in.proj_position, in.world_position);
+    let spot_lights_end = cluster.offset + cluster.nr_of_spot_lights;
+    for(var i: u32 = cluster.offset; i < spot_lights_end; i = i + 1u) {
+        let light = light_indices.indices[i];
+        result = result + calculate_spot_light(normal, view_direction, in.world_position, spot_lights.lights[light], u_globals.material_specular.xyz, u_globals.material_shininess, in.color);
     }
 
-    for(var i: u32 = 0u; i < u_globals.nr_of_point_lights; i = i + 1u) {
-        result = result + calculate_point_light(normal, view_direction, in.world_position, spot_lights.lights[i], u_globals.material_specular.xyz, u_globals.material_shininess, in.color);
+    let point_lights_end = spot_lights_end + cluster.nr_of_point_lights;
+    for(var i: u32 = spot_lights_end; i < point_lights_end; i = i + 1u) {
+        let light = light_indices.indices[i];
+        result = result + calculate_point_light(normal, view_direction, in.world_position, point_lights.lights[light], u_globals.material_specular.xyz, u_globals.material_shininess, in.color);
     }
 
-    let gamma: f32 = 2.2;
-    let color = vec4<f32>(pow(result, vec3<f32>(1.0 / gamma)), 1.0);
-    return color;
-}
\ No newline at end of file
+    // emissive voxels are not lit, the bloom of the post processing spreads their light
+    result = result + in.color * in.emission;
+
+    return result;
+}
+
+// linear hdr color, the post processing tonemaps it
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(shade(in), 1.0);
+}
+
+// blended over the opaque scene, the vertex alpha combines the voxel translucency and the entity opacity
+[[stage(fragment)]]
+fn fs_transparent(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(shade(in), in.alpha);
+}
+
+fn textured(in: VertexOutput) -> VertexOutput {
+    var out: VertexOutput = in;
+    let texel = textureSample(base_color_texture, base_color_sampler, in.uv);
+    out.color = in.color * texel.rgb;
+    out.alpha = in.alpha * texel.a;
+    return out;
+}
+
+[[stage(fragment)]]
+fn fs_textured(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    return vec4<f32>(shade(textured(in)), 1.0);
+}
+
+[[stage(fragment)]]
+fn fs_textured_transparent(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let textured_in = textured(in);
+    return vec4<f32>(shade(textured_in), textured_in.alpha);
+}
@@ -0,0 +1,62 @@
+// depth only pass from the directional light, rendered once per cascade
+[[block]]
+struct CascadeUniform {
+    view_proj: mat4x4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> cascade: CascadeUniform;
+
+struct Instance {
+    model: mat4x4<f32>;
+    inverse_model: mat4x4<f32>;
+    opacity: f32;
+    first_joint: u32;
+    p1: f32;
+    p2: f32;
+};
+
+[[block]]
+struct Instances {
+    models: array<Instance>;
+};
+
+[[group(0), binding(1)]]
+var<storage> models: [[access(read)]] Instances;
+
+[[stage(vertex)]]
+fn vs_main([[builtin(instance_index)]] instance_idx: u32, [[location(0)]] model_position: vec3<f32>) -> [[builtin(position)]] vec4<f32> {
+    return cascade.view_proj * models.models[instance_idx].model * vec4<f32>(model_position, 1.0);
+}
+
+struct Joint {
+    m: mat4x4<f32>;
+};
+
+[[block]]
+struct Joints {
+    joints: array<Joint>;
+};
+
+[[group(0), binding(2)]]
+var<storage> joints: [[access(read)]] Joints;
+
+// same as skin in shader.wgsl
+[[stage(vertex)]]
+fn vs_skinned([[builtin(instance_index)]] instance_idx: u32, [[location(0)]] model_position: vec3<f32>,
+              [[location(3)]] joint_indices: vec4<u32>,
+              [[location(4)]] weights: vec4<f32>) -> [[builtin(position)]] vec4<f32> {
+    let first_joint = models.models[instance_idx].first_joint;
+    let v = vec4<f32>(model_position, 1.0);
+    let position = (joints.joints[first_joint + joint_indices.x].m * v) * weights.x
+        + (joints.joints[first_joint + joint_indices.y].m * v) * weights.y
+        + (joints.joints[first_joint + joint_indices.z].m * v) * weights.z
+        + (joints.joints[first_joint + joint_indices.w].m * v) * weights.w;
+    return cascade.view_proj * models.models[instance_idx].model * position;
+}
+
+[[stage(vertex)]]
+fn vs_voxel([[builtin(instance_index)]] instance_idx: u32, [[location(0)]] position: vec4<u32>) -> [[builtin(position)]] vec4<f32> {
+    let model_position = vec3<f32>(f32(position.x), f32(position.y), f32(position.z));
+    return cascade.view_proj * models.models[instance_idx].model * vec4<f32>(model_position, 1.0);
+}
@@ -0,0 +1,66 @@
+// validate shader cargo run --features wgsl-in -- ../../xp-vox-engine/src/renderer/shaders/sky.wgsl
+[[block]]
+struct Sky {
+    inverse_view_proj: mat4x4<f32>;
+    camera_position: vec4<f32>;
+    sun_direction: vec4<f32>;
+    zenith_color: vec4<f32>;
+    horizon_color: vec4<f32>;
+    sun_color: vec4<f32>;
+    // cosines of the sun and moon sizes, star visibility and star density
+    parameters: vec4<f32>;
+};
+
+[[group(0), binding(0)]]
+var<uniform> sky: Sky;
+
+struct VertexOutput {
+    [[builtin(position)]] position: vec4<f32>;
+    [[location(0)]] ndc: vec2<f32>;
+};
+
+// one triangle that covers the screen on the far plane, the depth test keeps it behind the scene
+[[stage(vertex)]]
+fn vs_main([[builtin(vertex_index)]] vertex_index: u32) -> VertexOutput {
+    let x = f32((vertex_index << 1u) & 2u) * 2.0 - 1.0;
+    let y = 1.0 - f32(vertex_index & 2u) * 2.0;
+    var out: VertexOutput;
+    out.position = vec4<f32>(x, y, 1.0, 1.0);
+    out.ndc = vec2<f32>(x, y);
+    return out;
+}
+
+fn hash(cell: vec3<f32>) -> f32 {
+    return fract(sin(dot(cell, vec3<f32>(12.9898, 78.233, 37.719))) * 43758.5453);
+}
+
+[[stage(fragment)]]
+fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
+    let far = sky.inverse_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+    let direction = normalize(far.xyz / far.w - sky.camera_position.xyz);
+    let sun_direction = sky.sun_direction.xyz;
+
+    // gradient from the horizon to the zenith, below the horizon it stays at the horizon color
+    let up = clamp(direction.y, 0.0, 1.0);
+    let t = sqrt(up);
+    var color: vec3<f32> = mix(sky.horizon_color.rgb, sky.zenith_color.rgb, vec3<f32>(t, t, t));
+
+    // glow around the sun
+    let sun_angle = dot(direction, sun_direction);
+    let glow = pow(max(sun_angle, 0.0), 64.0) * 0.5;
+    color = color + sky.sun_color.rgb * glow * clamp(sun_direction.y + 0.2, 0.0, 1.0);
+
+    // discs are brighter than white so they bloom
+    let sun_disc = smoothStep(sky.parameters.x - 0.0005, sky.parameters.x, sun_angle);
+    color = color + sky.sun_color.rgb * 20.0 * sun_disc;
+    let moon_disc = smoothStep(sky.parameters.y - 0.0005, sky.parameters.y, -sun_angle);
+    color = color + vec3<f32>(0.8, 0.85, 1.0) * 2.0 * moon_disc * sky.parameters.z;
+
+    // stars are cells on a grid of directions above the horizon
+    let cell = floor(direction * 300.0);
+    let star = step(1.0 - sky.parameters.w, hash(cell)) * sky.parameters.z * step(0.0, direction.y);
+    let twinkle = 0.5 + 0.5 * hash(cell + vec3<f32>(1.0, 1.0, 1.0));
+    color = color + vec3<f32>(star, star, star) * twinkle * (1.0 - moon_disc);
+
+    return vec4<f32>(color, 1.0);
+}
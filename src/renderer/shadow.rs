@@ -0,0 +1,264 @@
+use glam::{Mat4, Vec3};
+
+pub const NR_OF_CASCADES: usize = 4;
+// casters this far behind a cascade, towards the light, still cast shadows into it
+const SHADOW_CASTER_DISTANCE: f32 = 50.0;
+
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    // width and height of every cascade in texels, the shadow map is recreated when it changes
+    pub resolution: u32,
+    // shadows are rendered up to this distance from the camera
+    pub max_distance: f32,
+    // blend between uniform (0.0) and logarithmic (1.0) cascade splits
+    pub split_lambda: f32,
+    pub depth_bias: f32,
+    // offset of the sampled position along the surface normal in shadow map texels
+    pub normal_bias: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            resolution: 2048,
+            max_distance: 100.0,
+            split_lambda: 0.75,
+            depth_bias: 0.0005,
+            normal_bias: 1.5,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cascade {
+    // distance from the camera where the cascade ends
+    pub far: f32,
+    pub view_proj: Mat4,
+    // size of a shadow map texel in meters
+    pub texel_size: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ShadowUniform {
+    pub view_proj: [Mat4; NR_OF_CASCADES],
+    pub far: [f32; NR_OF_CASCADES],
+    pub texel_size: [f32; NR_OF_CASCADES],
+    pub nr_of_cascades: u32,
+    pub depth_bias: f32,
+    pub normal_bias: f32,
+    pub pcf_texel: f32,
+}
+
+unsafe impl bytemuck::Pod for ShadowUniform {}
+unsafe impl bytemuck::Zeroable for ShadowUniform {}
+
+impl ShadowUniform {
+    // no cascades disables shadows in the shader
+    pub fn new(cascades: &[Cascade], settings: &ShadowSettings) -> Self {
+        assert!(cascades.len() <= NR_OF_CASCADES);
+        let mut uniform = Self {
+            view_proj: [Mat4::identity(); NR_OF_CASCADES],
+            far: [0.0; NR_OF_CASCADES],
+            texel_size: [0.0; NR_OF_CASCADES],
+            nr_of_cascades: cascades.len() as u32,
+            depth_bias: settings.depth_bias,
+            normal_bias: settings.normal_bias,
+            pcf_texel: 1.0 / settings.resolution as f32,
+        };
+        for (i, cascade) in cascades.iter().enumerate() {
+            uniform.view_proj[i] = cascade.view_proj;
+            uniform.far[i] = cascade.far;
+            uniform.texel_size[i] = cascade.texel_size;
+        }
+        uniform
+    }
+}
+
+// count + 1 distances from near to far, blending uniform and logarithmic splits
+pub fn cascade_splits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (0..=count)
+        .map(|i| {
+            let p = i as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+// world space corners of the part of the view frustum between the distances near and far from the camera
+pub fn frustum_corners(view: Mat4, projection: Mat4, near: f32, far: f32) -> [Vec3; 8] {
+    let inverse_projection = projection.inverse();
+    let inverse_view = view.inverse();
+    let mut corners = [Vec3::zero(); 8];
+    for (i, (x, y)) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter().enumerate() {
+        // direction of the ray through the corner, scaled to a depth of one
+        let ray = inverse_projection.transform_point3(Vec3::new(*x, *y, 1.0));
+        let ray = ray / -ray.z;
+        corners[i] = inverse_view.transform_point3(ray * near);
+        corners[i + 4] = inverse_view.transform_point3(ray * far);
+    }
+    corners
+}
+
+// orthographic light projection around the bounding sphere of the corners, returns the view projection and texel size
+// the sphere keeps the size constant when the camera rotates, snapping to texels keeps shadow edges from shimmering
+pub fn cascade_view_proj(corners: &[Vec3; 8], light_direction: Vec3, resolution: u32) -> (Mat4, f32) {
+    let center = corners.iter().fold(Vec3::zero(), |sum, corner| sum + *corner) / corners.len() as f32;
+    let radius = corners
+        .iter()
+        .map(|corner| (*corner - center).length())
+        .fold(0.0f32, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+    let texel_size = 2.0 * radius / resolution as f32;
+
+    let direction = light_direction.normalize();
+    let up = if direction.y.abs() > 0.99 {
+        Vec3::unit_z()
+    } else {
+        Vec3::unit_y()
+    };
+    let light_view = Mat4::look_at_rh(Vec3::zero(), direction, up);
+    let light_center = light_view.transform_point3(center);
+    let x = (light_center.x / texel_size).floor() * texel_size;
+    let y = (light_center.y / texel_size).floor() * texel_size;
+    let projection = Mat4::orthographic_rh(
+        x - radius,
+        x + radius,
+        y - radius,
+        y + radius,
+        -light_center.z - radius - SHADOW_CASTER_DISTANCE,
+        -light_center.z + radius,
+    );
+    (projection * light_view, texel_size)
+}
+
+pub fn cascades(view: Mat4, projection: Mat4, light_direction: Vec3, settings: &ShadowSettings) -> Vec<Cascade> {
+    let inverse_projection = projection.inverse();
+    let near = -inverse_projection.transform_point3(Vec3::new(0.0, 0.0, 0.0)).z;
+    let far = -inverse_projection.transform_point3(Vec3::new(0.0, 0.0, 1.0)).z;
+    let far = far.min(settings.max_distance);
+    cascade_splits(near, far, NR_OF_CASCADES, settings.split_lambda)
+        .windows(2)
+        .map(|split| {
+            let corners = frustum_corners(view, projection, split[0], split[1]);
+            let (view_proj, texel_size) = cascade_view_proj(&corners, light_direction, settings.resolution);
+            Cascade {
+                far: split[1],
+                view_proj,
+                texel_size,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        mesh::Aabb,
+        renderer::{
+            shadow::{cascade_splits, cascades, frustum_corners, ShadowSettings, NR_OF_CASCADES},
+            Frustum,
+        },
+    };
+    use glam::{Mat4, Vec3};
+
+    fn camera() -> (Mat4, Mat4) {
+        (
+            Mat4::look_at_rh(Vec3::new(3.0, 4.0, 5.0), Vec3::new(0.0, 0.0, -10.0), Vec3::unit_y()),
+            Mat4::perspective_rh(std::f32::consts::FRAC_PI_4, 16.0 / 9.0, 0.1, 1000.0),
+        )
+    }
+
+    #[test]
+    fn splits_range_from_near_to_far() {
+        let splits = cascade_splits(0.1, 100.0, 4, 0.75);
+        assert_eq!(5, splits.len());
+        assert!((splits[0] - 0.1).abs() < 1e-5);
+        assert!((splits[4] - 100.0).abs() < 1e-3);
+        assert!(splits.windows(2).all(|split| split[0] < split[1]));
+
+        let uniform = cascade_splits(1.0, 101.0, 4, 0.0);
+        for (split, expected) in uniform.iter().zip(&[1.0, 26.0, 51.0, 76.0, 101.0]) {
+            assert!((split - expected).abs() < 1e-4);
+        }
+        let logarithmic = cascade_splits(1.0, 10000.0, 4, 1.0);
+        for (split, expected) in logarithmic.iter().zip(&[1.0, 10.0, 100.0, 1000.0, 10000.0]) {
+            assert!((split / expected - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn frustum_corners_are_at_split_distances() {
+        let (view, projection) = camera();
+        let eye = view.inverse().transform_point3(Vec3::zero());
+        let forward = (Vec3::new(0.0, 0.0, -10.0) - Vec3::new(3.0, 4.0, 5.0)).normalize();
+        let corners = frustum_corners(view, projection, 2.0, 8.0);
+        for corner in &corners[..4] {
+            assert!(((*corner - eye).dot(forward) - 2.0).abs() < 1e-3);
+        }
+        for corner in &corners[4..] {
+            assert!(((*corner - eye).dot(forward) - 8.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn cascades_contain_their_part_of_the_frustum() {
+        let (view, projection) = camera();
+        let settings = ShadowSettings::default();
+        let light_direction = Vec3::new(-0.3, -1.0, 0.2);
+        let cascades = cascades(view, projection, light_direction, &settings);
+        assert_eq!(NR_OF_CASCADES, cascades.len());
+        assert!((cascades[NR_OF_CASCADES - 1].far - settings.max_distance).abs() < 1e-3);
+        let mut near = 0.1;
+        for cascade in &cascades {
+            for corner in frustum_corners(view, projection, near, cascade.far).iter() {
+                let p = cascade.view_proj.transform_point3(*corner);
+                assert!(p.x.abs() <= 1.0 && p.y.abs() <= 1.0, "{:?}", p);
+                assert!(p.z >= 0.0 && p.z <= 1.0, "{:?}", p);
+            }
+            // a caster between the cascade and the light is inside the depth range
+            let caster = cascade.view_proj.transform_point3(
+                frustum_corners(view, projection, near, cascade.far)[0] - light_direction.normalize() * 10.0,
+            );
+            assert!(caster.z >= 0.0);
+            near = cascade.far;
+        }
+        assert!(cascades.windows(2).all(|c| c[0].texel_size <= c[1].texel_size));
+    }
+
+    #[test]
+    fn cascade_frusta_keep_casters_towards_the_light() {
+        let (view, projection) = camera();
+        let light_direction = Vec3::new(-0.3, -1.0, 0.2);
+        let cascade = cascades(view, projection, light_direction, &ShadowSettings::default())[0];
+        let frustum = Frustum::from_view_proj(&cascade.view_proj);
+        let receiver = frustum_corners(view, projection, 0.1, cascade.far)[4];
+        let caster = |p: Vec3| Aabb::new(p - Vec3::one(), p + Vec3::one());
+        assert!(frustum.intersects_aabb(&caster(receiver - light_direction.normalize() * 20.0)));
+        assert!(!frustum.intersects_aabb(&caster(receiver + Vec3::new(500.0, 0.0, 0.0))));
+        assert!(!frustum.intersects_aabb(&caster(receiver - light_direction.normalize() * 500.0)));
+    }
+
+    #[test]
+    fn cascades_are_snapped_to_texels() {
+        let (view, projection) = camera();
+        let settings = ShadowSettings::default();
+        let light_direction = Vec3::new(-0.3, -1.0, 0.2);
+        let moved = Mat4::from_translation(Vec3::new(-0.013, 0.0, 0.007)) * view;
+        for (cascade, moved) in cascades(view, projection, light_direction, &settings)
+            .iter()
+            .zip(cascades(moved, projection, light_direction, &settings).iter())
+        {
+            // the world origin lands on a texel corner, before and after moving the camera
+            for view_proj in &[cascade.view_proj, moved.view_proj] {
+                let p = view_proj.transform_point3(Vec3::zero());
+                let texels = (p.x + 1.0) * settings.resolution as f32 / 2.0;
+                assert!((texels - texels.round()).abs() < 0.05, "{}", texels);
+            }
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{
+    mesh::{SkinnedVertex, Vertex, VoxelVertex},
+    renderer::{shadow_map::ShadowMap, Frame, RenderPass, Renderer, NR_OF_CASCADES, SHADOW_MAP},
+};
+use std::borrow::Cow;
+
+pub struct ShadowPipeline {
+    render_pipeline: wgpu::RenderPipeline,
+    voxel_render_pipeline: wgpu::RenderPipeline,
+    skinned_render_pipeline: wgpu::RenderPipeline,
+    cascade_uniforms: Vec<wgpu::Buffer>,
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl ShadowPipeline {
+    pub fn new(renderer: &Renderer, instances: &wgpu::Buffer, joints: &wgpu::Buffer) -> Self {
+        let shader = renderer.device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/shadow_shader.wgsl"))),
+            flags: wgpu::ShaderFlags::EXPERIMENTAL_TRANSLATION,
+        });
+        let bind_group_layout = renderer
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStage::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStage::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStage::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            min_binding_size: None,
+                            has_dynamic_offset: false,
+                        },
+                        count: None,
+                    },
+                ],
+                label: None,
+            });
+        // every cascade has its own matrix so all cascades can be recorded in one encoder
+        let cascade_uniforms = (0..NR_OF_CASCADES)
+            .map(|_| {
+                renderer.device.create_buffer(&wgpu::BufferDescriptor {
+                    label: None,
+                    usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+                    size: std::mem::size_of::<glam::Mat4>() as u64,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect::<Vec<_>>();
+        let bind_groups = cascade_uniforms
+            .iter()
+            .map(|cascade_uniform| {
+                renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: cascade_uniform.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: instances.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: joints.as_entire_binding(),
+                        },
+                    ],
+                })
+            })
+            .collect();
+        let render_pipeline_layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline =
+            create_shadow_pipeline(renderer, &render_pipeline_layout, &shader, "vs_main", &[Vertex::desc()]);
+        let voxel_render_pipeline = create_shadow_pipeline(
+            renderer,
+            &render_pipeline_layout,
+            &shader,
+            "vs_voxel",
+            &[VoxelVertex::desc()],
+        );
+        let skinned_render_pipeline = create_shadow_pipeline(
+            renderer,
+            &render_pipeline_layout,
+            &shader,
+            "vs_skinned",
+            &[SkinnedVertex::desc()],
+        );
+        Self {
+            render_pipeline,
+            voxel_render_pipeline,
+            skinned_render_pipeline,
+            cascade_uniforms,
+            bind_groups,
+        }
+    }
+}
+
+// draws the casters that the render graph culled for every cascade, no cascades means shadows are disabled
+impl RenderPass for ShadowPipeline {
+    fn name(&self) -> &'static str {
+        "shadow"
+    }
+
+    fn inputs(&self) -> &[&'static str] {
+        &[]
+    }
+
+    fn outputs(&self) -> &[&'static str] {
+        &[SHADOW_MAP]
+    }
+
+    fn prepare(&mut self, frame: &mut Frame) {
+        for (cascade, cascade_uniform) in frame.cascades.iter().zip(&self.cascade_uniforms) {
+            frame.renderer.queue.write_buffer(
+                cascade_uniform,
+                0,
+                bytemuck::cast_slice(&cascade.view_proj.to_cols_array()),
+            );
+        }
+    }
+
+    fn record(&self, frame: &Frame, encoder: &mut wgpu::CommandEncoder) {
+        for (i, casters) in frame.casters.iter().enumerate() {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &frame.bind_group.shadow_map.cascade_views[i],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_bind_group(0, &self.bind_groups[i], &[]);
+            for draw in &casters.draws {
+                let mesh = frame.scene.meshes.get(&draw.mesh).unwrap();
+                if mesh.voxel_size.is_some() {
+                    render_pass.set_pipeline(&self.voxel_render_pipeline);
+                } else if mesh.skinned {
+                    render_pass.set_pipeline(&self.skinned_render_pipeline);
+                } else {
+                    render_pass.set_pipeline(&self.render_pipeline);
+                }
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+                render_pass.draw_indexed(draw.indices.clone(), 0, draw.instances.clone());
+            }
+            render_pass.set_pipeline(&self.voxel_render_pipeline);
+            frame
+                .bind_group
+                .chunks
+                .draw(&mut render_pass, &casters.chunk_draws, casters.first_chunk_draw);
+        }
+    }
+}
+
+fn create_shadow_pipeline(
+    renderer: &Renderer,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    vertex_entry_point: &str,
+    buffers: &[wgpu::VertexBufferLayout],
+) -> wgpu::RenderPipeline {
+    renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: vertex_entry_point,
+            buffers,
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // both sides so open meshes like planes cast shadows too
+            cull_mode: None,
+            clamp_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: ShadowMap::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState {
+                front: wgpu::StencilFaceState::IGNORE,
+                back: wgpu::StencilFaceState::IGNORE,
+                read_mask: 0,
+                write_mask: 0,
+            },
+            bias: wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: None,
+    })
+}
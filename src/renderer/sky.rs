@@ -0,0 +1,215 @@
+use crate::{entity::Entities, renderer::Light};
+use glam::Vec3;
+
+const HOURS_PER_DAY: f32 = 24.0;
+
+const DAY_ZENITH: Vec3 = glam::const_vec3!([0.25, 0.45, 0.85]);
+const DAY_HORIZON: Vec3 = glam::const_vec3!([0.65, 0.75, 0.9]);
+const TWILIGHT_ZENITH: Vec3 = glam::const_vec3!([0.2, 0.25, 0.5]);
+const TWILIGHT_HORIZON: Vec3 = glam::const_vec3!([0.9, 0.5, 0.25]);
+const NIGHT_ZENITH: Vec3 = glam::const_vec3!([0.005, 0.01, 0.03]);
+const NIGHT_HORIZON: Vec3 = glam::const_vec3!([0.02, 0.03, 0.06]);
+const SUNRISE_COLOR: Vec3 = glam::const_vec3!([1.0, 0.5, 0.2]);
+const NOON_COLOR: Vec3 = glam::const_vec3!([1.0, 0.95, 0.9]);
+const MOON_COLOR: Vec3 = glam::const_vec3!([0.05, 0.07, 0.1]);
+
+#[derive(Debug, Copy, Clone)]
+pub struct SkySettings {
+    // without the sky the background is cleared to the horizon color
+    pub enabled: bool,
+    // angular radius of the discs in radians
+    pub sun_size: f32,
+    pub moon_size: f32,
+    // fraction of the sky that is covered by stars
+    pub star_density: f32,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sun_size: 0.03,
+            moon_size: 0.02,
+            star_density: 0.002,
+        }
+    }
+}
+
+// sky and directional light at a time of day
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Sky {
+    // points towards the sun, the moon is on the opposite side
+    pub sun_direction: [f32; 3],
+    pub zenith_color: [f32; 3],
+    pub horizon_color: [f32; 3],
+    pub sun_color: [f32; 3],
+    // direction the directional light shines in, it follows the sun by day and the moon by night
+    pub light_direction: [f32; 3],
+    pub light_color: [f32; 3],
+    pub ambient_color: [f32; 3],
+    // 0.0 by day and 1.0 by night
+    pub stars: f32,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        TimeOfDay::default().sky()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TimeOfDay {
+    // from 0.0 to 24.0, the sun rises at 6 in the east and sets at 18 in the west
+    pub hours: f32,
+    // seconds a whole day takes
+    pub day_length: f32,
+    pub paused: bool,
+    // angle between the path of the sun and the vertical plane through east and west
+    pub tilt: f32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            hours: 10.0,
+            day_length: 600.0,
+            paused: false,
+            tilt: 0.4,
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn advance(&mut self, seconds: f32) {
+        if !self.paused {
+            self.hours = (self.hours + seconds / self.day_length * HOURS_PER_DAY).rem_euclid(HOURS_PER_DAY);
+        }
+    }
+
+    pub fn sun_direction(&self) -> Vec3 {
+        let angle = (self.hours - 6.0) / HOURS_PER_DAY * std::f32::consts::TAU;
+        Vec3::new(
+            angle.cos(),
+            angle.sin() * self.tilt.cos(),
+            angle.sin() * self.tilt.sin(),
+        )
+    }
+
+    pub fn sky(&self) -> Sky {
+        let sun_direction = self.sun_direction();
+        let elevation = sun_direction.y;
+        let day = smoothstep(-0.2, 0.2, elevation);
+        // the horizon glows while the sun is close to it
+        let twilight = (1.0 - elevation.abs() / 0.2).max(0.0);
+        let zenith = NIGHT_ZENITH.lerp(DAY_ZENITH, day).lerp(TWILIGHT_ZENITH, twilight * 0.5);
+        let horizon = NIGHT_HORIZON
+            .lerp(DAY_HORIZON, day)
+            .lerp(TWILIGHT_HORIZON, twilight * 0.7);
+        let sun_color = SUNRISE_COLOR.lerp(NOON_COLOR, smoothstep(0.0, 0.4, elevation));
+        // both lights fade out at the horizon, so the direction flips unnoticed
+        let (light_direction, light_color) = if elevation >= 0.0 {
+            (-sun_direction, sun_color * 0.6 * smoothstep(0.0, 0.1, elevation))
+        } else {
+            (sun_direction, MOON_COLOR * smoothstep(0.05, 0.2, -elevation))
+        };
+        let ambient = (zenith + horizon) * 0.1 + Vec3::splat(0.01);
+        Sky {
+            sun_direction: sun_direction.into(),
+            zenith_color: zenith.into(),
+            horizon_color: horizon.into(),
+            sun_color: sun_color.into(),
+            light_direction: light_direction.into(),
+            light_color: light_color.into(),
+            ambient_color: ambient.into(),
+            stars: 1.0 - smoothstep(-0.25, 0.0, elevation),
+        }
+    }
+}
+
+// the first directional light shines like the sky, its direction is in world space
+pub fn apply_sky(sky: &Sky, entities: &mut Entities) {
+    let properties = entities.query_mut::<Light>().find_map(|(_, light)| match light {
+        Light::Directional(properties) => Some(properties),
+        _ => None,
+    });
+    if let Some(properties) = properties {
+        let [x, y, z] = sky.light_direction;
+        let [r, g, b] = sky.light_color;
+        let [ar, ag, ab] = sky.ambient_color;
+        properties.direction = [x, y, z, 0.0];
+        properties.diffuse = [r, g, b, 1.0];
+        properties.specular = [r * 0.25, g * 0.25, b * 0.25, 1.0];
+        properties.ambient = [ar, ag, ab, 1.0];
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).max(0.0).min(1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        entity::Entities,
+        renderer::{apply_sky, DirectionalProperties, Light, TimeOfDay},
+    };
+
+    fn at(hours: f32) -> TimeOfDay {
+        TimeOfDay {
+            hours,
+            ..TimeOfDay::default()
+        }
+    }
+
+    #[test]
+    fn sun_rises_in_the_east_and_sets_in_the_west() {
+        assert!(at(6.0).sun_direction().y.abs() < 1e-5);
+        assert!(at(6.0).sun_direction().x > 0.99);
+        assert!(at(18.0).sun_direction().x < -0.99);
+        assert!(at(12.0).sun_direction().y > 0.9);
+        assert!(at(0.0).sun_direction().y < -0.9);
+    }
+
+    #[test]
+    fn time_wraps_around_at_midnight() {
+        let mut time = at(23.0);
+        time.advance(time.day_length / 12.0);
+        assert!((time.hours - 1.0).abs() < 1e-4);
+        time.paused = true;
+        time.advance(100.0);
+        assert!((time.hours - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn nights_are_dark_and_starry() {
+        let (noon, midnight) = (at(12.0).sky(), at(0.0).sky());
+        let brightness = |c: [f32; 3]| c[0] + c[1] + c[2];
+        assert!(brightness(midnight.horizon_color) < 0.2 * brightness(noon.horizon_color));
+        assert!(brightness(midnight.light_color) < 0.2 * brightness(noon.light_color));
+        assert_eq!(0.0, noon.stars);
+        assert_eq!(1.0, midnight.stars);
+        // the light shines down from the sun by day and from the moon by night
+        assert!(noon.light_direction[1] < 0.0);
+        assert!(midnight.light_direction[1] < 0.0);
+    }
+
+    #[test]
+    fn directional_light_follows_the_sky() {
+        let mut entities = Entities::new();
+        let light = entities
+            .spawn()
+            .with(Light::Directional(DirectionalProperties::new([0.0, -1.0, 0.0, 0.0])))
+            .id();
+        let sky = at(8.0).sky();
+        apply_sky(&sky, &mut entities);
+        match entities.get::<Light>(&light).unwrap() {
+            Light::Directional(properties) => {
+                assert_eq!(sky.light_direction[0], properties.direction[0]);
+                assert_eq!(sky.light_color[1], properties.diffuse[1]);
+                assert_eq!(sky.ambient_color[2], properties.ambient[2]);
+            }
+            _ => panic!("not a directional light"),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use crate::renderer::{
+    depth_texture::DepthTexture, error::RendererError, hdr_texture::HdrTexture, Frame, RenderPass, Renderer, COLOR,
+    DEPTH,
+};
+use glam::Mat4;
+use std::borrow::Cow;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+struct SkyUniform {
+    inverse_view_proj: Mat4,
+    camera_position: [f32; 4],
+    sun_direction: [f32; 4],
+    zenith_color: [f32; 4],
+    horizon_color: [f32; 4],
+    sun_color: [f32; 4],
+    parameters: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for SkyUniform {}
+unsafe impl bytemuck::Zeroable for SkyUniform {}
+
+// fills the background that the scene passes left at the far plane
+pub struct SkyPipeline {
+    render_pipeline: wgpu::RenderPipeline,
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    enabled: bool,
+}
+
+impl SkyPipeline {
+    pub async fn new(renderer: &Renderer) -> Result<Self, RendererError> {
+        let uniform = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+            size: std::mem::size_of::<SkyUniform>() as u64,
+            mapped_at_creation: false,
+        });
+        let bind_group_layout = renderer
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        min_binding_size: None,
+                        has_dynamic_offset: false,
+                    },
+                    count: None,
+                }],
+                label: None,
+            });
+        let bind_group = renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            }],
+        });
+        let shader = renderer.device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/sky.wgsl"))),
+            flags: wgpu::ShaderFlags::EXPERIMENTAL_TRANSLATION,
+        });
+        let render_pipeline_layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                clamp_depth: false,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+            },
+            // only where the depth is still cleared to the far plane
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DepthTexture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState {
+                    front: wgpu::StencilFaceState::IGNORE,
+                    back: wgpu::StencilFaceState::IGNORE,
+                    read_mask: 0,
+                    write_mask: 0,
+                },
+                bias: wgpu::DepthBiasState {
+                    constant: 0,
+                    slope_scale: 0.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[HdrTexture::HDR_FORMAT.into()],
+            }),
+        });
+        Ok(Self {
+            render_pipeline,
+            uniform,
+            bind_group,
+            enabled: true,
+        })
+    }
+}
+
+impl RenderPass for SkyPipeline {
+    fn name(&self) -> &'static str {
+        "sky"
+    }
+
+    fn inputs(&self) -> &[&'static str] {
+        &[COLOR, DEPTH]
+    }
+
+    fn outputs(&self) -> &[&'static str] {
+        &[COLOR]
+    }
+
+    fn prepare(&mut self, frame: &mut Frame) {
+        let settings = frame.renderer.settings.sky;
+        let sky = frame.scene.sky;
+        let camera = frame.scene.camera;
+        let position = camera.get_position();
+        let vec4 = |v: [f32; 3], w: f32| [v[0], v[1], v[2], w];
+        let uniform = SkyUniform {
+            inverse_view_proj: (camera.get_projection() * camera.get_view()).inverse(),
+            camera_position: [position.x, position.y, position.z, 1.0],
+            sun_direction: vec4(sky.sun_direction, 0.0),
+            zenith_color: vec4(sky.zenith_color, 1.0),
+            horizon_color: vec4(sky.horizon_color, 1.0),
+            sun_color: vec4(sky.sun_color, 1.0),
+            parameters: [
+                settings.sun_size.cos(),
+                settings.moon_size.cos(),
+                sky.stars,
+                settings.star_density,
+            ],
+        };
+        frame
+            .renderer
+            .queue
+            .write_buffer(&self.uniform, 0, bytemuck::cast_slice(&[uniform]));
+        self.enabled = settings.enabled;
+    }
+
+    fn record(&self, frame: &Frame, encoder: &mut wgpu::CommandEncoder) {
+        if !self.enabled {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &frame.renderer.hdr_texture.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &frame.renderer.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::renderer::Renderer;
+
+// storage buffer that grows to the next power of two when written data does not fit
+pub struct StorageBuffer {
+    pub buffer: wgpu::Buffer,
+    capacity: u64,
+    usage: wgpu::BufferUsage,
+}
+
+impl StorageBuffer {
+    // the capacity has to fit at least one element, shaders require that of a binding
+    pub fn new(device: &wgpu::Device, capacity: u64) -> Self {
+        Self::with_usage(
+            device,
+            capacity,
+            wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::COPY_DST,
+        )
+    }
+
+    // holds the arguments of indirect draws
+    pub fn new_indirect(device: &wgpu::Device, capacity: u64) -> Self {
+        Self::with_usage(
+            device,
+            capacity,
+            wgpu::BufferUsage::STORAGE | wgpu::BufferUsage::INDIRECT | wgpu::BufferUsage::COPY_DST,
+        )
+    }
+
+    fn with_usage(device: &wgpu::Device, capacity: u64, usage: wgpu::BufferUsage) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            usage,
+            size: capacity,
+            mapped_at_creation: false,
+        });
+        Self {
+            buffer,
+            capacity,
+            usage,
+        }
+    }
+
+    // returns true when the buffer is recreated, bind groups that use it have to be recreated as well
+    pub fn write(&mut self, renderer: &Renderer, data: &[u8]) -> bool {
+        let size = data.len() as u64;
+        let recreated = size > self.capacity;
+        if recreated {
+            *self = Self::with_usage(&renderer.device, size.next_power_of_two(), self.usage);
+        }
+        renderer.queue.write_buffer(&self.buffer, 0, data);
+        recreated
+    }
+}
@@ -0,0 +1,146 @@
+use crate::{
+    mesh::{SkinnedVertex, Vertex, VoxelVertex},
+    renderer::{
+        chunk_buffers::draw_indexed, culling::TransparentDraw, error::RendererError, pipeline::create_render_pipeline,
+        BindGroup, Frame, Mesh, RenderPass, Renderer, COLOR, DEPTH,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+// entities with an opacity below 1.0 are drawn by the transparent pass
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Opacity(pub f32);
+
+// blends the transparent draws that the render graph sorted back to front
+pub struct TransparentPipeline {
+    render_pipeline: wgpu::RenderPipeline,
+    textured_render_pipeline: wgpu::RenderPipeline,
+    voxel_render_pipeline: wgpu::RenderPipeline,
+    skinned_render_pipeline: wgpu::RenderPipeline,
+}
+
+impl TransparentPipeline {
+    pub async fn new(renderer: &Renderer, bind_group: &BindGroup) -> Result<Self, RendererError> {
+        let shader = renderer.device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shaders/shader.wgsl"))),
+            flags: wgpu::ShaderFlags::EXPERIMENTAL_TRANSLATION,
+        });
+        let render_pipeline_layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group.bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let textured_render_pipeline_layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group.bind_group_layout, &renderer.material_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = create_render_pipeline(
+            renderer,
+            &render_pipeline_layout,
+            &shader,
+            ("vs_main", "fs_transparent"),
+            &[Vertex::desc()],
+            true,
+        );
+        let textured_render_pipeline = create_render_pipeline(
+            renderer,
+            &textured_render_pipeline_layout,
+            &shader,
+            ("vs_textured", "fs_textured_transparent"),
+            &[Vertex::desc(), Mesh::uv_desc()],
+            true,
+        );
+        let voxel_render_pipeline = create_render_pipeline(
+            renderer,
+            &render_pipeline_layout,
+            &shader,
+            ("vs_voxel", "fs_transparent"),
+            &[VoxelVertex::desc()],
+            true,
+        );
+        let skinned_render_pipeline = create_render_pipeline(
+            renderer,
+            &render_pipeline_layout,
+            &shader,
+            ("vs_skinned", "fs_transparent"),
+            &[SkinnedVertex::desc()],
+            true,
+        );
+        Ok(Self {
+            render_pipeline,
+            textured_render_pipeline,
+            voxel_render_pipeline,
+            skinned_render_pipeline,
+        })
+    }
+}
+
+impl RenderPass for TransparentPipeline {
+    fn name(&self) -> &'static str {
+        "transparent"
+    }
+
+    fn inputs(&self) -> &[&'static str] {
+        &[COLOR, DEPTH]
+    }
+
+    fn outputs(&self) -> &[&'static str] {
+        &[COLOR]
+    }
+
+    fn record(&self, frame: &Frame, encoder: &mut wgpu::CommandEncoder) {
+        if frame.transparent_draws.is_empty() {
+            return;
+        }
+        let meshes = frame.scene.meshes;
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[wgpu::RenderPassColorAttachment {
+                view: &frame.renderer.hdr_texture.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &frame.renderer.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_bind_group(0, &frame.bind_group.bind_group, &[]);
+        for draw in &frame.transparent_draws {
+            match draw {
+                TransparentDraw::Mesh(draw) => {
+                    let mesh = meshes.get(&draw.mesh).unwrap();
+                    match (&mesh.material, &mesh.uv_buffer) {
+                        _ if mesh.voxel_size.is_some() => render_pass.set_pipeline(&self.voxel_render_pipeline),
+                        _ if mesh.skinned => render_pass.set_pipeline(&self.skinned_render_pipeline),
+                        (Some(material), Some(uv_buffer)) => {
+                            render_pass.set_pipeline(&self.textured_render_pipeline);
+                            render_pass.set_bind_group(1, material, &[]);
+                            render_pass.set_vertex_buffer(1, uv_buffer.slice(..));
+                        }
+                        _ => render_pass.set_pipeline(&self.render_pipeline),
+                    }
+                    render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    render_pass.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+                    render_pass.draw_indexed(draw.indices.clone(), 0, draw.instances.clone());
+                }
+                TransparentDraw::Chunk(draw, index_format) => {
+                    render_pass.set_pipeline(&self.voxel_render_pipeline);
+                    frame.bind_group.chunks.bind(&mut render_pass, *index_format);
+                    draw_indexed(&mut render_pass, draw);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{
+    entity::{Entities, Entity, Parent},
+    physics::CollisionShape,
+    registry::Handle,
+    renderer::{Light, Opacity, MAX_NR_OF_DIRECTIONAL_LIGHTS},
+    scene::SceneError,
+    transform::Transform,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// where the mesh of an entity comes from, meshes are loaded into the assets by load_meshes
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum MeshSource {
+    Cube { size: f32 },
+    IcoSphere { radius: f32 },
+    Plane { size: f32 },
+    Gltf { path: String, node: String },
+    Vox { path: String },
+    // every model in the .vox file is a frame
+    VoxAnimation { path: String, frame_rate: f32 },
+}
+
+// the follow camera follows the character of the scene
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SceneCamera {
+    Static { position: [f32; 3], target: [f32; 3] },
+    Follow { follow_angle: f32, follow_distance: f32 },
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneEntity {
+    pub transform: Option<Transform>,
+    pub mesh: Option<MeshSource>,
+    pub collision_shape: Option<CollisionShape>,
+    pub light: Option<Light>,
+    pub opacity: Option<Opacity>,
+    // index of the parent in the entities of the scene
+    pub parent: Option<usize>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+    pub camera: Option<SceneCamera>,
+    // index of the entity controlled by the character controller
+    pub character: Option<usize>,
+    pub entities: Vec<SceneEntity>,
+}
+
+impl Scene {
+    // returns the spawned entities in the order of the scene
+    pub fn instantiate(&self, entities: &mut Entities) -> Result<Vec<Handle<Entity>>, SceneError> {
+        self.validate()?;
+        let handles = self
+            .entities
+            .iter()
+            .map(|scene_entity| {
+                let entity = entities.spawn().id();
+                if let Some(transform) = &scene_entity.transform {
+                    entities.insert(&entity, transform.clone());
+                }
+                if let Some(mesh) = &scene_entity.mesh {
+                    entities.insert(&entity, mesh.clone());
+                }
+                if let Some(collision_shape) = &scene_entity.collision_shape {
+                    entities.insert(&entity, collision_shape.clone());
+                }
+                if let Some(light) = &scene_entity.light {
+                    entities.insert(&entity, *light);
+                }
+                if let Some(opacity) = &scene_entity.opacity {
+                    entities.insert(&entity, *opacity);
+                }
+                entity
+            })
+            .collect::<Vec<_>>();
+        for (index, scene_entity) in self.entities.iter().enumerate() {
+            if let Some(parent) = scene_entity.parent {
+                entities.set_parent(&handles[index], &handles[parent]);
+            }
+        }
+        Ok(handles)
+    }
+
+    // entities without any of the scene components are skipped
+    pub fn from_entities(entities: &Entities, character: Option<&Handle<Entity>>, camera: Option<SceneCamera>) -> Self {
+        let handles = entities
+            .iter()
+            .filter(|entity| {
+                entities.has::<Transform>(entity)
+                    || entities.has::<MeshSource>(entity)
+                    || entities.has::<CollisionShape>(entity)
+                    || entities.has::<Light>(entity)
+                    || entities.has::<Opacity>(entity)
+            })
+            .collect::<Vec<_>>();
+        let indices = handles
+            .iter()
+            .enumerate()
+            .map(|(index, entity)| (*entity, index))
+            .collect::<HashMap<_, _>>();
+        let scene_entities = handles
+            .iter()
+            .map(|entity| SceneEntity {
+                transform: entities.get::<Transform>(entity).cloned(),
+                mesh: entities.get::<MeshSource>(entity).cloned(),
+                collision_shape: entities.get::<CollisionShape>(entity).cloned(),
+                light: entities.get::<Light>(entity).copied(),
+                opacity: entities.get::<Opacity>(entity).copied(),
+                parent: entities
+                    .get::<Parent>(entity)
+                    .and_then(|parent| indices.get(&parent.0).copied()),
+            })
+            .collect();
+        Self {
+            camera,
+            character: character.and_then(|character| indices.get(character).copied()),
+            entities: scene_entities,
+        }
+    }
+
+    fn validate(&self) -> Result<(), SceneError> {
+        if let Some(character) = self.character {
+            if character >= self.entities.len() {
+                return Err(SceneError::InvalidIndex(character));
+            }
+        }
+        let nr_of_directional_lights = self
+            .entities
+            .iter()
+            .filter(|scene_entity| matches!(scene_entity.light, Some(Light::Directional(_))))
+            .count();
+        if nr_of_directional_lights > MAX_NR_OF_DIRECTIONAL_LIGHTS {
+            return Err(SceneError::TooManyDirectionalLights(nr_of_directional_lights));
+        }
+        for (index, scene_entity) in self.entities.iter().enumerate() {
+            // walking up from every entity must reach a root within the number of entities
+            let mut current = scene_entity.parent;
+            let mut depth = 0;
+            while let Some(parent) = current {
+                if parent >= self.entities.len() || depth == self.entities.len() {
+                    return Err(SceneError::InvalidIndex(index));
+                }
+                current = self.entities[parent].parent;
+                depth += 1;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    animation::Flipbook,
+    entity::Entities,
+    gltf::{load_gltf_file, GltfScene, MeshLoadError},
+    mesh::{Cube, IcoSphere, MeshData, Plane, VoxelMeshData},
+    registry::{AssetHandle, Assets},
+    renderer::{Mesh, Renderer},
+    scene::{MeshSource, Scene},
+    world::{mesh_data_from_vox, mesh_data_from_vox_frames, VOXEL_SIZE_IN_METERS},
+};
+use ron::ser::PrettyConfig;
+
+#[derive(Debug)]
+pub enum SceneError {
+    IOError(std::io::Error),
+    Ron(ron::Error),
+    MeshLoad(MeshLoadError),
+    Vox(&'static str),
+    MissingNode(String),
+    InvalidIndex(usize),
+    // the renderer only has room for MAX_NR_OF_DIRECTIONAL_LIGHTS
+    TooManyDirectionalLights(usize),
+}
+
+impl From<std::io::Error> for SceneError {
+    fn from(e: std::io::Error) -> SceneError {
+        SceneError::IOError(e)
+    }
+}
+
+impl From<ron::Error> for SceneError {
+    fn from(e: ron::Error) -> SceneError {
+        SceneError::Ron(e)
+    }
+}
+
+impl From<MeshLoadError> for SceneError {
+    fn from(e: MeshLoadError) -> SceneError {
+        SceneError::MeshLoad(e)
+    }
+}
+
+pub fn load_scene(path: &str) -> Result<Scene, SceneError> {
+    Ok(ron::de::from_bytes(std::fs::read(path)?.as_slice())?)
+}
+
+pub fn save_scene(path: &str, scene: &Scene) -> Result<(), SceneError> {
+    std::fs::write(path, ron::ser::to_string_pretty(scene, PrettyConfig::new())?)?;
+    Ok(())
+}
+
+// .vox models stay voxel meshes, so the emission and translucency of their materials reach the voxel pipelines
+pub enum SourceMeshData {
+    Mesh(MeshData),
+    Voxel(VoxelMeshData),
+}
+
+impl SourceMeshData {
+    pub fn into_mesh(self, renderer: &Renderer) -> Mesh {
+        match self {
+            SourceMeshData::Mesh(mesh_data) => Mesh::from_mesh_data(renderer, mesh_data),
+            SourceMeshData::Voxel(mesh_data) => Mesh::from_voxel_mesh_data(renderer, &mesh_data, VOXEL_SIZE_IN_METERS),
+        }
+    }
+}
+
+impl MeshSource {
+    // the first frame for animated sources
+    pub fn mesh_data(&self) -> Result<SourceMeshData, SceneError> {
+        match self {
+            MeshSource::Cube { size } => Ok(SourceMeshData::Mesh(MeshData::from(Cube::new(*size)))),
+            MeshSource::IcoSphere { radius } => Ok(SourceMeshData::Mesh(MeshData::from(IcoSphere::new(*radius)))),
+            MeshSource::Plane { size } => Ok(SourceMeshData::Mesh(MeshData::from(Plane::flat(*size)))),
+            MeshSource::Gltf { path, node } => load_gltf_file(path)?
+                .named_mesh(node)
+                .cloned()
+                .map(SourceMeshData::Mesh)
+                .ok_or_else(|| SceneError::MissingNode(node.clone())),
+            MeshSource::Vox { path } | MeshSource::VoxAnimation { path, .. } => Ok(SourceMeshData::Voxel(
+                mesh_data_from_vox(&dot_vox::load(path).map_err(SceneError::Vox)?),
+            )),
+        }
+    }
+
+    pub fn frames_mesh_data(&self) -> Result<Vec<SourceMeshData>, SceneError> {
+        match self {
+            MeshSource::VoxAnimation { path, .. } => Ok(mesh_data_from_vox_frames(
+                &dot_vox::load(path).map_err(SceneError::Vox)?,
+            )
+            .into_iter()
+            .map(SourceMeshData::Voxel)
+            .collect()),
+            _ => Ok(vec![self.mesh_data()?]),
+        }
+    }
+}
+
+// adds a mesh for every entity with a MeshSource but without a mesh, equal sources share one mesh
+// animated sources also get a Flipbook with a mesh for every frame
+pub fn load_meshes(entities: &mut Entities, renderer: &Renderer, meshes: &mut Assets<Mesh>) -> Result<(), SceneError> {
+    let to_load = entities
+        .query::<MeshSource>()
+        .filter(|(entity, _)| !entities.has::<AssetHandle<Mesh>>(entity))
+        .map(|(entity, mesh_source)| (entity, mesh_source.clone()))
+        .collect::<Vec<_>>();
+    let mut loaded: Vec<(MeshSource, Vec<AssetHandle<Mesh>>)> = Vec::new();
+    for (entity, mesh_source) in to_load {
+        let frames = match loaded.iter().find(|(source, _)| *source == mesh_source) {
+            Some((_, frames)) => frames.clone(),
+            None => {
+                let frames = mesh_source
+                    .frames_mesh_data()?
+                    .into_iter()
+                    .map(|mesh_data| meshes.add(mesh_data.into_mesh(renderer)))
+                    .collect::<Vec<_>>();
+                loaded.push((mesh_source.clone(), frames.clone()));
+                frames
+            }
+        };
+        if let Some(first) = frames.first() {
+            entities.insert(&entity, first.clone());
+        }
+        if let MeshSource::VoxAnimation { frame_rate, .. } = mesh_source {
+            entities.insert(&entity, Flipbook::new(frames, frame_rate));
+        }
+    }
+    Ok(())
+}
+
+// a mesh for every mesh of the gltf scene in the order GltfScene::spawn takes them
+// meshes with joints and weights are skinned, the Skin that spawn adds poses them
+pub fn load_gltf_meshes(scene: &GltfScene, renderer: &Renderer, meshes: &mut Assets<Mesh>) -> Vec<AssetHandle<Mesh>> {
+    scene
+        .meshes
+        .iter()
+        .zip(scene.skinned_meshes.iter())
+        .map(|(mesh_data, skinned_mesh_data)| match skinned_mesh_data {
+            Some(skinned_mesh_data) => meshes.add(Mesh::from_skinned_mesh_data(renderer, skinned_mesh_data.clone())),
+            None => meshes.add(Mesh::from_mesh_data(renderer, mesh_data.clone())),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        entity::{Entities, Parent},
+        physics::CollisionShape,
+        renderer::{Light, Opacity},
+        scene::{MeshSource, Scene, SceneCamera, SceneError},
+        transform::Transform,
+    };
+    use glam::Vec3;
+
+    #[test]
+    fn main_scene_round_trip() {
+        let scene: Scene = ron::de::from_bytes(std::fs::read("res/scenes/main.ron").unwrap().as_slice()).unwrap();
+        let mut entities = Entities::new();
+        let handles = scene.instantiate(&mut entities).unwrap();
+        assert_eq!(scene.entities.len(), handles.len());
+        let character = handles[scene.character.unwrap()];
+        assert_eq!(
+            Some(&MeshSource::IcoSphere { radius: 0.5 }),
+            entities.get::<MeshSource>(&character)
+        );
+        assert_eq!(1, entities.query::<Parent>().count());
+        assert_eq!(1, entities.query::<Opacity>().count());
+
+        entities.get_mut::<Transform>(&character).unwrap().translation = Vec3::new(1.0, 2.0, 3.0);
+        let saved = Scene::from_entities(&entities, Some(&character), scene.camera.clone());
+        let text = ron::ser::to_string(&saved).unwrap();
+        let reloaded: Scene = ron::de::from_str(&text).unwrap();
+        let mut reloaded_entities = Entities::new();
+        let reloaded_handles = reloaded.instantiate(&mut reloaded_entities).unwrap();
+        let reloaded_character = reloaded_handles[reloaded.character.unwrap()];
+        assert_eq!(
+            Vec3::new(1.0, 2.0, 3.0),
+            reloaded_entities
+                .get::<Transform>(&reloaded_character)
+                .unwrap()
+                .translation
+        );
+        assert_eq!(
+            entities.query::<Light>().count(),
+            reloaded_entities.query::<Light>().count()
+        );
+        assert_eq!(
+            entities.query::<CollisionShape>().count(),
+            reloaded_entities.query::<CollisionShape>().count()
+        );
+        assert_eq!(1, reloaded_entities.query::<Parent>().count());
+        assert_eq!(
+            Some(&Opacity(0.4)),
+            reloaded_entities.query::<Opacity>().next().map(|(_, opacity)| opacity)
+        );
+        assert!(matches!(reloaded.camera, Some(SceneCamera::Follow { .. })));
+    }
+
+    #[test]
+    fn parent_cycles_are_rejected() {
+        let scene: Scene = ron::de::from_str("(entities: [(parent: Some(1)), (parent: Some(0))])").unwrap();
+        let mut entities = Entities::new();
+        assert!(matches!(
+            scene.instantiate(&mut entities),
+            Err(SceneError::InvalidIndex(_))
+        ));
+        assert!(entities.is_empty());
+    }
+
+    #[test]
+    fn second_directional_light_is_rejected() {
+        let light = "(light: Some(Directional((direction: (0.0, -1.0, 0.0, 0.0), ambient: (0.1, 0.1, 0.1, 1.0), \
+                     diffuse: (0.5, 0.5, 0.5, 1.0), specular: (0.1, 0.1, 0.1, 1.0)))))";
+        let scene: Scene = ron::de::from_str(&format!("(entities: [{}, {}])", light, light)).unwrap();
+        let mut entities = Entities::new();
+        assert!(matches!(
+            scene.instantiate(&mut entities),
+            Err(SceneError::TooManyDirectionalLights(2))
+        ));
+        assert!(entities.is_empty());
+    }
+}
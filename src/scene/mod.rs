@@ -0,0 +1,5 @@
+mod description;
+mod loader;
+
+pub use description::{MeshSource, Scene, SceneCamera, SceneEntity};
+pub use loader::{load_gltf_meshes, load_meshes, load_scene, save_scene, SceneError, SourceMeshData};
@@ -0,0 +1,5 @@
+use crate::transform::Transform;
+
+// world space transform, written by propagate_transforms from the local Transform and its parents
+#[derive(Clone, Default)]
+pub struct GlobalTransform(pub Transform);
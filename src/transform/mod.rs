@@ -1,2 +1,4 @@
+mod global_transform;
 mod transform;
+pub use global_transform::GlobalTransform;
 pub use transform::Transform;
@@ -1,6 +1,8 @@
 use glam::{Mat4, Quat, Vec3};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Transform {
     pub translation: Vec3,
     pub rotation: Quat,
@@ -68,6 +70,17 @@ impl Transform {
         value
     }
 
+    // exact for uniform scales, which is all that mul_transform keeps consistent
+    pub fn inverse(&self) -> Transform {
+        let rotation = self.rotation.conjugate();
+        let scale = self.scale.recip();
+        Transform {
+            translation: -(rotation * (self.translation * scale)),
+            rotation,
+            scale,
+        }
+    }
+
     pub fn forward(&self) -> Vec3 {
         self.rotation * Vec3::unit_z()
     }
@@ -1,10 +1,11 @@
-use crate::{physics::PhysicsHandle, registry::Handle, renderer::Mesh, transform::Transform};
+use crate::{physics::PhysicsHandle, renderer::ChunkAllocation};
 
 #[derive(Clone)]
 pub struct ChunkData {
     pub physics_handle: PhysicsHandle,
-    pub mesh_handle: Handle<Mesh>,
-    pub transform: Transform,
+    // the mesh in the pooled chunk buffers, freed when the chunk is deleted
+    // none while the chunk buffers are full, the world retries it on later updates
+    pub allocation: Option<ChunkAllocation>,
 }
 
 #[derive(Clone)]
@@ -1,5 +1,5 @@
 use crate::{
-    mesh::MeshData,
+    mesh::VoxelMeshData,
     registry::Handle,
     transform::Transform,
     world::{
@@ -31,7 +31,7 @@ impl Chunker {
         }
     }
 
-    pub fn generate_chunk(&mut self, chunk: [i32; 2]) -> Vec<(MeshData, Transform)> {
+    pub fn generate_chunk(&mut self, chunk: [i32; 2]) -> Vec<(VoxelMeshData, Transform)> {
         let mut ground_vox = VoxHeightMap::new(CHUNK_SIZE_IN_VOXELS, CHUNK_SIZE_IN_VOXELS);
         for z in 0..CHUNK_SIZE_IN_VOXELS {
             for x in 0..CHUNK_SIZE_IN_VOXELS {
@@ -1,6 +1,6 @@
 use crate::{
-    mesh::{MeshData, Vertex},
-    world::{constants::VOXEL_SIZE_IN_METERS, vox::Vox, vox3d::Vox3d, voxheightmap::VoxHeightMap},
+    mesh::{VoxelMeshData, VoxelVertex, VOXEL_AO_MAX},
+    world::{vox::Vox, vox3d::Vox3d, voxheightmap::VoxHeightMap},
 };
 
 struct Descriptor {
@@ -25,8 +25,11 @@ impl Descriptor {
     }
 }
 
+// color id and the ambient occlusion of the four corners of a face, faces only merge when both are equal
+type Face = (u8, [u8; 4]);
+
 struct Mask {
-    data: Vec<Option<u8>>,
+    data: Vec<Option<Face>>,
     size_x: usize,
     size_y: usize,
 }
@@ -40,20 +43,56 @@ impl Mask {
         }
     }
 
-    pub fn set(&mut self, x: usize, y: usize, color_id: Option<u8>) {
+    pub fn set(&mut self, x: usize, y: usize, face: Option<Face>) {
         assert!(x < self.size_x);
         assert!(y < self.size_y);
-        self.data[y * self.size_x + x] = color_id;
+        self.data[y * self.size_x + x] = face;
     }
 
-    pub fn get(&mut self, x: usize, y: usize) -> Option<u8> {
+    pub fn get(&mut self, x: usize, y: usize) -> Option<Face> {
         self.data[y * self.size_x + x]
     }
 }
 
-pub fn greedy_mesh(vox: &Vox) -> MeshData {
+fn is_solid(vox: &dyn Vox, position: [i32; 3]) -> bool {
+    let size = vox.get_size();
+    (0..3).all(|i| position[i] >= 0 && (position[i] as usize) < size[i])
+        && vox
+            .get(position[0] as usize, position[1] as usize, position[2] as usize)
+            .is_some()
+}
+
+// ambient occlusion of the face corners, in the order (-v, -w), (+v, -w), (+v, +w), (-v, +w)
+fn face_ao(vox: &dyn Vox, cursor: [usize; 3], normal: [i32; 3], v: usize, w: usize) -> [u8; 4] {
+    let outside = [
+        cursor[0] as i32 - normal[0],
+        cursor[1] as i32 - normal[1],
+        cursor[2] as i32 - normal[2],
+    ];
+    let offset = |dv: i32, dw: i32| {
+        let mut position = outside;
+        position[v] += dv;
+        position[w] += dw;
+        position
+    };
+    let corner_ao = |dv: i32, dw: i32| {
+        let side_v = is_solid(vox, offset(dv, 0));
+        let side_w = is_solid(vox, offset(0, dw));
+        let corner = is_solid(vox, offset(dv, dw));
+        if side_v && side_w {
+            0
+        } else {
+            VOXEL_AO_MAX - side_v as u8 - side_w as u8 - corner as u8
+        }
+    };
+    [corner_ao(-1, -1), corner_ao(1, -1), corner_ao(1, 1), corner_ao(-1, 1)]
+}
+
+// positions of the mesh are in voxels, faces of transparent colors come after the opaque faces in the indices
+pub fn greedy_mesh(vox: &dyn Vox) -> VoxelMeshData {
     let mut vertices = Vec::new();
     let mut indices = Vec::new();
+    let mut transparent_indices = Vec::new();
 
     let descriptors = [
         Descriptor::new(0, 1, 2, 1, [1, 0, 0], [0, 0, 0]),
@@ -71,7 +110,8 @@ pub fn greedy_mesh(vox: &Vox) -> MeshData {
         let v = d.v;
         let w = d.w;
         let normal = d.normal;
-        let normal_outside = [-(normal[0] as f32), -(normal[1] as f32), -(normal[2] as f32)];
+        // index of the outside normal -normal in VOXEL_NORMALS
+        let normal_index = (2 * u + if d.step == 1 { 1 } else { 0 }) as u8;
 
         for slice in 0..vox_size[u] {
             let slice = if d.step == 1 { slice } else { vox_size[u] - (slice + 1) };
@@ -93,20 +133,20 @@ pub fn greedy_mesh(vox: &Vox) -> MeshData {
                         None
                     };
                     let voxel = vox.get(cursor[0], cursor[1], cursor[2]);
-                    let color_id = if voxel_back != None && voxel != None && voxel_back == voxel {
+                    let face = if voxel_back != None && voxel != None && voxel_back == voxel {
                         None
                     } else {
-                        voxel
+                        voxel.map(|color_id| (color_id, face_ao(vox, cursor, normal, v, w)))
                     };
-                    mask.set(cursor[v], cursor[w], color_id);
+                    mask.set(cursor[v], cursor[w], face);
                 }
             }
             for y in 0..vox_size[w] {
                 for x in 0..vox_size[v] {
-                    let color_id = mask.get(x, y);
-                    if let Some(m) = color_id {
+                    let face = mask.get(x, y);
+                    if let Some((m, ao)) = face {
                         let mut width = 1;
-                        while x + width < vox_size[v] && mask.get(x + width, y) == color_id {
+                        while x + width < vox_size[v] && mask.get(x + width, y) == face {
                             width += 1;
                         }
                         let mut height = 1;
@@ -114,7 +154,7 @@ pub fn greedy_mesh(vox: &Vox) -> MeshData {
                         while y + height < vox_size[w] && !done {
                             let mut k = 0;
                             while k < width && !done {
-                                if mask.get(x + k, y + height) == color_id {
+                                if mask.get(x + k, y + height) == face {
                                     k += 1;
                                 } else {
                                     done = true;
@@ -124,40 +164,44 @@ pub fn greedy_mesh(vox: &Vox) -> MeshData {
                                 height += 1;
                             }
                         }
-                        let mut base = [0.0, 0.0, 0.0];
-                        base[u] = slice as f32 * VOXEL_SIZE_IN_METERS + d.q[0] as f32 * VOXEL_SIZE_IN_METERS;
-                        base[v] = x as f32 * VOXEL_SIZE_IN_METERS + d.q[1] as f32 * VOXEL_SIZE_IN_METERS;
-                        base[w] = y as f32 * VOXEL_SIZE_IN_METERS + d.q[2] as f32 * VOXEL_SIZE_IN_METERS;
+                        let mut base = [0, 0, 0];
+                        base[u] = (slice as i32 + d.q[0]) as u16;
+                        base[v] = (x as i32 + d.q[1]) as u16;
+                        base[w] = (y as i32 + d.q[2]) as u16;
 
-                        let mut dv = [0.0, 0.0, 0.0];
-                        dv[v] = width as f32 * VOXEL_SIZE_IN_METERS;
-                        let mut dw = [0.0, 0.0, 0.0];
-                        dw[w] = height as f32 * VOXEL_SIZE_IN_METERS;
+                        let mut dv = [0, 0, 0];
+                        dv[v] = width as u16;
+                        let mut dw = [0, 0, 0];
+                        dw[w] = height as u16;
 
                         let color = vox.get_color(m);
+                        let emission = vox.get_emission(m);
+                        let alpha = vox.get_alpha(m);
+                        let vertex = |position: [u16; 3], ao: u8| {
+                            VoxelVertex::new(position, normal_index, ao, color, m)
+                                .with_emission(emission)
+                                .with_alpha(alpha)
+                        };
                         let count = vertices.len() as u32;
                         vertices.extend_from_slice(&[
-                            Vertex::new([base[0], base[1], base[2]], normal_outside, color),
-                            Vertex::new(
+                            vertex(base, ao[0]),
+                            vertex(
                                 [
                                     base[0] + dv[0] + dw[0],
                                     base[1] + dv[1] + dw[1],
                                     base[2] + dv[2] + dw[2],
                                 ],
-                                normal_outside,
-                                color,
-                            ),
-                            Vertex::new(
-                                [base[0] + dv[0], base[1] + dv[1], base[2] + dv[2]],
-                                normal_outside,
-                                color,
-                            ),
-                            Vertex::new(
-                                [base[0] + dw[0], base[1] + dw[1], base[2] + dw[2]],
-                                normal_outside,
-                                color,
+                                ao[2],
                             ),
+                            vertex([base[0] + dv[0], base[1] + dv[1], base[2] + dv[2]], ao[1]),
+                            vertex([base[0] + dw[0], base[1] + dw[1], base[2] + dw[2]], ao[3]),
                         ]);
+                        // alphas that round to opaque stay with the opaque faces, like the shaders see them
+                        let indices = if vertices[count as usize].alpha() < 1.0 {
+                            &mut transparent_indices
+                        } else {
+                            &mut indices
+                        };
                         if d.step == 1 {
                             indices.extend_from_slice(&[count, count + 1, count + 2, count, count + 3, count + 1]);
                         } else {
@@ -173,5 +217,128 @@ pub fn greedy_mesh(vox: &Vox) -> MeshData {
             }
         }
     }
-    MeshData { vertices, indices }
+    let nr_of_opaque_indices = indices.len();
+    indices.extend_from_slice(&transparent_indices);
+    VoxelMeshData {
+        vertices,
+        indices,
+        nr_of_opaque_indices,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        mesh::{VoxelVertex, VOXEL_AO_MAX, VOXEL_EMISSION_MAX},
+        world::{
+            constants::{CHUNK_SIZE_IN_VOXELS, VOXEL_SIZE_IN_METERS},
+            greedy_meshing::greedy_mesh,
+            vox3d::Vox3d,
+            voxheightmap::VoxHeightMap,
+        },
+    };
+
+    #[test]
+    fn single_voxel() {
+        let mut vox = Vox3d::new(1, 1, 1);
+        vox.set(0, 0, 0, 7, [1.0, 0.0, 0.0]);
+        let mesh_data = greedy_mesh(&vox);
+        assert_eq!(24, mesh_data.vertices.len());
+        assert_eq!(36, mesh_data.indices.len());
+        for vertex in &mesh_data.vertices {
+            assert_eq!(VOXEL_AO_MAX, vertex.ao());
+            assert_eq!(7, vertex.palette_index());
+            assert_eq!([255, 0, 0], [vertex.color[0], vertex.color[1], vertex.color[2]]);
+            // every vertex lies on the side of the voxel its normal points to
+            let normal = vertex.normal();
+            for (axis, n) in normal.iter().enumerate() {
+                if *n != 0.0 {
+                    assert_eq!(if *n > 0.0 { 1 } else { 0 }, vertex.position[axis]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn emission_of_colors_is_stored_in_vertices() {
+        let mut vox = Vox3d::new(2, 1, 1);
+        vox.set(0, 0, 0, 1, [1.0, 1.0, 1.0]);
+        vox.set(1, 0, 0, 2, [1.0, 1.0, 0.0]);
+        vox.set_emission(2, 2.0);
+        let mesh_data = greedy_mesh(&vox);
+        for vertex in &mesh_data.vertices {
+            let expected = if vertex.palette_index() == 2 { 2.0 } else { 0.0 };
+            assert!((vertex.emission() - expected).abs() < VOXEL_EMISSION_MAX / 255.0);
+        }
+    }
+
+    #[test]
+    fn faces_of_transparent_colors_come_last() {
+        let mut vox = Vox3d::new(2, 1, 1);
+        vox.set(0, 0, 0, 1, [1.0, 1.0, 1.0]);
+        vox.set(1, 0, 0, 2, [0.5, 0.5, 1.0]);
+        vox.set_alpha(2, 0.5);
+        let mesh_data = greedy_mesh(&vox);
+        // the faces between the voxels are kept, the opaque face is seen through the transparent voxel
+        assert_eq!(36, mesh_data.nr_of_opaque_indices);
+        assert_eq!(72, mesh_data.indices.len());
+        let alpha = |index: &u32| mesh_data.vertices[*index as usize].alpha();
+        assert!(mesh_data.indices[..36].iter().all(|index| alpha(index) == 1.0));
+        assert!(mesh_data.indices[36..].iter().all(|index| alpha(index) == 0.5));
+    }
+
+    #[test]
+    fn nearly_opaque_colors_are_opaque() {
+        let mut vox = Vox3d::new(1, 1, 1);
+        vox.set(0, 0, 0, 1, [1.0, 1.0, 1.0]);
+        vox.set_alpha(1, 0.99);
+        let mesh_data = greedy_mesh(&vox);
+        assert_eq!(mesh_data.indices.len(), mesh_data.nr_of_opaque_indices);
+        assert!(mesh_data.vertices.iter().all(|vertex| vertex.alpha() == 1.0));
+    }
+
+    #[test]
+    fn corners_next_to_voxels_are_occluded() {
+        let mut vox = Vox3d::new(3, 2, 3);
+        for z in 0..3 {
+            for x in 0..3 {
+                vox.set(x, 0, z, 1, [1.0, 1.0, 1.0]);
+            }
+        }
+        vox.set(0, 1, 0, 1, [1.0, 1.0, 1.0]);
+        let mesh_data = greedy_mesh(&vox);
+        let top = |position: [u16; 3]| -> Vec<VoxelVertex> {
+            mesh_data
+                .vertices
+                .iter()
+                .filter(|v| v.normal() == [0.0, 1.0, 0.0] && v.position[..3] == position)
+                .copied()
+                .collect()
+        };
+        assert!(top([1, 1, 1]).iter().any(|v| v.ao() < VOXEL_AO_MAX));
+        assert!(top([3, 1, 3]).iter().all(|v| v.ao() == VOXEL_AO_MAX));
+        assert!(top([0, 2, 0]).iter().all(|v| v.ao() == VOXEL_AO_MAX));
+    }
+
+    #[test]
+    fn voxel_mesh_is_smaller_than_mesh_data() {
+        let mut height_map = VoxHeightMap::new(CHUNK_SIZE_IN_VOXELS, CHUNK_SIZE_IN_VOXELS);
+        for z in 0..CHUNK_SIZE_IN_VOXELS {
+            for x in 0..CHUNK_SIZE_IN_VOXELS {
+                height_map.set(x, z, ((x / 4 + z / 3) % 5) as f32 * VOXEL_SIZE_IN_METERS);
+            }
+        }
+        let voxel_mesh_data = greedy_mesh(&height_map);
+        let mesh_data = voxel_mesh_data.to_mesh_data(VOXEL_SIZE_IN_METERS);
+        assert_eq!(12, std::mem::size_of::<VoxelVertex>());
+        assert!(voxel_mesh_data.has_short_indices());
+        assert!(voxel_mesh_data.size_in_bytes() * 2 < mesh_data.size_in_bytes());
+        for (voxel_vertex, vertex) in voxel_mesh_data.vertices.iter().zip(mesh_data.vertices.iter()) {
+            assert_eq!(
+                voxel_vertex.position[1] as f32 * VOXEL_SIZE_IN_METERS,
+                vertex.position[1]
+            );
+            assert_eq!(voxel_vertex.normal(), vertex.normal);
+        }
+    }
 }
@@ -9,7 +9,9 @@ mod voxheightmap;
 mod world;
 
 pub use chunker::Chunker;
+pub use constants::VOXEL_SIZE_IN_METERS;
 use constants::*;
 use vox::Vox;
 use vox3d::{load_vox, Vox3d};
+pub use vox3d::{mesh_data_from_vox, mesh_data_from_vox_frames};
 pub use world::World;
@@ -4,6 +4,14 @@ pub trait Vox {
     fn get_size(&self) -> [usize; 3];
     fn get(&self, x: usize, y: usize, z: usize) -> Option<u8>;
     fn get_color(&self, color_id: u8) -> [f32; 3];
+    // light a color gives off as a multiple of the color
+    fn get_emission(&self, _color_id: u8) -> f32 {
+        0.0
+    }
+    // colors with an alpha below 1.0 are drawn by the transparent pass
+    fn get_alpha(&self, _color_id: u8) -> f32 {
+        1.0
+    }
     fn get_y_min_offset(&self) -> f32;
     fn get_y_max_offset(&self) -> f32;
 }
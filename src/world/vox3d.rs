@@ -1,13 +1,16 @@
 use crate::{
+    mesh::VoxelMeshData,
     registry::{Handle, Registry},
     transform::Transform,
-    world::{constants::VOXEL_SIZE_IN_METERS, vox::Vox},
+    world::{constants::VOXEL_SIZE_IN_METERS, greedy_meshing::greedy_mesh, vox::Vox},
 };
 use std::collections::HashMap;
 
 pub struct Vox3d {
     data: Vec<Option<u8>>,
     palette: HashMap<u8, [f32; 3]>,
+    emission: HashMap<u8, f32>,
+    alpha: HashMap<u8, f32>,
     pub x_size: usize,
     pub y_size: usize,
     pub z_size: usize,
@@ -19,6 +22,8 @@ impl Vox3d {
         Self {
             data: vec![None; z_size * y_size * x_size],
             palette: HashMap::default(),
+            emission: HashMap::default(),
+            alpha: HashMap::default(),
             x_size,
             y_size,
             z_size,
@@ -31,6 +36,14 @@ impl Vox3d {
         self.data[z * self.y_size * self.x_size + y * self.x_size + x] = Some(color_id);
         self.palette.insert(color_id, color);
     }
+
+    pub fn set_emission(&mut self, color_id: u8, emission: f32) {
+        self.emission.insert(color_id, emission);
+    }
+
+    pub fn set_alpha(&mut self, color_id: u8, alpha: f32) {
+        self.alpha.insert(color_id, alpha);
+    }
 }
 
 impl Vox for Vox3d {
@@ -46,6 +59,14 @@ impl Vox for Vox3d {
         self.palette[&color_id]
     }
 
+    fn get_emission(&self, color_id: u8) -> f32 {
+        self.emission.get(&color_id).copied().unwrap_or(0.0)
+    }
+
+    fn get_alpha(&self, color_id: u8) -> f32 {
+        self.alpha.get(&color_id).copied().unwrap_or(1.0)
+    }
+
     fn get_y_min_offset(&self) -> f32 {
         0.0
     }
@@ -56,16 +77,139 @@ impl Vox for Vox3d {
 }
 
 pub fn load_vox(data: &dot_vox::DotVoxData) -> Vox3d {
-    let model = &data.models[0];
+    vox_from_model(data, &data.models[0])
+}
+
+// every model in the file is a frame, in the order they are stored
+pub fn load_vox_frames(data: &dot_vox::DotVoxData) -> Vec<Vox3d> {
+    data.models.iter().map(|model| vox_from_model(data, model)).collect()
+}
+
+fn vox_from_model(data: &dot_vox::DotVoxData, model: &dot_vox::Model) -> Vox3d {
     let mut vox_model = Vox3d::new(model.size.x as usize, model.size.z as usize, model.size.y as usize);
     for v in &model.voxels {
         let color = palette_to_color(data.palette[v.i as usize]);
         vox_model.set(v.x as usize, v.z as usize, v.y as usize, v.i, color);
     }
+    // material ids count from 1 like the color indices in the file
+    for material in data
+        .materials
+        .iter()
+        .filter(|material| (1..=256).contains(&material.id))
+    {
+        let color_id = (material.id - 1) as u8;
+        if let Some(emission) = material_emission(material) {
+            vox_model.set_emission(color_id, emission);
+        }
+        if let Some(alpha) = material_alpha(material) {
+            vox_model.set_alpha(color_id, alpha);
+        }
+    }
     vox_model
 }
 
+// positions are in voxels, the emission and translucency of the materials stay in the vertices
+pub fn mesh_data_from_vox(data: &dot_vox::DotVoxData) -> VoxelMeshData {
+    greedy_mesh(&load_vox(data))
+}
+
+pub fn mesh_data_from_vox_frames(data: &dot_vox::DotVoxData) -> Vec<VoxelMeshData> {
+    load_vox_frames(data).iter().map(|vox| greedy_mesh(vox)).collect()
+}
+
+// emissive materials have an _emit strength from 0.0 to 1.0 and a _flux from 0 to 4 that multiplies it
+fn material_emission(material: &dot_vox::Material) -> Option<f32> {
+    if material.properties.get("_type").map(String::as_str) != Some("_emit") {
+        return None;
+    }
+    let property = |name: &str| {
+        material
+            .properties
+            .get(name)
+            .and_then(|value| value.parse::<f32>().ok())
+    };
+    Some(property("_emit").unwrap_or(0.0) * (1.0 + property("_flux").unwrap_or(0.0)))
+}
+
+// glass materials have a _trans from 0.0 to 1.0
+fn material_alpha(material: &dot_vox::Material) -> Option<f32> {
+    if material.properties.get("_type").map(String::as_str) != Some("_glass") {
+        return None;
+    }
+    let transparency = material
+        .properties
+        .get("_trans")
+        .and_then(|value| value.parse::<f32>().ok())
+        .unwrap_or(0.5);
+    Some(1.0 - transparency)
+}
+
 fn palette_to_color(from: u32) -> [f32; 3] {
     let (_a, b, g, r) = (from >> 24 & 0xFF, from >> 16 & 0xFF, from >> 8 & 0xFF, from & 0xFF);
     [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0]
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::world::mesh_data_from_vox;
+    use dot_vox::{DotVoxData, Material, Model, Size, Voxel};
+
+    #[test]
+    fn emissive_materials_reach_the_vertices() {
+        let voxel = |x, i| Voxel { x, y: 0, z: 0, i };
+        let properties = [("_type", "_emit"), ("_emit", "0.5")];
+        let data = DotVoxData {
+            version: 150,
+            models: vec![Model {
+                size: Size { x: 2, y: 1, z: 1 },
+                voxels: vec![voxel(0, 0), voxel(1, 1)],
+            }],
+            palette: vec![0xffffffff; 256],
+            // material ids count from 1, this one belongs to the first color
+            materials: vec![Material {
+                id: 1,
+                properties: properties
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            }],
+        };
+        let mesh_data = mesh_data_from_vox(&data);
+        let emission = |palette_index: u8| {
+            mesh_data
+                .vertices
+                .iter()
+                .filter(|vertex| vertex.palette_index() == palette_index)
+                .map(|vertex| vertex.emission())
+                .collect::<Vec<_>>()
+        };
+        assert!(!emission(0).is_empty());
+        assert!(emission(0).iter().all(|emission| (emission - 0.5).abs() < 0.01));
+        assert!(!emission(1).is_empty());
+        assert!(emission(1).iter().all(|emission| *emission == 0.0));
+    }
+
+    #[test]
+    fn glass_materials_are_transparent() {
+        let data = DotVoxData {
+            version: 150,
+            models: vec![Model {
+                size: Size { x: 2, y: 1, z: 1 },
+                voxels: vec![Voxel { x: 0, y: 0, z: 0, i: 0 }, Voxel { x: 1, y: 0, z: 0, i: 1 }],
+            }],
+            palette: vec![0xffffffff; 256],
+            materials: vec![Material {
+                id: 2,
+                properties: [("_type", "_glass"), ("_trans", "0.5")]
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            }],
+        };
+        let mesh_data = mesh_data_from_vox(&data);
+        let alpha = |index: &u32| mesh_data.vertices[*index as usize].alpha();
+        let (opaque, transparent) = mesh_data.indices.split_at(mesh_data.nr_of_opaque_indices);
+        assert!(!opaque.is_empty() && opaque.iter().all(|index| alpha(index) == 1.0));
+        assert!(!transparent.is_empty() && transparent.iter().all(|index| alpha(index) == 0.5));
+    }
+}
@@ -1,15 +1,16 @@
 use crate::{
-    physics::Physics,
-    registry::{Handle, Registry},
-    renderer::{Mesh, Renderer},
+    mesh::{Aabb, VoxelMeshData},
+    physics::{Physics, TriMesh},
+    renderer::{BindGroup, ChunkAllocation, DebugDraw, Renderer, RendererError},
     transform::Transform,
     world::{
         chunk::{Chunk, ChunkData},
-        constants::{CHUNK_SIZE_IN_METERS, CHUNK_SIZE_IN_VOXELS},
+        constants::{CHUNK_SIZE_IN_METERS, CHUNK_SIZE_IN_VOXELS, VOXEL_SIZE_IN_METERS},
         sliding_vec3d::Vec2dSliding,
         Chunker,
     },
 };
+use glam::{Mat4, Vec3};
 
 pub struct ChunkArea {
     center: [i32; 2],
@@ -46,9 +47,19 @@ impl Iterator for ChunkArea {
     }
 }
 
+// a chunk mesh that did not fit in the chunk buffers, kept until it fits or its chunk is deleted
+struct UnallocatedChunk {
+    location: [i32; 2],
+    // of the chunk data in the chunk
+    index: usize,
+    mesh_data: VoxelMeshData,
+    m: Mat4,
+}
+
 pub struct World {
     chunker: Chunker,
     chunks: Vec2dSliding<Option<Chunk>>,
+    unallocated: Vec<UnallocatedChunk>,
     old_center: Option<[f32; 2]>,
     walking_window: [f32; 2],
     radius: usize,
@@ -59,6 +70,7 @@ impl World {
         Self {
             chunker: Chunker::new(),
             chunks: Vec2dSliding::new([100, 100]),
+            unallocated: Vec::new(),
             old_center: None,
             walking_window: [6.0, 6.0],
             radius: 10,
@@ -107,28 +119,50 @@ impl World {
         !Self::within_distance_2d(first, second, distance)
     }
 
+    // the chunk gets its colliders even when its meshes do not fit in the chunk buffers, those are retried later
     fn generate_chunk(
         &mut self,
         chunk_pos: [i32; 2],
         physics: &mut Physics,
-        meshes: &mut Registry<Mesh>,
+        bind_group: &mut BindGroup,
         renderer: &mut Renderer,
-    ) {
+    ) -> Result<(), RendererError> {
+        let mut result = Ok(());
         let mut chunk_data = Vec::new();
         for (mesh_data, transform) in self.chunker.generate_chunk(chunk_pos).drain(..) {
+            // chunk meshes are in voxels, the transform scales them to meters
+            let m = Transform {
+                scale: Vec3::splat(VOXEL_SIZE_IN_METERS),
+                ..transform
+            }
+            .to_matrix();
             let physics_handle = physics.register_trimesh(
-                &mesh_data,
+                TriMesh {
+                    vertices: mesh_data.positions(VOXEL_SIZE_IN_METERS),
+                    indices: mesh_data.indices.chunks(3).map(|i| [i[0], i[1], i[2]]).collect(),
+                },
                 [
                     transform.translation.x,
                     transform.translation.y,
                     transform.translation.z,
                 ],
             );
-            let mesh_handle = meshes.add(Mesh::from_mesh_data(renderer, mesh_data));
+            let allocation = match bind_group.allocate_chunk(renderer, &mesh_data, m) {
+                Ok(allocation) => Some(allocation),
+                Err(e) => {
+                    self.unallocated.push(UnallocatedChunk {
+                        location: chunk_pos,
+                        index: chunk_data.len(),
+                        mesh_data,
+                        m,
+                    });
+                    result = Err(e);
+                    None
+                }
+            };
             chunk_data.push(ChunkData {
                 physics_handle,
-                mesh_handle,
-                transform,
+                allocation,
             });
         }
         self.chunks.set(
@@ -139,27 +173,51 @@ impl World {
                 requested: true,
             }),
         );
+        result
     }
 
-    fn delete_chunk(&mut self, chunk_pos: [i32; 2], physics: &mut Physics, meshes: &mut Registry<Mesh>) {
+    // in the order they failed, the ones that still do not fit wait for the next update
+    fn allocate_unallocated(&mut self, bind_group: &mut BindGroup, renderer: &Renderer) {
+        let mut unallocated = std::mem::take(&mut self.unallocated);
+        unallocated.retain(|unallocated| {
+            let allocation = match bind_group.allocate_chunk(renderer, &unallocated.mesh_data, unallocated.m) {
+                Ok(allocation) => allocation,
+                Err(_) => return true,
+            };
+            match self.chunks.get(unallocated.location) {
+                Some(mut chunk) if chunk.location == unallocated.location => {
+                    chunk.chunk_data[unallocated.index].allocation = Some(allocation);
+                    self.chunks.set(unallocated.location, Some(chunk));
+                }
+                _ => bind_group.free_chunk(&allocation),
+            }
+            false
+        });
+        self.unallocated = unallocated;
+    }
+
+    fn delete_chunk(&mut self, chunk_pos: [i32; 2], physics: &mut Physics, bind_group: &mut BindGroup) {
         if let Some(chunk) = self.chunks.get(chunk_pos) {
             if chunk.location == chunk_pos {
                 self.chunks.set(chunk_pos, None);
+                self.unallocated.retain(|unallocated| unallocated.location != chunk_pos);
                 for chunk_data in chunk.chunk_data {
-                    meshes.remove(chunk_data.mesh_handle);
                     physics.remove_physics_handle(&chunk_data.physics_handle);
+                    if let Some(allocation) = &chunk_data.allocation {
+                        bind_group.free_chunk(allocation);
+                    }
                 }
             }
         }
     }
 
-    fn delete_obsolete(&mut self, meshes: &mut Registry<Mesh>, physics: &mut Physics, center: [f32; 2]) {
+    fn delete_obsolete(&mut self, physics: &mut Physics, bind_group: &mut BindGroup, center: [f32; 2]) {
         if let Some(old_center) = self.old_center {
             let previous_center_index = Self::position_to_chunk_index_2d(old_center);
             for chunk_pos in ChunkArea::new(previous_center_index, self.radius as i32) {
                 let center_index = Self::position_to_chunk_index_2d(center);
                 if Self::outside_distance_2d(center_index, chunk_pos, self.radius) {
-                    self.delete_chunk(chunk_pos, physics, meshes);
+                    self.delete_chunk(chunk_pos, physics, bind_group);
                 }
             }
         }
@@ -167,11 +225,12 @@ impl World {
 
     fn generate_new(
         &mut self,
-        meshes: &mut Registry<Mesh>,
+        bind_group: &mut BindGroup,
         physics: &mut Physics,
         renderer: &mut Renderer,
         new_center: [f32; 2],
-    ) {
+    ) -> Result<(), RendererError> {
+        let mut result = Ok(());
         let center_index = Self::position_to_chunk_index_2d(new_center);
         for chunk_pos in ChunkArea::new(center_index, self.radius as i32) {
             if let Some(old_center) = self.old_center {
@@ -180,42 +239,94 @@ impl World {
                     && !Self::within_distance_2d(old_center_index, chunk_pos, self.radius)
                 {
                     println!("{:?}", chunk_pos);
-                    self.generate_chunk(chunk_pos, physics, meshes, renderer);
+                    if let Err(e) = self.generate_chunk(chunk_pos, physics, bind_group, renderer) {
+                        result = Err(e);
+                    }
                 }
             } else {
                 println!("{:?}", chunk_pos);
-                self.generate_chunk(chunk_pos, physics, meshes, renderer);
+                if let Err(e) = self.generate_chunk(chunk_pos, physics, bind_group, renderer) {
+                    result = Err(e);
+                }
             }
         }
+        result
     }
 
+    // fails when new chunks do not fit in the chunk buffers, they are drawn once deleted chunks make room for them
     pub fn update(
         &mut self,
         position: [f32; 3],
         renderer: &mut Renderer,
         physics: &mut Physics,
-        meshes: &mut Registry<Mesh>,
-    ) {
+        bind_group: &mut BindGroup,
+    ) -> Result<(), RendererError> {
         let center = if let Some(old_center) = self.old_center {
             Self::move_to_posidtion_2d([position[0], position[2]], old_center, self.walking_window)
         } else {
             [position[0], position[2]]
         };
-        self.delete_obsolete(meshes, physics, center);
-        self.generate_new(meshes, physics, renderer, center);
+        self.delete_obsolete(physics, bind_group, center);
+        self.allocate_unallocated(bind_group, renderer);
+        let result = self.generate_new(bind_group, physics, renderer, center);
         self.old_center = Some(center);
+        result
+    }
+
+    // chunks are drawn at least this far from the position in every direction
+    pub fn view_distance(&self) -> f32 {
+        self.radius as f32 * CHUNK_SIZE_IN_METERS
     }
 
-    pub fn get_within_view_mesh_transform(&self, position: [f32; 2]) -> Vec<(Handle<Mesh>, Transform)> {
-        let mut mesh_transforms = Vec::new();
+    pub fn get_within_view_chunks(&self, position: [f32; 2]) -> Vec<ChunkAllocation> {
+        let mut allocations = Vec::new();
         let position_index = Self::position_to_chunk_index_2d(position);
         for chunk_pos in ChunkArea::new(position_index, self.radius as i32) {
             if let Some(chunk) = self.chunks.get(chunk_pos) {
-                for chunk_data in chunk.chunk_data {
-                    mesh_transforms.push((chunk_data.mesh_handle, chunk_data.transform));
-                }
+                allocations.extend(
+                    chunk
+                        .chunk_data
+                        .into_iter()
+                        .filter_map(|chunk_data| chunk_data.allocation),
+                );
+            }
+        }
+        allocations
+    }
+
+    // the column of every loaded chunk from its lowest to its highest voxel, labeled with its location
+    pub fn draw_chunk_grid(&self, position: [f32; 2], debug: &mut DebugDraw, color: [f32; 4]) {
+        let position_index = Self::position_to_chunk_index_2d(position);
+        for chunk_pos in ChunkArea::new(position_index, self.radius as i32) {
+            let chunk = match self.chunks.get(chunk_pos) {
+                Some(chunk) if chunk.location == chunk_pos => chunk,
+                _ => continue,
+            };
+            // chunks without drawn meshes have no column
+            let (min_y, max_y) = chunk
+                .chunk_data
+                .iter()
+                .filter_map(|data| data.allocation.as_ref())
+                .fold((f32::MAX, f32::MIN), |(min_y, max_y), allocation| {
+                    (min_y.min(allocation.aabb.min.y), max_y.max(allocation.aabb.max.y))
+                });
+            if min_y > max_y {
+                continue;
             }
+            let min = Vec3::new(
+                chunk_pos[0] as f32 * CHUNK_SIZE_IN_METERS,
+                min_y,
+                chunk_pos[1] as f32 * CHUNK_SIZE_IN_METERS,
+            );
+            let max = Vec3::new(min.x + CHUNK_SIZE_IN_METERS, max_y, min.z + CHUNK_SIZE_IN_METERS);
+            debug.aabb(&Aabb::new(min, max), color);
+            let label = format!("{},{}", chunk_pos[0], chunk_pos[1]);
+            debug.text(
+                (min + max) * 0.5 + Vec3::new(0.0, (max.y - min.y) * 0.5 + 0.5, 0.0),
+                &label,
+                1.0,
+                color,
+            );
         }
-        mesh_transforms
     }
 }